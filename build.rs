@@ -40,17 +40,15 @@ fn main() {
                 
                 // 遍历所有 tags
                 if let Ok(refs) = repo.references() {
-                    for reference in refs.all().ok().into_iter().flatten() {
-                        if let Ok(r) = reference {
-                            if let Some(name) = r.name().as_bstr().to_str().ok() {
-                                if name.starts_with("refs/tags/") {
-                                    if let Ok(peeled) = r.id().object() {
-                                        if peeled.id == id {
-                                            tag_name = name.strip_prefix("refs/tags/")
-                                                .unwrap_or(name)
-                                                .to_string();
-                                            break;
-                                        }
+                    for r in refs.all().ok().into_iter().flatten().flatten() {
+                        if let Ok(name) = r.name().as_bstr().to_str() {
+                            if name.starts_with("refs/tags/") {
+                                if let Ok(peeled) = r.id().object() {
+                                    if peeled.id == id {
+                                        tag_name = name.strip_prefix("refs/tags/")
+                                            .unwrap_or(name)
+                                            .to_string();
+                                        break;
                                     }
                                 }
                             }
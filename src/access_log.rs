@@ -0,0 +1,117 @@
+//! 访问日志
+//!
+//! 每个请求在响应体发送完毕(或客户端断开)后输出一行访问日志,包括请求 ID、客户端 Key、
+//! 模型、解析到的区域和项目、上游状态码、响应 ID、耗时、首字节时间、token 用量和响应字节数。
+//! 日志的 target 为 `vertex_oai::access_log`,可以在 `logging.level` 中单独调整,
+//! 如 `info,vertex_oai::access_log=off` 关闭访问日志;`logging.format = "json"` 时每行一个 JSON 对象
+
+use crate::context::RequestContext;
+use crate::usage::cached_tokens;
+use axum::http::{HeaderMap, HeaderName, Method, StatusCode};
+use std::time::{Duration, Instant};
+
+/// 访问日志使用的 target
+pub const TARGET: &str = module_path!();
+
+/// 请求 ID 请求头和响应头
+pub static HEADER_X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// 客户端请求 ID 的最大长度
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 客户端在响应头返回前断开时记录的状态码(沿用 nginx 的 499 Client Closed Request)
+pub fn client_closed_request() -> StatusCode {
+    StatusCode::from_u16(499).unwrap()
+}
+
+/// 当前日志过滤规则是否输出访问日志
+pub fn enabled() -> bool {
+    tracing::enabled!(target: TARGET, tracing::Level::INFO)
+}
+
+/// 请求 ID: 优先使用客户端的 `x-request-id`(可见 ASCII 字符,不超过 128 个),否则生成新的 ID
+pub fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(&HEADER_X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_owned)
+        .unwrap_or_else(|| format!("{:032x}", fastrand::u128(..)))
+}
+
+/// 输出一个请求的访问日志,在响应体发送完毕(或客户端断开)后调用
+pub fn emit(
+    context: &RequestContext,
+    method: &Method,
+    route: &str,
+    status: StatusCode,
+    stream: bool,
+    start: Instant,
+) {
+    if !enabled() {
+        return;
+    }
+    let latency = start.elapsed();
+    let summary = context.summary();
+    let usage = summary.and_then(|s| s.usage.as_ref());
+    let ttfb = summary
+        .and_then(|s| s.first_byte)
+        .map_or(latency, |t| t.saturating_duration_since(start));
+    let resolved = context.route();
+    tracing::info!(
+        target: TARGET,
+        request_id = context.request_id(),
+        key = context.key(),
+        method = %method,
+        route,
+        model = context.model(),
+        region = resolved.as_ref().map(|r| r.location.as_str()),
+        project = resolved.as_ref().map(|r| r.project_id.as_str()),
+        status = status.as_u16(),
+        upstream_status = context.upstream_status().map(|s| s.as_u16()),
+        response_id = summary.and_then(|s| s.response_id.as_deref()),
+        prompt_tokens = usage.map(|u| u.prompt_tokens),
+        completion_tokens = usage.map(|u| u.completion_tokens),
+        cached_tokens = usage.map(cached_tokens),
+        total_tokens = usage.map(|u| u.total_tokens),
+        latency_ms = millis(latency),
+        ttfb_ms = millis(ttfb),
+        bytes = summary.map_or(0, |s| s.bytes),
+        stream,
+        completed = summary.is_some_and(|s| s.completed),
+        "Request completed"
+    );
+}
+
+/// 毫秒,保留到微秒
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_request_id_from_client_or_generated() {
+        let mut headers = HeaderMap::new();
+        headers.insert(&HEADER_X_REQUEST_ID, HeaderValue::from_static(" req-42 "));
+        assert_eq!(request_id(&headers), "req-42");
+
+        headers.insert(&HEADER_X_REQUEST_ID, HeaderValue::from_static("has space"));
+        let generated = request_id(&headers);
+        assert_eq!(generated.len(), 32);
+        assert!(generated.bytes().all(|b| b.is_ascii_hexdigit()));
+
+        let long = HeaderValue::from_str(&"a".repeat(MAX_REQUEST_ID_LEN + 1)).unwrap();
+        headers.insert(&HEADER_X_REQUEST_ID, long);
+        assert_eq!(request_id(&headers).len(), 32);
+        assert_ne!(request_id(&HeaderMap::new()), generated);
+    }
+}
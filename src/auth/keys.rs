@@ -0,0 +1,130 @@
+use crate::config::LimitsConfig;
+use crate::state::model_matches;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// 虚拟 API Key
+///
+/// 客户端使用 `Authorization: Bearer sk-...` 访问网关,
+/// 网关校验后再使用自身的 GCP 凭据调用 Vertex AI
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub key: String,
+    pub name: String,
+    /// 允许访问的模型,为空表示不限制,支持以 `*` 结尾的前缀匹配
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// 过期时间 (RFC 3339),为空表示永不过期
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// 每分钟请求数限制,为空时使用 `rate_limit.per_key.rpm`
+    #[serde(default)]
+    pub rpm: Option<u64>,
+    /// 每分钟 token 数限制,为空时使用 `rate_limit.per_key.tpm`
+    #[serde(default)]
+    pub tpm: Option<u64>,
+    /// 最大并发请求数,为空时使用 `rate_limit.per_key.max_concurrent`
+    #[serde(default)]
+    pub max_concurrent: Option<u64>,
+    /// 每月预算(美元),按 Key 名称统计当月(UTC)费用,超出后拒绝请求
+    #[serde(default)]
+    pub monthly_budget: Option<f64>,
+    /// 是否允许访问 `/admin/*` 管理接口
+    #[serde(default)]
+    pub admin: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl ApiKey {
+    /// 判断该 Key 是否允许访问指定模型
+    pub fn allows_model(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|p| model_matches(p, model))
+    }
+
+    /// 判断该 Key 是否已过期
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|t| t <= Utc::now())
+    }
+
+    /// 该 Key 的限流阈值,未单独设置的项使用默认值
+    pub fn limits(&self, defaults: &LimitsConfig) -> LimitsConfig {
+        LimitsConfig {
+            rpm: self.rpm.unwrap_or(defaults.rpm),
+            tpm: self.tpm.unwrap_or(defaults.tpm),
+            max_concurrent: self.max_concurrent.unwrap_or(defaults.max_concurrent),
+        }
+    }
+}
+
+/// API Key 文件格式
+#[derive(Debug, Deserialize)]
+struct KeyFile {
+    keys: Vec<ApiKey>,
+}
+
+/// API Key 存储
+#[derive(Debug, Clone, Default)]
+pub struct KeyStore {
+    keys: HashMap<String, Arc<ApiKey>>,
+}
+
+impl KeyStore {
+    /// 从 JSON 文件加载 API Key
+    ///
+    /// 文件格式:
+    /// ```json
+    /// {"keys": [{"key": "sk-...", "name": "team-a", "models": ["gemini-2.5-*"],
+    ///            "expires_at": "2026-12-31T00:00:00Z", "enabled": true,
+    ///            "rpm": 60, "tpm": 100000, "max_concurrent": 4,
+    ///            "monthly_budget": 100.0, "admin": false}]}
+    /// ```
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("无法读取 API Key 文件 {}: {}", path.display(), e))?;
+        let file: KeyFile = serde_json::from_str(&content)
+            .map_err(|e| format!("API Key 文件 {} 格式错误: {}", path.display(), e))?;
+        Self::from_keys(file.keys)
+    }
+
+    /// 校验并构建 Key 存储
+    pub fn from_keys(list: Vec<ApiKey>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut keys = HashMap::with_capacity(list.len());
+        for key in list {
+            if !key.key.starts_with("sk-") {
+                return Err(format!("API Key {} 必须以 sk- 开头", key.name).into());
+            }
+            if keys.contains_key(&key.key) {
+                return Err(format!("API Key {} 重复", key.name).into());
+            }
+            if key.monthly_budget.is_some_and(|b| !b.is_finite() || b < 0.0) {
+                return Err(format!("API Key {} 的 monthly_budget 必须为非负数", key.name).into());
+            }
+            keys.insert(key.key.clone(), Arc::new(key));
+        }
+        Ok(Self { keys })
+    }
+
+    /// 查找 API Key
+    pub fn get(&self, key: &str) -> Option<Arc<ApiKey>> {
+        self.keys.get(key).cloned()
+    }
+
+    /// Key 数量
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// 遍历所有 Key
+    pub fn iter(&self) -> impl Iterator<Item = &ApiKey> {
+        self.keys.values().map(Arc::as_ref)
+    }
+}
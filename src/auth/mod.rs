@@ -0,0 +1,170 @@
+pub mod keys;
+
+pub use keys::{ApiKey, KeyStore};
+
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::state::AppState;
+use axum::{
+    extract::{Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// Anthropic SDK 携带 API Key 的请求头
+const HEADER_X_API_KEY: &str = "x-api-key";
+/// Google GenAI SDK 携带 API Key 的请求头
+const HEADER_X_GOOG_API_KEY: &str = "x-goog-api-key";
+
+/// 客户端 API Key 认证中间件
+///
+/// 只有显式设置 `auth.disabled = true` 时不做校验;
+/// 否则要求请求携带有效的 `Authorization: Bearer sk-...`
+/// (Anthropic SDK 使用的 `x-api-key` 头、Google GenAI SDK 使用的 `x-goog-api-key` 头
+/// 和 `?key=` 查询参数同样有效),
+/// 并将匹配到的 [`ApiKey`] 放入请求扩展,供处理器校验模型权限;Key 的名称记入请求上下文
+pub async fn require_api_key(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(key_store) = &state.key_store else {
+        return next.run(request).await;
+    };
+
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| {
+            [HEADER_X_API_KEY, HEADER_X_GOOG_API_KEY]
+                .iter()
+                .find_map(|name| request.headers().get(*name))
+                .and_then(|v| v.to_str().ok())
+        })
+        .or_else(|| query_key(request.uri().query()));
+    let api_key = match authenticate(key_store, token) {
+        Ok(api_key) => api_key,
+        Err(e) => return e.into_response(),
+    };
+
+    if let Some(context) = request.extensions().get::<Arc<RequestContext>>() {
+        context.set_key(&api_key.name);
+    }
+    request.extensions_mut().insert(api_key);
+    next.run(request).await
+}
+
+/// 校验客户端携带的 API Key,失败时返回 OpenAI 格式的 401 错误
+fn authenticate(key_store: &KeyStore, token: Option<&str>) -> Result<Arc<ApiKey>, GatewayError> {
+    let Some(token) = token.map(str::trim).filter(|t| !t.is_empty()) else {
+        return Err(GatewayError::invalid_api_key("You didn't provide an API key. You need to provide your API key in an Authorization header using Bearer auth (i.e. Authorization: Bearer YOUR_KEY)."));
+    };
+
+    let Some(api_key) = key_store.get(token) else {
+        tracing::warn!("Rejected request with unknown API key");
+        return Err(GatewayError::invalid_api_key("Incorrect API key provided."));
+    };
+    if !api_key.enabled {
+        tracing::warn!("Rejected request with disabled API key: {}", api_key.name);
+        return Err(GatewayError::invalid_api_key(
+            "The API key provided has been disabled.",
+        ));
+    }
+    if api_key.is_expired() {
+        tracing::warn!("Rejected request with expired API key: {}", api_key.name);
+        return Err(GatewayError::invalid_api_key(
+            "The API key provided has expired.",
+        ));
+    }
+    Ok(api_key)
+}
+
+/// 查询参数中的 `key`
+///
+/// API Key 只包含 URL 安全字符,无需解码
+fn query_key(query: Option<&str>) -> Option<&str> {
+    query?.split('&').find_map(|pair| pair.strip_prefix("key="))
+}
+
+/// 校验客户端 Key 是否允许访问指定模型
+pub fn check_model(api_key: Option<&ApiKey>, model: &str) -> Result<(), GatewayError> {
+    match api_key {
+        Some(key) if !key.allows_model(model) => {
+            tracing::warn!("API key {} is not allowed to use model {}", key.name, model);
+            Err(GatewayError::permission_denied(format!(
+                "The API key '{}' is not allowed to use model '{}'.",
+                key.name, model
+            ))
+            .with_param("model")
+            .with_code("model_not_allowed"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    fn store() -> KeyStore {
+        let keys = json!([
+            {"key": "sk-active", "name": "active", "models": ["gemini-2.5-*"]},
+            {"key": "sk-disabled", "name": "disabled", "enabled": false},
+            {"key": "sk-expired", "name": "expired", "expires_at": "2020-01-01T00:00:00Z"}
+        ]);
+        KeyStore::from_keys(serde_json::from_value(keys).unwrap()).unwrap()
+    }
+
+    fn rejection(token: Option<&str>) -> GatewayError {
+        authenticate(&store(), token).unwrap_err()
+    }
+
+    #[test]
+    fn test_authenticate_accepts_active_key() {
+        let key = authenticate(&store(), Some(" sk-active ")).unwrap();
+        assert_eq!(key.name, "active");
+    }
+
+    #[test]
+    fn test_authenticate_rejects_invalid_keys() {
+        for (token, message) in [
+            (None, "You didn't provide an API key"),
+            (Some(""), "You didn't provide an API key"),
+            (Some("sk-unknown"), "Incorrect API key provided."),
+            (Some("sk-disabled"), "has been disabled"),
+            (Some("sk-expired"), "has expired"),
+        ] {
+            let error = rejection(token);
+            assert_eq!(error.status, StatusCode::UNAUTHORIZED);
+            let body = error.body();
+            assert_eq!(body["error"]["type"], "invalid_request_error");
+            assert_eq!(body["error"]["code"], "invalid_api_key");
+            assert!(body["error"]["message"].as_str().unwrap().contains(message));
+        }
+    }
+
+    #[test]
+    fn test_key_store_requires_sk_prefix() {
+        let keys = json!([{"key": "abc", "name": "legacy"}]);
+        let error = KeyStore::from_keys(serde_json::from_value(keys).unwrap()).unwrap_err();
+        assert!(error.to_string().contains("sk-"));
+    }
+
+    #[test]
+    fn test_check_model_rejects_disallowed_model() {
+        let key = authenticate(&store(), Some("sk-active")).unwrap();
+        check_model(Some(&key), "gemini-2.5-pro").unwrap();
+        check_model(None, "claude-sonnet-4").unwrap();
+
+        let error = check_model(Some(&key), "claude-sonnet-4").unwrap_err();
+        assert_eq!(error.status, StatusCode::FORBIDDEN);
+        assert_eq!(error.body()["error"]["code"], "model_not_allowed");
+        assert_eq!(error.body()["error"]["param"], "model");
+    }
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+/// 账本中的一条用量记录,对应一次请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub timestamp: DateTime<Utc>,
+    /// API Key 名称,未启用认证时为 `anonymous`
+    pub key: String,
+    pub model: String,
+    pub input_tokens: u64,
+    pub cached_tokens: u64,
+    pub output_tokens: u64,
+    pub reasoning_tokens: u64,
+    /// 费用(美元)
+    pub cost: f64,
+}
+
+/// 汇总后的用量
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub cached_tokens: u64,
+    pub output_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.output_tokens += other.output_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        self.cost += other.cost;
+    }
+}
+
+impl From<&LedgerEntry> for UsageTotals {
+    fn from(entry: &LedgerEntry) -> Self {
+        Self {
+            requests: 1,
+            input_tokens: entry.input_tokens,
+            cached_tokens: entry.cached_tokens,
+            output_tokens: entry.output_tokens,
+            reasoning_tokens: entry.reasoning_tokens,
+            cost: entry.cost,
+        }
+    }
+}
+
+/// 按 Key、模型和日期(UTC)汇总的一行
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageRow {
+    pub key: String,
+    pub model: String,
+    pub date: NaiveDate,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// 用量查询条件,日期范围为闭区间
+#[derive(Debug, Clone, Default)]
+pub struct UsageFilter {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+    pub key: Option<String>,
+    pub model: Option<String>,
+}
+
+type AggregateKey = (String, String, NaiveDate);
+
+/// 用量账本
+///
+/// 内存中按 Key、模型和日期汇总,配置了账本文件时每条记录同时由后台任务追加写入文件,
+/// 启动时回放文件恢复汇总
+#[derive(Debug, Default)]
+pub struct Ledger {
+    aggregates: Mutex<BTreeMap<AggregateKey, UsageTotals>>,
+    writer: Option<mpsc::UnboundedSender<LedgerEntry>>,
+}
+
+impl Ledger {
+    /// 打开账本,文件不存在时在首次写入时创建
+    pub fn open(path: Option<&Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+
+        let ledger = Self::default();
+        if path.exists() {
+            let file = std::fs::File::open(path)
+                .map_err(|e| format!("无法读取用量账本 {}: {}", path.display(), e))?;
+            let mut count = 0;
+            for (index, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<LedgerEntry>(&line) {
+                    Ok(entry) => {
+                        ledger.aggregate(&entry);
+                        count += 1;
+                    }
+                    Err(e) => tracing::warn!(
+                        "Skipping malformed ledger line {} in {}: {}",
+                        index + 1,
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+            tracing::info!("Loaded {} usage records from {}", count, path.display());
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_entries(path.to_owned(), receiver));
+        Ok(Self {
+            writer: Some(sender),
+            ..ledger
+        })
+    }
+
+    /// 记录一次请求的用量
+    pub fn record(&self, entry: LedgerEntry) {
+        self.aggregate(&entry);
+        if let Some(writer) = &self.writer {
+            if writer.send(entry).is_err() {
+                tracing::error!("Usage ledger writer has stopped");
+            }
+        }
+    }
+
+    fn aggregate(&self, entry: &LedgerEntry) {
+        let key = (
+            entry.key.clone(),
+            entry.model.clone(),
+            entry.timestamp.date_naive(),
+        );
+        self.aggregates
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .add(&UsageTotals::from(entry));
+    }
+
+    /// 指定 Key 在 `now` 所在月份(UTC)的费用
+    pub fn month_cost(&self, key: &str, now: DateTime<Utc>) -> f64 {
+        let today = now.date_naive();
+        let first = today.with_day(1).unwrap_or(today);
+        self.aggregates
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((k, _, date), _)| k == key && (first..=today).contains(date))
+            .map(|(_, totals)| totals.cost)
+            .sum()
+    }
+
+    /// 按条件查询汇总行,按 Key、模型、日期排序
+    pub fn query(&self, filter: &UsageFilter) -> Vec<UsageRow> {
+        self.aggregates
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((key, model, date), _)| {
+                filter.start.map_or(true, |start| *date >= start)
+                    && filter.end.map_or(true, |end| *date <= end)
+                    && filter.key.as_ref().map_or(true, |k| k == key)
+                    && filter.model.as_ref().map_or(true, |m| m == model)
+            })
+            .map(|((key, model, date), totals)| UsageRow {
+                key: key.clone(),
+                model: model.clone(),
+                date: *date,
+                totals: totals.clone(),
+            })
+            .collect()
+    }
+}
+
+/// 将查询结果按 Key 汇总
+pub fn totals_by_key(rows: &[UsageRow]) -> BTreeMap<String, UsageTotals> {
+    let mut totals: BTreeMap<String, UsageTotals> = BTreeMap::new();
+    for row in rows {
+        totals.entry(row.key.clone()).or_default().add(&row.totals);
+    }
+    totals
+}
+
+/// 后台写入任务,每条记录追加一行 JSON
+async fn write_entries(
+    path: std::path::PathBuf,
+    mut receiver: mpsc::UnboundedReceiver<LedgerEntry>,
+) {
+    let mut file = match tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Failed to open usage ledger {}: {}", path.display(), e);
+            return;
+        }
+    };
+    while let Some(entry) = receiver.recv().await {
+        let mut line = serde_json::to_vec(&entry).unwrap_or_default();
+        line.push(b'\n');
+        if let Err(e) = file.write_all(&line).await {
+            tracing::error!("Failed to write usage ledger {}: {}", path.display(), e);
+            continue;
+        }
+        if let Err(e) = file.flush().await {
+            tracing::error!("Failed to flush usage ledger {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, model: &str, timestamp: &str, cost: f64) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: timestamp.parse().unwrap(),
+            key: key.to_owned(),
+            model: model.to_owned(),
+            input_tokens: 100,
+            cached_tokens: 0,
+            output_tokens: 10,
+            reasoning_tokens: 0,
+            cost,
+        }
+    }
+
+    #[test]
+    fn test_month_cost_and_query_aggregate_by_day() {
+        let ledger = Ledger::default();
+        ledger.record(entry(
+            "team-a",
+            "gemini-2.5-pro",
+            "2026-09-30T23:00:00Z",
+            5.0,
+        ));
+        ledger.record(entry(
+            "team-a",
+            "gemini-2.5-pro",
+            "2026-10-02T01:00:00Z",
+            1.0,
+        ));
+        ledger.record(entry(
+            "team-a",
+            "gemini-2.5-pro",
+            "2026-10-02T12:00:00Z",
+            2.0,
+        ));
+        ledger.record(entry(
+            "team-b",
+            "gemini-2.5-flash",
+            "2026-10-03T12:00:00Z",
+            4.0,
+        ));
+
+        let now = "2026-10-17T00:00:00Z".parse().unwrap();
+        assert_eq!(ledger.month_cost("team-a", now), 3.0);
+        assert_eq!(ledger.month_cost("team-c", now), 0.0);
+
+        let rows = ledger.query(&UsageFilter {
+            start: NaiveDate::from_ymd_opt(2026, 10, 1),
+            ..Default::default()
+        });
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].totals.requests, 2);
+        assert_eq!(rows[0].totals.input_tokens, 200);
+        let totals = totals_by_key(&rows);
+        assert_eq!(totals["team-b"].cost, 4.0);
+    }
+}
@@ -0,0 +1,187 @@
+//! 用量计费
+//!
+//! 从每个响应中解析 token 用量(缺失时按请求前的预估),按模型价格表计算费用后写入用量账本,
+//! 设置了每月预算的 Key 在当月费用超出预算后被拒绝
+
+pub mod ledger;
+
+pub use ledger::{Ledger, LedgerEntry, UsageFilter, UsageRow, UsageTotals};
+
+use crate::auth::ApiKey;
+use crate::cache;
+use crate::config::{BillingConfig, PriceConfig};
+use crate::context::{ModelCall, RequestContext};
+use crate::error::GatewayError;
+use crate::models::Usage;
+use crate::state::{model_matches, AppState};
+use crate::usage::{cached_tokens, reasoning_tokens};
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use std::sync::Arc;
+
+/// 未启用客户端认证时账本中使用的 Key 名称
+pub const ANONYMOUS: &str = "anonymous";
+
+/// 计费器,持有价格表和用量账本
+#[derive(Debug)]
+pub struct Billing {
+    prices: Vec<PriceConfig>,
+    pub ledger: Ledger,
+}
+
+impl Billing {
+    pub fn new(config: &BillingConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            prices: config.prices.clone(),
+            ledger: Ledger::open(config.ledger_file.as_deref())?,
+        })
+    }
+
+    /// 计算一次请求的费用(美元),价格表中没有该模型时为 0
+    pub fn cost(&self, model: &str, usage: &Usage) -> f64 {
+        let Some(price) = self.prices.iter().find(|p| model_matches(&p.model, model)) else {
+            return 0.0;
+        };
+        let cached = cached_tokens(usage).min(usage.prompt_tokens);
+        let reasoning = reasoning_tokens(usage).min(usage.completion_tokens);
+        let tokens = [
+            (usage.prompt_tokens - cached, price.input),
+            (cached, price.cached_input.unwrap_or(price.input)),
+            (usage.completion_tokens - reasoning, price.output),
+            (reasoning, price.thinking.unwrap_or(price.output)),
+        ];
+        tokens
+            .iter()
+            .map(|(count, price)| *count as f64 * price)
+            .sum::<f64>()
+            / 1_000_000.0
+    }
+
+    /// 记录一次请求的用量
+    pub fn record(&self, key: &str, model: &str, usage: &Usage) {
+        self.ledger.record(LedgerEntry {
+            timestamp: Utc::now(),
+            key: key.to_owned(),
+            model: model.to_owned(),
+            input_tokens: usage.prompt_tokens,
+            cached_tokens: cached_tokens(usage),
+            output_tokens: usage.completion_tokens,
+            reasoning_tokens: reasoning_tokens(usage),
+            cost: self.cost(model, usage),
+        });
+    }
+
+    /// 检查 Key 的当月费用是否已超出预算
+    pub fn check_budget(&self, api_key: &ApiKey) -> Result<(), GatewayError> {
+        let Some(budget) = api_key.monthly_budget else {
+            return Ok(());
+        };
+        let now = Utc::now();
+        let spent = self.ledger.month_cost(&api_key.name, now);
+        if spent < budget {
+            return Ok(());
+        }
+        let reset = NaiveDate::from_ymd_opt(now.year(), now.month(), 1)
+            .and_then(|d| d.checked_add_months(Months::new(1)))
+            .map(|d| d.to_string())
+            .unwrap_or_default();
+        tracing::warn!(
+            "API key {} exceeded its monthly budget: spent ${:.4} of ${:.2}",
+            api_key.name,
+            spent,
+            budget
+        );
+        Err(GatewayError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "insufficient_quota",
+            format!(
+                "The API key '{}' has exceeded its monthly budget: spent ${:.4} of ${:.2}. The budget resets on {}.",
+                api_key.name, spent, budget, reset
+            ),
+        )
+        .with_code("insufficient_quota"))
+    }
+}
+
+/// 计费中间件,位于认证之后、限流之前
+///
+/// 只处理调用模型的路由(不论请求方法): 请求前检查 Key 的每月预算,成功响应结束后按 usage 写入账本,
+/// 命中响应缓存的请求不计费
+pub async fn enforce(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let context = request.extensions().get::<Arc<RequestContext>>().cloned();
+    if context
+        .as_ref()
+        .map_or(true, |c| c.model_call() == ModelCall::None)
+    {
+        return next.run(request).await;
+    }
+    let api_key = request.extensions().get::<Arc<ApiKey>>().cloned();
+    if let Some(api_key) = &api_key {
+        if let Err(e) = state.billing.check_budget(api_key) {
+            return e.into_response();
+        }
+    }
+
+    let response = next.run(request).await;
+    if !response.status().is_success() || cache::is_hit(&response) {
+        return response;
+    }
+    let Some(context) = context else {
+        return response;
+    };
+    let billing = state.billing.clone();
+    context.on_complete(move |context| {
+        let Some(model) = context.model() else {
+            return;
+        };
+        let Some(usage) = context.billable_usage() else {
+            return;
+        };
+        let key = api_key.as_ref().map_or(ANONYMOUS, |k| k.name.as_str());
+        billing.record(key, model, &usage);
+    });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CompletionTokensDetails, PromptTokensDetails};
+
+    #[test]
+    fn test_cost_prices_cached_and_thinking_tokens() {
+        let config: BillingConfig = toml::from_str(
+            r#"
+            [[prices]]
+            model = "gemini-2.5-pro*"
+            input = 1.0
+            output = 10.0
+            cached_input = 0.25
+            thinking = 20.0
+            "#,
+        )
+        .unwrap();
+        let billing = Billing::new(&config).unwrap();
+        let usage = Usage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 300_000,
+            total_tokens: 1_300_000,
+            prompt_tokens_details: Some(PromptTokensDetails {
+                cached_tokens: 400_000,
+            }),
+            completion_tokens_details: Some(CompletionTokensDetails {
+                reasoning_tokens: 100_000,
+            }),
+        };
+
+        // 0.6 * 1 + 0.4 * 0.25 + 0.2 * 10 + 0.1 * 20
+        let cost = billing.cost("google/gemini-2.5-pro", &usage);
+        assert!((cost - 4.7).abs() < 1e-9);
+        assert_eq!(billing.cost("gemini-2.5-flash", &usage), 0.0);
+    }
+}
@@ -0,0 +1,288 @@
+//! 聊天补全响应缓存
+//!
+//! 对确定性请求(默认要求 `temperature` 为 0)按请求精确匹配缓存完整的非流式响应。
+//! 缓存键由规范化后的请求体(对象键排序,去掉流式相关字段)和解析到的路由组成;
+//! 流式请求命中时把缓存的响应重放为 SSE 流。配置了持久化目录时每个响应同时写入
+//! 一个 JSON 文件,内存未命中时回退到磁盘。精确匹配未命中时可再查询语义缓存,见 [`semantic`]
+
+pub mod replay;
+pub mod semantic;
+
+use crate::config::ChatCacheConfig;
+use crate::routing::ResolvedRoute;
+use axum::{
+    http::{header::CACHE_CONTROL, HeaderMap, HeaderName, HeaderValue},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 缓存状态响应头
+pub static HEADER_X_CACHE: HeaderName = HeaderName::from_static("x-cache");
+
+/// 不参与缓存键的请求字段,它们只影响响应的传输方式
+const IGNORED_FIELDS: &[&str] = &["stream", "stream_options"];
+
+/// 一条缓存的响应
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedResponse {
+    /// 完整的缓存键,内存和磁盘都以它的指纹索引,读取时需核对
+    key: String,
+    created_at: DateTime<Utc>,
+    /// `chat.completion` 响应体
+    pub response: Value,
+}
+
+/// 客户端 `Cache-Control` 请求头中与缓存相关的指令
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheDirectives {
+    /// `no-cache`: 不读取缓存,但仍写入新的响应
+    pub no_cache: bool,
+    /// `no-store`: 既不读取也不写入
+    pub no_store: bool,
+}
+
+impl CacheDirectives {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut directives = Self::default();
+        for value in headers.get_all(CACHE_CONTROL) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            for directive in value.split(',').map(|d| d.trim().to_ascii_lowercase()) {
+                match directive.as_str() {
+                    "no-cache" => directives.no_cache = true,
+                    "no-store" => directives.no_store = true,
+                    _ => {}
+                }
+            }
+        }
+        directives
+    }
+}
+
+/// 聊天补全响应缓存
+#[derive(Debug)]
+pub struct ChatCache {
+    entries: moka::future::Cache<String, Arc<CachedResponse>>,
+    dir: Option<PathBuf>,
+    ttl: Duration,
+    deterministic_only: bool,
+}
+
+impl ChatCache {
+    /// 根据配置创建缓存,未启用时返回 `None`
+    pub fn new(config: &ChatCacheConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let entries = moka::future::Cache::builder()
+            .max_capacity(config.max_entries)
+            .time_to_live(config.ttl())
+            .build();
+        Some(Self {
+            entries,
+            dir: config.dir.clone(),
+            ttl: config.ttl(),
+            deterministic_only: config.deterministic_only,
+        })
+    }
+
+    /// 计算请求的缓存键,不可缓存的请求返回 `None`
+    pub fn key(&self, request: &Map<String, Value>, route: &ResolvedRoute) -> Option<String> {
+        if self.deterministic_only
+            && request.get("temperature").and_then(Value::as_f64) != Some(0.0)
+        {
+            return None;
+        }
+        let mut canonical = format!(
+            "{}|{}|{}|{:?}\n",
+            route.name, route.location, route.endpoint_id, route.backend
+        );
+        let body: Map<String, Value> = request
+            .iter()
+            .filter(|(k, _)| !IGNORED_FIELDS.contains(&k.as_str()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        write_canonical(&Value::Object(body), &mut canonical);
+        Some(canonical)
+    }
+
+    /// 查询缓存,内存未命中时回退到磁盘
+    pub async fn get(&self, key: &str) -> Option<Arc<CachedResponse>> {
+        let id = fingerprint(key);
+        if let Some(entry) = self.entries.get(&id).await {
+            if entry.key == key && !self.is_expired(&entry) {
+                return Some(entry);
+            }
+        }
+        let path = self.dir.as_ref()?.join(format!("{id}.json"));
+        let bytes = tokio::fs::read(&path).await.ok()?;
+        let entry: CachedResponse = match serde_json::from_slice(&bytes) {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!("Ignoring corrupt cache file {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if entry.key != key {
+            return None;
+        }
+        if self.is_expired(&entry) {
+            let _ = tokio::fs::remove_file(&path).await;
+            return None;
+        }
+        let entry = Arc::new(entry);
+        self.entries.insert(id, entry.clone()).await;
+        Some(entry)
+    }
+
+    /// 写入一个成功的非流式响应,持久化在后台进行
+    pub async fn insert(&self, key: String, response: Value) {
+        let id = fingerprint(&key);
+        let entry = Arc::new(CachedResponse {
+            key,
+            created_at: Utc::now(),
+            response,
+        });
+        self.entries.insert(id.clone(), entry.clone()).await;
+        if let Some(dir) = &self.dir {
+            let path = dir.join(format!("{id}.json"));
+            tokio::spawn(async move {
+                let bytes = serde_json::to_vec(&*entry).unwrap_or_default();
+                if let Err(e) = tokio::fs::write(&path, bytes).await {
+                    tracing::error!("Failed to write cache file {}: {}", path.display(), e);
+                }
+            });
+        }
+    }
+
+    fn is_expired(&self, entry: &CachedResponse) -> bool {
+        Utc::now()
+            .signed_duration_since(entry.created_at)
+            .to_std()
+            .is_ok_and(|age| age >= self.ttl)
+    }
+}
+
+/// 为响应加上 `x-cache` 头
+pub fn mark(response: &mut Response, hit: bool) {
+    let value = HeaderValue::from_static(if hit { "HIT" } else { "MISS" });
+    response.headers_mut().insert(HEADER_X_CACHE.clone(), value);
+}
+
+/// 响应是否来自缓存,命中的请求不消耗上游 token,不计费也不计入限流
+pub fn is_hit(response: &Response) -> bool {
+    response
+        .headers()
+        .get(&HEADER_X_CACHE)
+        .is_some_and(|v| v == "HIT")
+}
+
+/// 按对象键排序输出 JSON,保证相同内容的请求得到相同的键
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        value => out.push_str(&value.to_string()),
+    }
+}
+
+/// 缓存键的 128 位指纹,用作内存索引和磁盘文件名
+fn fingerprint(key: &str) -> String {
+    let hash = |seed: u8| {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        key.hash(&mut hasher);
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", hash(0), hash(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Backend;
+    use serde_json::json;
+
+    fn route(location: &str) -> ResolvedRoute {
+        ResolvedRoute {
+            name: "default".to_owned(),
+            location: location.to_owned(),
+            project_id: "p".to_owned(),
+            project_pinned: false,
+            endpoint_id: "openapi".to_owned(),
+            backend: Backend::Openapi,
+            fallback_locations: Vec::new(),
+            timeouts: crate::config::UpstreamConfig::default().timeouts(),
+            known_model: true,
+        }
+    }
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_key_ignores_order_and_stream_fields() {
+        let cache = ChatCache::new(&ChatCacheConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let a = object(json!({"model": "gemini-2.5-flash", "temperature": 0,
+            "messages": [{"role": "user", "content": "hi"}]}));
+        let b = object(json!({"messages": [{"content": "hi", "role": "user"}],
+            "stream": true, "temperature": 0, "model": "gemini-2.5-flash"}));
+        let hot = object(json!({"model": "gemini-2.5-flash", "temperature": 0.7,
+            "messages": [{"role": "user", "content": "hi"}]}));
+
+        let key = cache.key(&a, &route("us-central1")).unwrap();
+        assert_eq!(Some(&key), cache.key(&b, &route("us-central1")).as_ref());
+        assert_ne!(Some(key), cache.key(&a, &route("global")));
+        assert!(cache.key(&hot, &route("us-central1")).is_none());
+    }
+
+    #[test]
+    fn test_cache_control_directives() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_static("max-age=0, No-Cache"),
+        );
+        let directives = CacheDirectives::from_headers(&headers);
+        assert!(directives.no_cache);
+        assert!(!directives.no_store);
+    }
+}
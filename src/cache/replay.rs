@@ -0,0 +1,80 @@
+//! 将缓存的 `chat.completion` 响应重放为 `chat.completion.chunk` SSE 流
+
+use crate::sse::format_data;
+use serde_json::{json, Value};
+
+/// 生成完整的 SSE 响应体
+///
+/// 每个选项输出一个包含完整消息的块,`include_usage` 时追加只含 usage 的块,最后是 `[DONE]`
+pub fn to_sse(response: &Value, include_usage: bool) -> String {
+    let mut out = String::new();
+    let chunk = |choices: Value| {
+        json!({
+            "id": response["id"],
+            "object": "chat.completion.chunk",
+            "created": response["created"],
+            "model": response["model"],
+            "choices": choices,
+        })
+    };
+
+    let choices = response["choices"].as_array().cloned().unwrap_or_default();
+    for (position, choice) in choices.iter().enumerate() {
+        let mut delta = choice["message"].clone();
+        // 流式的 tool_calls 需要 index 字段
+        if let Some(calls) = delta.get_mut("tool_calls").and_then(Value::as_array_mut) {
+            for (index, call) in calls.iter_mut().enumerate() {
+                call["index"] = Value::from(index);
+            }
+        }
+        let choice = json!({
+            "index": choice.get("index").cloned().unwrap_or(Value::from(position)),
+            "delta": delta,
+            "finish_reason": choice["finish_reason"],
+        });
+        format_data(&mut out, &chunk(json!([choice])).to_string());
+    }
+
+    if include_usage {
+        if let Some(usage) = response.get("usage").filter(|u| u.is_object()) {
+            let mut usage_chunk = chunk(json!([]));
+            usage_chunk["usage"] = usage.clone();
+            format_data(&mut out, &usage_chunk.to_string());
+        }
+    }
+    format_data(&mut out, "[DONE]");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sse::SseDecoder;
+
+    #[test]
+    fn test_replay_emits_chunks_usage_and_done() {
+        let response = json!({
+            "id": "chatcmpl-1", "object": "chat.completion", "created": 1, "model": "gemini-2.5-flash",
+            "choices": [{"index": 0, "finish_reason": "tool_calls", "message": {
+                "role": "assistant", "content": null,
+                "tool_calls": [{"id": "call_1", "type": "function",
+                    "function": {"name": "f", "arguments": "{}"}}]
+            }}],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5}
+        });
+        let events: Vec<String> = SseDecoder::new()
+            .push(to_sse(&response, true).as_bytes())
+            .into_iter()
+            .map(|e| e.data)
+            .collect();
+
+        assert_eq!(events.len(), 3);
+        let first: Value = serde_json::from_str(&events[0]).unwrap();
+        assert_eq!(first["object"], "chat.completion.chunk");
+        assert_eq!(first["choices"][0]["delta"]["tool_calls"][0]["index"], 0);
+        assert_eq!(first["choices"][0]["finish_reason"], "tool_calls");
+        let usage: Value = serde_json::from_str(&events[1]).unwrap();
+        assert_eq!(usage["usage"]["total_tokens"], 5);
+        assert_eq!(events[2], "[DONE]");
+    }
+}
@@ -0,0 +1,289 @@
+//! 聊天补全语义缓存
+//!
+//! 用嵌入模型计算最后一条用户消息的向量,在进程内的向量索引中查找最相似的已缓存问题,
+//! 余弦相似度达到阈值时直接返回其响应。索引按客户端 Key、模型和上下文
+//! (除最后一条用户消息外的请求内容)分区,不同 Key 之间、不同对话历史之间互不命中。
+//! 分区内线性扫描,适合几万条以内的规模
+
+use super::{fingerprint, write_canonical, IGNORED_FIELDS};
+use crate::config::SemanticCacheConfig;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 索引分区
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Scope {
+    key: String,
+    model: String,
+    /// 上下文指纹
+    context: String,
+}
+
+/// 一条可用于语义查找的请求
+#[derive(Debug, Clone)]
+pub struct SemanticQuery {
+    pub scope: Scope,
+    /// 最后一条用户消息的文本
+    pub question: String,
+}
+
+/// 一个命中的缓存响应
+#[derive(Debug, Clone)]
+pub struct SemanticHit {
+    pub response: Arc<Value>,
+    pub similarity: f32,
+}
+
+#[derive(Debug)]
+struct Entry {
+    seq: u64,
+    /// 单位化后的向量
+    vector: Vec<f32>,
+    response: Arc<Value>,
+    created_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    scopes: HashMap<Scope, Vec<Entry>>,
+    /// 按写入顺序记录的条目,用于淘汰最早的条目;已被移除的条目在弹出时跳过
+    order: VecDeque<(u64, Scope)>,
+    next_seq: u64,
+    len: usize,
+}
+
+impl Index {
+    fn remove(&mut self, scope: &Scope, seq: u64) {
+        if let Some(entries) = self.scopes.get_mut(scope) {
+            let before = entries.len();
+            entries.retain(|e| e.seq != seq);
+            self.len -= before - entries.len();
+            if entries.is_empty() {
+                self.scopes.remove(scope);
+            }
+        }
+    }
+}
+
+/// 聊天补全语义缓存
+#[derive(Debug)]
+pub struct SemanticCache {
+    embedding_model: String,
+    threshold: f32,
+    ttl: Duration,
+    max_entries: usize,
+    index: Mutex<Index>,
+}
+
+impl SemanticCache {
+    /// 根据配置创建缓存,未启用时返回 `None`
+    pub fn new(config: &SemanticCacheConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self {
+            embedding_model: config.embedding_model.clone(),
+            threshold: config.threshold,
+            ttl: config.ttl(),
+            max_entries: config.max_entries as usize,
+            index: Mutex::new(Index::default()),
+        })
+    }
+
+    /// 计算问题向量使用的嵌入模型
+    pub fn embedding_model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    /// 提取请求的分区和问题文本,最后一条消息不是带文本的用户消息时返回 `None`
+    pub fn query(
+        &self,
+        key: &str,
+        model: &str,
+        request: &Map<String, Value>,
+    ) -> Option<SemanticQuery> {
+        let messages = request.get("messages")?.as_array()?;
+        let (last, history) = messages.split_last()?;
+        if last.get("role").and_then(Value::as_str) != Some("user") {
+            return None;
+        }
+        let question = message_text(last.get("content")?)?;
+
+        let mut context: Map<String, Value> = request
+            .iter()
+            .filter(|(k, _)| !IGNORED_FIELDS.contains(&k.as_str()) && k.as_str() != "messages")
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        context.insert("messages".to_owned(), Value::Array(history.to_vec()));
+        let mut canonical = String::new();
+        write_canonical(&Value::Object(context), &mut canonical);
+
+        Some(SemanticQuery {
+            scope: Scope {
+                key: key.to_owned(),
+                model: model.to_owned(),
+                context: fingerprint(&canonical),
+            },
+            question,
+        })
+    }
+
+    /// 在分区内查找相似度最高且达到阈值的响应,顺带清理过期条目
+    pub fn lookup(&self, scope: &Scope, vector: &[f32]) -> Option<SemanticHit> {
+        let vector = normalize(vector)?;
+        let mut index = self.index.lock().unwrap();
+        let entries = index.scopes.get_mut(scope)?;
+        let before = entries.len();
+        entries.retain(|e| e.created_at.elapsed() < self.ttl);
+        let expired = before - entries.len();
+        let hit = entries
+            .iter()
+            .filter(|e| e.vector.len() == vector.len())
+            .map(|e| (e, dot(&e.vector, &vector)))
+            .filter(|(_, similarity)| *similarity >= self.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(e, similarity)| SemanticHit {
+                response: e.response.clone(),
+                similarity,
+            });
+        if entries.is_empty() {
+            index.scopes.remove(scope);
+        }
+        index.len -= expired;
+        hit
+    }
+
+    /// 写入一个成功的非流式响应,超出容量时淘汰最早写入的条目
+    pub fn insert(&self, scope: Scope, vector: &[f32], response: Value) {
+        let Some(vector) = normalize(vector) else {
+            return;
+        };
+        let mut index = self.index.lock().unwrap();
+        let seq = index.next_seq;
+        index.next_seq += 1;
+        index.order.push_back((seq, scope.clone()));
+        index.scopes.entry(scope).or_default().push(Entry {
+            seq,
+            vector,
+            response: Arc::new(response),
+            created_at: Instant::now(),
+        });
+        index.len += 1;
+
+        while index.len > self.max_entries {
+            let Some((seq, scope)) = index.order.pop_front() else {
+                break;
+            };
+            index.remove(&scope, seq);
+        }
+        // 过期清理不会同步 order,避免其无限增长
+        if index.order.len() > self.max_entries.saturating_mul(2) {
+            let Index { scopes, order, .. } = &mut *index;
+            order.retain(|(seq, scope)| {
+                scopes
+                    .get(scope)
+                    .is_some_and(|entries| entries.iter().any(|e| e.seq == *seq))
+            });
+        }
+    }
+}
+
+/// 提取消息内容中的文本,多模态内容只取文本部分
+fn message_text(content: &Value) -> Option<String> {
+    let text = match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter(|p| p.get("type").and_then(Value::as_str) == Some("text"))
+            .filter_map(|p| p.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => return None,
+    };
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+/// 单位化向量,零向量返回 `None`
+fn normalize(vector: &[f32]) -> Option<Vec<f32>> {
+    let norm = dot(vector, vector).sqrt();
+    (norm > 0.0 && norm.is_finite()).then(|| vector.iter().map(|v| v / norm).collect())
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cache(max_entries: u64) -> SemanticCache {
+        SemanticCache::new(&SemanticCacheConfig {
+            enabled: true,
+            threshold: 0.9,
+            max_entries,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn request(history: &str, question: &str) -> Map<String, Value> {
+        match json!({"model": "gemini-2.5-flash", "stream": true, "messages": [
+            {"role": "system", "content": history},
+            {"role": "user", "content": [{"type": "text", "text": question}]},
+        ]}) {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_query_scopes_by_key_and_context() {
+        let cache = cache(10);
+        let a = cache
+            .query(
+                "alice",
+                "m",
+                &request("be brief", "How do I reset my password?"),
+            )
+            .unwrap();
+        let b = cache
+            .query("alice", "m", &request("be brief", "password reset?"))
+            .unwrap();
+        let other_key = cache
+            .query("bob", "m", &request("be brief", "password reset?"))
+            .unwrap();
+        let other_context = cache
+            .query("alice", "m", &request("be verbose", "password reset?"))
+            .unwrap();
+
+        assert_eq!(a.question, "How do I reset my password?");
+        assert_eq!(a.scope, b.scope);
+        assert_ne!(a.scope, other_key.scope);
+        assert_ne!(a.scope, other_context.scope);
+    }
+
+    #[test]
+    fn test_lookup_threshold_and_eviction() {
+        let cache = cache(2);
+        let scope = cache.query("k", "m", &request("", "q")).unwrap().scope;
+        cache.insert(scope.clone(), &[1.0, 0.0], json!({"answer": 1}));
+
+        let hit = cache.lookup(&scope, &[2.0, 0.2]).unwrap();
+        assert_eq!(hit.response["answer"], 1);
+        assert!(hit.similarity > 0.99);
+        assert!(cache.lookup(&scope, &[1.0, 1.0]).is_none());
+
+        cache.insert(scope.clone(), &[0.0, 1.0], json!({"answer": 2}));
+        cache.insert(scope.clone(), &[-1.0, 0.0], json!({"answer": 3}));
+        assert!(cache.lookup(&scope, &[1.0, 0.0]).is_none());
+        assert_eq!(
+            cache.lookup(&scope, &[0.0, 1.0]).unwrap().response["answer"],
+            2
+        );
+    }
+}
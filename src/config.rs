@@ -0,0 +1,1030 @@
+//! 网关配置
+//!
+//! 配置来源优先级(从高到低):
+//! 1. 环境变量(包括 .env 文件)
+//! 2. `--config` 指定的配置文件(TOML 或 YAML,按扩展名识别)
+//! 3. 内置默认值
+
+use crate::routing::RouteTable;
+use crate::timeout::Timeouts;
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// 配置错误
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// 网关配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub gcp: GcpConfig,
+    pub upstream: UpstreamConfig,
+    pub cache: CacheConfig,
+    pub logging: LoggingConfig,
+    pub routing: RoutingConfig,
+    pub auth: AuthConfig,
+    pub retry: RetryConfig,
+    pub rate_limit: RateLimitConfig,
+    pub billing: BillingConfig,
+    pub streaming: StreamingConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+/// 服务监听配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// 监听地址
+    pub listen: String,
+    /// 请求体大小上限(字节),超过时返回 413
+    pub max_body_bytes: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:8087".to_owned(),
+            max_body_bytes: 2 * 1024 * 1024,
+        }
+    }
+}
+
+/// GCP 项目配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GcpConfig {
+    /// 默认项目,配置了 `projects` 时可省略(使用第一个项目)
+    pub project_id: String,
+    pub location: String,
+    pub endpoint_id: String,
+    /// 项目池,请求按 `balance` 策略分摊到各项目以合并配额;为空时只使用 `project_id`
+    pub projects: Vec<ProjectConfig>,
+    /// 项目池负载均衡策略
+    pub balance: BalanceStrategy,
+    /// 连续返回配额错误多少次后暂时摘除项目
+    pub eject_after_failures: u32,
+    /// 项目被摘除的时长(秒)
+    pub eject_secs: u64,
+}
+
+impl Default for GcpConfig {
+    fn default() -> Self {
+        Self {
+            project_id: String::new(),
+            location: "global".to_owned(),
+            endpoint_id: "openapi".to_owned(),
+            projects: Vec::new(),
+            balance: BalanceStrategy::default(),
+            eject_after_failures: 3,
+            eject_secs: 60,
+        }
+    }
+}
+
+impl GcpConfig {
+    pub fn eject_duration(&self) -> Duration {
+        Duration::from_secs(self.eject_secs)
+    }
+}
+
+/// 项目池中的单个项目
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub project_id: String,
+    /// 权重,仅 weighted 策略使用;为 0 时只在路由显式指定该项目时使用
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// 服务账号或用户凭据 JSON 文件,默认使用 Application Default Credentials
+    #[serde(default)]
+    pub credentials_file: Option<PathBuf>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// 项目池负载均衡策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceStrategy {
+    /// 按权重随机选择
+    #[default]
+    Weighted,
+    /// 选择进行中请求最少的项目
+    LeastInFlight,
+    /// 轮询
+    RoundRobin,
+}
+
+/// 上游 HTTP 客户端配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpstreamConfig {
+    /// 连接超时(秒)
+    pub connect_timeout_secs: u64,
+    /// 非流式请求的总超时(秒)
+    pub timeout_secs: u64,
+    /// 流式请求等待首个数据块的超时(秒)
+    pub first_byte_timeout_secs: u64,
+    /// 流式响应相邻两个数据块之间的最大间隔(秒)
+    pub stream_idle_timeout_secs: u64,
+    /// 每个主机最大空闲连接数
+    pub pool_max_idle_per_host: usize,
+    /// 空闲连接超时(秒)
+    pub pool_idle_timeout_secs: u64,
+    /// TCP keep-alive 间隔(秒)
+    pub tcp_keepalive_secs: u64,
+    /// HTTP/2 keep-alive 间隔(秒)
+    pub http2_keep_alive_interval_secs: u64,
+    /// HTTP/2 keep-alive 超时(秒)
+    pub http2_keep_alive_timeout_secs: u64,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            timeout_secs: 600,
+            first_byte_timeout_secs: 300,
+            stream_idle_timeout_secs: 120,
+            pool_max_idle_per_host: 10,
+            pool_idle_timeout_secs: 90,
+            tcp_keepalive_secs: 60,
+            http2_keep_alive_interval_secs: 30,
+            http2_keep_alive_timeout_secs: 20,
+        }
+    }
+}
+
+impl UpstreamConfig {
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    /// 默认超时,路由可以单独覆盖
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            total: Duration::from_secs(self.timeout_secs),
+            first_byte: Duration::from_secs(self.first_byte_timeout_secs),
+            stream_idle: Duration::from_secs(self.stream_idle_timeout_secs),
+        }
+    }
+
+    pub fn pool_idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.pool_idle_timeout_secs)
+    }
+
+    pub fn tcp_keepalive(&self) -> Duration {
+        Duration::from_secs(self.tcp_keepalive_secs)
+    }
+
+    pub fn http2_keep_alive_interval(&self) -> Duration {
+        Duration::from_secs(self.http2_keep_alive_interval_secs)
+    }
+
+    pub fn http2_keep_alive_timeout(&self) -> Duration {
+        Duration::from_secs(self.http2_keep_alive_timeout_secs)
+    }
+}
+
+/// 上游重试配置
+///
+/// 仅在向客户端返回任何响应数据之前重试
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// 每个区域的最大尝试次数(含首次)
+    pub attempts_per_location: u32,
+    /// 首次重试的退避时间(毫秒),之后按指数增长
+    pub initial_backoff_ms: u64,
+    /// 最大退避时间(毫秒),Retry-After 超过该值时直接切换到下一个区域
+    pub max_backoff_ms: u64,
+    /// 触发重试的上游状态码
+    pub status_codes: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts_per_location: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+            status_codes: vec![429, 503],
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn initial_backoff(&self) -> Duration {
+        Duration::from_millis(self.initial_backoff_ms)
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_millis(self.max_backoff_ms)
+    }
+
+    /// 上游状态码是否可重试
+    pub fn is_retryable(&self, status: u16) -> bool {
+        self.status_codes.contains(&status)
+    }
+}
+
+/// 缓存配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// 模型列表缓存时间(秒)
+    pub models_ttl_secs: u64,
+    /// Responses 接口保存响应的时间(秒),过期后无法通过 `previous_response_id` 续接
+    pub responses_ttl_secs: u64,
+    /// Responses 接口最多保存的响应数量
+    pub responses_max_entries: u64,
+    /// 聊天补全响应缓存
+    pub chat: ChatCacheConfig,
+    /// 聊天补全语义缓存
+    pub semantic: SemanticCacheConfig,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            models_ttl_secs: 3600,
+            responses_ttl_secs: 86400,
+            responses_max_entries: 10000,
+            chat: ChatCacheConfig::default(),
+            semantic: SemanticCacheConfig::default(),
+        }
+    }
+}
+
+/// 聊天补全响应缓存配置
+///
+/// 按规范化后的请求体、模型和路由精确匹配,缓存完整的非流式响应
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatCacheConfig {
+    /// 是否启用
+    pub enabled: bool,
+    /// 缓存时间(秒)
+    pub ttl_secs: u64,
+    /// 内存中最多缓存的响应数量
+    pub max_entries: u64,
+    /// 持久化目录(需预先创建),设置后响应同时写入磁盘,重启后仍可命中
+    pub dir: Option<PathBuf>,
+    /// 只缓存 `temperature` 为 0 的请求
+    pub deterministic_only: bool,
+}
+
+impl Default for ChatCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_secs: 86400,
+            max_entries: 10000,
+            dir: None,
+            deterministic_only: true,
+        }
+    }
+}
+
+impl ChatCacheConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+}
+
+/// 聊天补全语义缓存配置
+///
+/// 用嵌入模型计算最后一条用户消息的向量,与同一 Key、模型和上下文下缓存的问题比较相似度
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SemanticCacheConfig {
+    /// 是否启用
+    pub enabled: bool,
+    /// 计算向量使用的 Vertex AI 嵌入模型
+    pub embedding_model: String,
+    /// 命中所需的最低余弦相似度,取值 (0, 1]
+    pub threshold: f32,
+    /// 缓存时间(秒)
+    pub ttl_secs: u64,
+    /// 最多缓存的问题数量,超出时淘汰最早写入的
+    pub max_entries: u64,
+}
+
+impl Default for SemanticCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            embedding_model: "text-embedding-005".to_owned(),
+            threshold: 0.95,
+            ttl_secs: 3600,
+            max_entries: 10000,
+        }
+    }
+}
+
+impl SemanticCacheConfig {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs)
+    }
+}
+
+/// 流式响应处理配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamingConfig {
+    /// 解析 openapi 端点的流式响应,逐块规范化为 OpenAI 格式,并保证以 `[DONE]` 结束
+    pub normalize: bool,
+    /// 规范化模式下上游没有数据时发送 `: keepalive` 注释的间隔(秒),0 表示不发送
+    pub keepalive_interval_secs: u64,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            normalize: false,
+            keepalive_interval_secs: 15,
+        }
+    }
+}
+
+impl StreamingConfig {
+    /// 心跳间隔,未启用规范化或间隔为 0 时为 `None`
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        (self.normalize && self.keepalive_interval_secs > 0)
+            .then(|| Duration::from_secs(self.keepalive_interval_secs))
+    }
+}
+
+/// OpenTelemetry 追踪配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// 是否通过 OTLP 导出 span
+    pub enabled: bool,
+    /// OTLP 传输协议
+    pub protocol: OtlpProtocol,
+    /// 采集端地址,未设置时使用 `OTEL_EXPORTER_OTLP_ENDPOINT` 或协议的默认地址;
+    /// HTTP 协议需写完整路径,如 `http://localhost:4318/v1/traces`
+    pub endpoint: Option<String>,
+    /// `service.name` 资源属性
+    pub service_name: String,
+    /// 没有上游 trace 时的采样比例,取值 [0, 1];客户端传入的 `traceparent` 按其采样标记处理
+    pub sample_ratio: f64,
+    /// 单次导出超时(秒)
+    pub timeout_secs: u64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: OtlpProtocol::Grpc,
+            endpoint: None,
+            service_name: "vertex-oai".to_owned(),
+            sample_ratio: 1.0,
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// OTLP 传输协议
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    /// gRPC,默认端口 4317
+    #[default]
+    Grpc,
+    /// HTTP/protobuf,默认端口 4318
+    Http,
+}
+
+/// 日志配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// 日志过滤规则,语法同 `RUST_LOG`,如 `info,vertex_oai=debug`
+    pub level: String,
+    /// 日志输出格式
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_owned(),
+            format: LogFormat::Human,
+        }
+    }
+}
+
+/// 日志输出格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// 便于阅读的文本格式
+    #[default]
+    Human,
+    /// 每行一个 JSON 对象,便于日志系统采集和检索
+    Json,
+}
+
+/// 路由配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    /// 使用原生 generateContent 翻译模式的模型,支持以 `*` 结尾的前缀匹配
+    pub native_models: Vec<String>,
+    /// 模型路由表,按顺序匹配,第一个命中的生效;未命中时使用 `gcp` 中的默认区域和项目
+    pub routes: Vec<RouteConfig>,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            native_models: Vec::new(),
+            // Gemini 3.x 仅在 global 区域提供
+            routes: vec![RouteConfig {
+                name: Some("gemini-3".to_owned()),
+                model: "gemini-3*".to_owned(),
+                match_type: MatchType::Glob,
+                location: Some("global".to_owned()),
+                project_id: None,
+                endpoint_id: None,
+                backend: None,
+                fallback_locations: Vec::new(),
+                timeout_secs: None,
+                first_byte_timeout_secs: None,
+                stream_idle_timeout_secs: None,
+            }],
+        }
+    }
+}
+
+/// 单条路由规则
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// 路由名称,用于日志和响应头,默认使用 `model`
+    #[serde(default)]
+    pub name: Option<String>,
+    /// 模型匹配模式
+    pub model: String,
+    /// 匹配方式,默认 glob
+    #[serde(default)]
+    pub match_type: MatchType,
+    /// 区域,默认 `gcp.location`
+    #[serde(default)]
+    pub location: Option<String>,
+    /// 项目,默认 `gcp.project_id`
+    #[serde(default)]
+    pub project_id: Option<String>,
+    /// 端点,默认 `gcp.endpoint_id`
+    #[serde(default)]
+    pub endpoint_id: Option<String>,
+    /// 后端模式,默认 claude-* 使用 anthropic,其余根据 `native_models` 判断
+    #[serde(default)]
+    pub backend: Option<Backend>,
+    /// 主区域重试耗尽后依次尝试的备用区域
+    #[serde(default)]
+    pub fallback_locations: Vec<String>,
+    /// 非流式请求的总超时(秒),默认 `upstream.timeout_secs`
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// 流式请求等待首个数据块的超时(秒),默认 `upstream.first_byte_timeout_secs`
+    #[serde(default)]
+    pub first_byte_timeout_secs: Option<u64>,
+    /// 流式响应的空闲超时(秒),默认 `upstream.stream_idle_timeout_secs`
+    #[serde(default)]
+    pub stream_idle_timeout_secs: Option<u64>,
+}
+
+/// 模型匹配方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchType {
+    /// 完全相同
+    Exact,
+    /// 前缀匹配
+    Prefix,
+    /// 通配符匹配,支持 `*` 和 `?`
+    #[default]
+    Glob,
+    /// 正则表达式(需完整匹配)
+    Regex,
+}
+
+/// 聊天接口后端模式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Vertex AI OpenAI 兼容端点透传
+    #[default]
+    Openapi,
+    /// 翻译为原生 generateContent 调用
+    Native,
+    /// 翻译为 Anthropic Messages 格式,调用 publishers/anthropic 的 rawPredict
+    Anthropic,
+}
+
+/// 客户端认证配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// 客户端 API Key 文件,必须设置,除非显式设置 `disabled = true`
+    pub keys_file: Option<PathBuf>,
+    /// 关闭客户端认证,允许匿名访问
+    pub disabled: bool,
+}
+
+/// 客户端限流配置
+///
+/// RPM/TPM 使用令牌桶,容量为每分钟限额并按秒平滑补充
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// 全局限制,所有客户端共享
+    pub global: LimitsConfig,
+    /// 每个 API Key 的默认限制,可在 Key 文件中按 Key 覆盖
+    pub per_key: LimitsConfig,
+}
+
+/// 一组限流阈值,0 表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// 每分钟请求数
+    pub rpm: u64,
+    /// 每分钟 token 数(输入与输出合计)
+    pub tpm: u64,
+    /// 最大并发请求数,流式请求在响应结束前一直占用
+    pub max_concurrent: u64,
+}
+
+impl LimitsConfig {
+    /// 是否未设置任何限制
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 用量计费配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BillingConfig {
+    /// 用量账本文件(JSON Lines,追加写入),启动时回放以恢复统计;未设置时只在内存中统计
+    pub ledger_file: Option<PathBuf>,
+    /// 模型价格表,按顺序匹配,第一个命中的规则生效,未命中的模型费用记为 0
+    pub prices: Vec<PriceConfig>,
+}
+
+/// 单个模型的价格,单位为美元/百万 token
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriceConfig {
+    /// 模型名,支持以 `*` 结尾的前缀匹配
+    pub model: String,
+    /// 输入 token 价格
+    pub input: f64,
+    /// 输出 token 价格
+    pub output: f64,
+    /// 缓存命中的输入 token 价格,未设置时同 `input`
+    #[serde(default)]
+    pub cached_input: Option<f64>,
+    /// 思考 token 价格,未设置时同 `output`
+    #[serde(default)]
+    pub thinking: Option<f64>,
+}
+
+impl Config {
+    /// 加载配置文件并应用环境变量覆盖,最后校验
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_env();
+        if config.gcp.project_id.trim().is_empty() {
+            if let Some(project) = config.gcp.projects.first() {
+                config.gcp.project_id = project.project_id.clone();
+            }
+        }
+        config.validate()?;
+        // 守护进程模式会切换工作目录,提前将相对路径转换为绝对路径
+        let files = config.auth.keys_file.iter_mut().chain(
+            config
+                .gcp
+                .projects
+                .iter_mut()
+                .filter_map(|p| p.credentials_file.as_mut())
+                .chain(config.cache.chat.dir.as_mut()),
+        );
+        for file in files {
+            if let Ok(path) = file.canonicalize() {
+                *file = path;
+            }
+        }
+        // 账本文件可能尚未创建,不能使用 canonicalize
+        if let Some(file) = config.billing.ledger_file.as_mut() {
+            if file.is_relative() {
+                if let Ok(dir) = std::env::current_dir() {
+                    *file = dir.join(&*file);
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// 从 TOML 或 YAML 文件读取配置
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError(format!("无法读取配置文件 {}: {}", path.display(), e)))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "toml" => toml::from_str(&content)
+                .map_err(|e| ConfigError(format!("配置文件 {} 格式错误: {}", path.display(), e))),
+            "yaml" | "yml" => serde_yaml::from_str(&content)
+                .map_err(|e| ConfigError(format!("配置文件 {} 格式错误: {}", path.display(), e))),
+            _ => Err(ConfigError(format!(
+                "不支持的配置文件格式 {},请使用 .toml、.yaml 或 .yml",
+                path.display()
+            ))),
+        }
+    }
+
+    /// 使用环境变量覆盖配置
+    fn apply_env(&mut self) {
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+
+        if let Some(listen) = env("LISTEN_ADDR") {
+            self.server.listen = listen;
+        }
+        if let Some(port) = env("PORT") {
+            let host = self
+                .server
+                .listen
+                .rsplit_once(':')
+                .map(|(host, _)| host.to_owned())
+                .unwrap_or_else(|| "0.0.0.0".to_owned());
+            self.server.listen = format!("{host}:{port}");
+        }
+        if let Some(project_id) = env("GCP_PROJECT_ID") {
+            self.gcp.project_id = project_id;
+        }
+        if let Some(location) = env("GCP_LOCATION") {
+            self.gcp.location = location;
+        }
+        if let Some(models) = env("GEMINI_NATIVE_MODELS") {
+            self.routing.native_models = models
+                .split(',')
+                .map(|m| m.trim().to_owned())
+                .filter(|m| !m.is_empty())
+                .collect();
+        }
+        if let Some(keys_file) = env("API_KEYS_FILE") {
+            self.auth.keys_file = Some(PathBuf::from(keys_file));
+        }
+        if let Some(disabled) = env("AUTH_DISABLED") {
+            self.auth.disabled = matches!(disabled.trim(), "1" | "true");
+        }
+        if let Some(level) = env("RUST_LOG") {
+            self.logging.level = level;
+        }
+    }
+
+    /// 校验配置,返回第一个错误
+    fn validate(&self) -> Result<(), ConfigError> {
+        let err = |msg: String| Err(ConfigError(msg));
+
+        if self.server.listen.parse::<SocketAddr>().is_err() {
+            return err(format!(
+                "server.listen 无效: {:?},应为 IP:端口 格式,如 0.0.0.0:8087",
+                self.server.listen
+            ));
+        }
+        if self.server.max_body_bytes == 0 {
+            return err("server.max_body_bytes 必须大于 0".to_owned());
+        }
+        if self.gcp.project_id.trim().is_empty() {
+            return err(
+                "未设置 GCP 项目 ID,请设置 gcp.project_id 或环境变量 GCP_PROJECT_ID".to_owned(),
+            );
+        }
+        if !is_valid_location(&self.gcp.location) {
+            return err(format!(
+                "gcp.location 无效: {:?},应为 global 或区域名称,如 us-central1",
+                self.gcp.location
+            ));
+        }
+        if self.gcp.endpoint_id.trim().is_empty() {
+            return err("gcp.endpoint_id 不能为空".to_owned());
+        }
+        for (index, project) in self.gcp.projects.iter().enumerate() {
+            if project.project_id.trim().is_empty() {
+                return err(format!("gcp.projects[{index}].project_id 不能为空"));
+            }
+            if self.gcp.projects[..index]
+                .iter()
+                .any(|p| p.project_id == project.project_id)
+            {
+                return err(format!(
+                    "gcp.projects 中存在重复的项目: {}",
+                    project.project_id
+                ));
+            }
+            if let Some(file) = &project.credentials_file {
+                if !file.is_file() {
+                    return err(format!(
+                        "gcp.projects[{}].credentials_file 不存在: {}",
+                        project.project_id,
+                        file.display()
+                    ));
+                }
+            }
+        }
+        if !self.gcp.projects.is_empty() && self.gcp.projects.iter().all(|p| p.weight == 0) {
+            return err("gcp.projects 中至少需要一个权重大于 0 的项目".to_owned());
+        }
+        if self.gcp.eject_after_failures == 0 {
+            return err("gcp.eject_after_failures 必须大于 0".to_owned());
+        }
+        for (name, value) in [
+            (
+                "upstream.connect_timeout_secs",
+                self.upstream.connect_timeout_secs,
+            ),
+            ("upstream.timeout_secs", self.upstream.timeout_secs),
+            (
+                "upstream.first_byte_timeout_secs",
+                self.upstream.first_byte_timeout_secs,
+            ),
+            (
+                "upstream.stream_idle_timeout_secs",
+                self.upstream.stream_idle_timeout_secs,
+            ),
+            ("cache.models_ttl_secs", self.cache.models_ttl_secs),
+            ("cache.responses_ttl_secs", self.cache.responses_ttl_secs),
+            (
+                "cache.responses_max_entries",
+                self.cache.responses_max_entries,
+            ),
+            ("cache.chat.ttl_secs", self.cache.chat.ttl_secs),
+            ("cache.chat.max_entries", self.cache.chat.max_entries),
+            ("cache.semantic.ttl_secs", self.cache.semantic.ttl_secs),
+            (
+                "cache.semantic.max_entries",
+                self.cache.semantic.max_entries,
+            ),
+            ("telemetry.timeout_secs", self.telemetry.timeout_secs),
+        ] {
+            if value == 0 {
+                return err(format!("{name} 必须大于 0"));
+            }
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.logging.level) {
+            return err(format!(
+                "logging.level 无效 {:?}: {}",
+                self.logging.level, e
+            ));
+        }
+        for route in &self.routing.routes {
+            for location in route.location.iter().chain(&route.fallback_locations) {
+                if !is_valid_location(location) {
+                    return err(format!(
+                        "routing.routes[{}] 中的区域无效: {:?}",
+                        route.model, location
+                    ));
+                }
+            }
+            if [
+                route.timeout_secs,
+                route.first_byte_timeout_secs,
+                route.stream_idle_timeout_secs,
+            ]
+            .contains(&Some(0))
+            {
+                return err(format!(
+                    "routing.routes[{}] 中的超时必须大于 0",
+                    route.model
+                ));
+            }
+        }
+        if self.retry.attempts_per_location == 0 {
+            return err("retry.attempts_per_location 必须大于 0".to_owned());
+        }
+        if self.retry.initial_backoff_ms > self.retry.max_backoff_ms {
+            return err("retry.initial_backoff_ms 不能大于 retry.max_backoff_ms".to_owned());
+        }
+        RouteTable::new(self)?;
+        match (&self.auth.keys_file, self.auth.disabled) {
+            (Some(_), true) => {
+                return err("auth.disabled = true 时不能同时设置 auth.keys_file".to_owned());
+            }
+            (Some(keys_file), false) if !keys_file.is_file() => {
+                return err(format!("auth.keys_file 不存在: {}", keys_file.display()));
+            }
+            (None, false) => {
+                return err(
+                    "未设置 auth.keys_file 或环境变量 API_KEYS_FILE;如需允许匿名访问,请显式设置 auth.disabled = true"
+                        .to_owned(),
+                );
+            }
+            _ => {}
+        }
+        if !self.rate_limit.per_key.is_unlimited() && self.auth.keys_file.is_none() {
+            return err("rate_limit.per_key 需要同时设置 auth.keys_file".to_owned());
+        }
+        for price in &self.billing.prices {
+            let prices = [
+                Some(price.input),
+                Some(price.output),
+                price.cached_input,
+                price.thinking,
+            ];
+            if prices
+                .into_iter()
+                .flatten()
+                .any(|p| !p.is_finite() || p < 0.0)
+            {
+                return err(format!(
+                    "billing.prices[{}] 中的价格必须为非负数",
+                    price.model
+                ));
+            }
+        }
+        if let Some(parent) = self
+            .billing
+            .ledger_file
+            .as_ref()
+            .and_then(|f| f.parent())
+            .filter(|p| !p.as_os_str().is_empty())
+        {
+            if !parent.is_dir() {
+                return err(format!(
+                    "billing.ledger_file 所在目录不存在: {}",
+                    parent.display()
+                ));
+            }
+        }
+        let semantic = &self.cache.semantic;
+        if !(semantic.threshold > 0.0 && semantic.threshold <= 1.0) {
+            return err(format!(
+                "cache.semantic.threshold 必须在 (0, 1] 范围内: {}",
+                semantic.threshold
+            ));
+        }
+        if semantic.enabled && !semantic.embedding_model.contains("embedding") {
+            return err(format!(
+                "cache.semantic.embedding_model 不是嵌入模型: {:?}",
+                semantic.embedding_model
+            ));
+        }
+        let telemetry = &self.telemetry;
+        if !(0.0..=1.0).contains(&telemetry.sample_ratio) {
+            return err(format!(
+                "telemetry.sample_ratio 必须在 [0, 1] 范围内: {}",
+                telemetry.sample_ratio
+            ));
+        }
+        if let Some(endpoint) = &telemetry.endpoint {
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                return err(format!(
+                    "telemetry.endpoint 应以 http:// 或 https:// 开头: {endpoint:?}"
+                ));
+            }
+        }
+        if telemetry.service_name.trim().is_empty() {
+            return err("telemetry.service_name 不能为空".to_owned());
+        }
+        if let Some(dir) = &self.cache.chat.dir {
+            if !dir.is_dir() {
+                return err(format!("cache.chat.dir 目录不存在: {}", dir.display()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 监听地址(已在加载时校验)
+    pub fn listen_addr(&self) -> SocketAddr {
+        self.server
+            .listen
+            .parse()
+            .expect("validated listen address")
+    }
+}
+
+/// 区域名称只允许小写字母、数字和连字符
+fn is_valid_location(location: &str) -> bool {
+    !location.is_empty()
+        && location
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml_and_validate() {
+        let config: Config = toml::from_str(
+            r#"
+            [server]
+            listen = "127.0.0.1:9000"
+
+            [gcp]
+            project_id = "my-project"
+            location = "us-central1"
+
+            [upstream]
+            timeout_secs = 120
+
+            [routing]
+            native_models = ["gemini-2.5-*"]
+
+            [[routing.routes]]
+            model = "claude-*"
+            location = "us-east5"
+
+            [auth]
+            disabled = true
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.listen_addr().port(), 9000);
+        assert_eq!(config.gcp.endpoint_id, "openapi");
+        assert_eq!(config.upstream.connect_timeout_secs, 10);
+        let routes = RouteTable::new(&config).unwrap();
+        assert_eq!(
+            routes.resolve("google/gemini-2.5-pro").backend,
+            Backend::Native
+        );
+        assert_eq!(
+            routes.resolve("claude-sonnet-4").backend,
+            Backend::Anthropic
+        );
+        assert_eq!(routes.resolve("claude-sonnet-4").location, "us-east5");
+        assert_eq!(config.routing.routes.len(), 1);
+        assert_eq!(config.routing.routes[0].match_type, MatchType::Glob);
+    }
+
+    #[test]
+    fn test_project_pool_config() {
+        let config: Config = serde_yaml::from_str(
+            "gcp:\n  project_id: a\n  balance: least_in_flight\n  projects:\n    - project_id: a\n    - project_id: b\n      weight: 3\nauth:\n  disabled: true\n",
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.gcp.balance, BalanceStrategy::LeastInFlight);
+        assert_eq!(config.gcp.projects[0].weight, 1);
+        assert_eq!(config.gcp.projects[1].weight, 3);
+
+        let mut duplicated = config.clone();
+        duplicated.gcp.projects[1].project_id = "a".to_owned();
+        assert!(duplicated.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_missing_project() {
+        let error = Config::default().validate().unwrap_err();
+        assert!(error.to_string().contains("GCP_PROJECT_ID"));
+    }
+
+    #[test]
+    fn test_validate_requires_keys_file_unless_auth_disabled() {
+        let mut config: Config = toml::from_str("[gcp]\nproject_id = \"p\"\n").unwrap();
+        let error = config.validate().unwrap_err();
+        assert!(error.to_string().contains("auth.disabled"));
+
+        config.auth.disabled = true;
+        config.validate().unwrap();
+
+        config.auth.keys_file = Some(PathBuf::from("keys.json"));
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_rejected() {
+        let result: Result<Config, _> = serde_yaml::from_str("gcp:\n  projet_id: x\n");
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,771 @@
+//! 请求上下文
+//!
+//! 由中间件为每个请求创建并放入请求扩展,处理器在处理过程中逐步填充,
+//! 中间件在响应返回后读取,用于指标统计和调试响应头。
+//! 中间件同时负责识别客户端断开导致的取消,启用追踪时创建入站请求的 span,
+//! 并分配请求 ID(`x-request-id`)。
+//!
+//! 响应体只被中间件跟踪一次: 发送完毕(或客户端断开)后汇总的 [`ResponseSummary`]
+//! 写入上下文,随后依次运行计费、限流注册的完成回调,记录 token 指标和追踪属性,并输出访问日志
+
+use crate::access_log;
+use crate::cache;
+use crate::error::GatewayError;
+use crate::metrics;
+use crate::models::Usage;
+use crate::routing::ResolvedRoute;
+use crate::telemetry;
+use crate::usage::{self, ResponseSummary};
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header::CONTENT_TYPE, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+use tracing::{field::Empty, Instrument, Span};
+
+/// 返回给客户端的路由调试响应头
+static HEADER_VERTEX_ROUTE: HeaderName = HeaderName::from_static("x-vertex-route");
+
+/// 一次上游调用的用量写入位置,流式转换器每处理一个事件写入一次
+pub type UsageSlot = Arc<Mutex<Option<Usage>>>;
+
+/// 响应体发送完毕后运行的回调
+type CompletionHook = Box<dyn FnOnce(&RequestContext) + Send>;
+
+/// 完成回调列表
+#[derive(Default)]
+struct CompletionHooks(Mutex<Vec<CompletionHook>>);
+
+impl fmt::Debug for CompletionHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CompletionHooks({})", self.0.lock().unwrap().len())
+    }
+}
+
+/// 请求对模型的调用方式,由路由决定(与请求方法无关的路由不区分方法)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModelCall {
+    /// 不调用模型,如模型列表、查询已保存的响应、用量报告
+    #[default]
+    None,
+    /// 生成内容或向量,消耗 token
+    Generation,
+    /// 调用 Vertex AI 但不生成内容,如计算 token 数、创建或更新上下文缓存
+    Auxiliary,
+}
+
+impl ModelCall {
+    /// 按匹配到的路由和请求方法分类,`path` 用于区分 Gemini API 的 `:countTokens`
+    pub fn classify(method: &Method, route: &str, path: &str) -> Self {
+        let route = route
+            .trim_start_matches("/v1beta")
+            .trim_start_matches("/v1");
+        match route {
+            "/chat/completions" | "/completions" | "/embeddings" | "/messages" | "/responses" => {
+                Self::Generation
+            }
+            "/models/{model}" if path.ends_with(":countTokens") => Self::Auxiliary,
+            "/models/{model}" => Self::Generation,
+            "/count_tokens" => Self::Auxiliary,
+            "/cached_contents" if method == Method::POST => Self::Auxiliary,
+            "/cached_contents/{*name}" if method == Method::PATCH => Self::Auxiliary,
+            _ => Self::None,
+        }
+    }
+}
+
+/// 单个请求的上下文
+#[derive(Debug, Default)]
+pub struct RequestContext {
+    request_id: String,
+    model_call: ModelCall,
+    key: OnceLock<String>,
+    model: OnceLock<String>,
+    route: Mutex<Option<ResolvedRoute>>,
+    upstream_status: Mutex<Option<StatusCode>>,
+    usage: Mutex<Vec<UsageSlot>>,
+    estimated_usage: OnceLock<Usage>,
+    summary: OnceLock<ResponseSummary>,
+    hooks: CompletionHooks,
+}
+
+impl RequestContext {
+    fn new(request_id: String, model_call: ModelCall) -> Self {
+        Self {
+            request_id,
+            model_call,
+            ..Default::default()
+        }
+    }
+
+    /// 请求 ID,来自客户端的 `x-request-id` 或由网关生成
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// 请求对模型的调用方式,调用模型的请求受限流和预算约束
+    pub fn model_call(&self) -> ModelCall {
+        self.model_call
+    }
+
+    /// 记录客户端 API Key 的名称
+    pub fn set_key(&self, name: &str) {
+        let _ = self.key.set(name.to_owned());
+    }
+
+    /// 客户端 API Key 的名称,未启用认证时为 `None`
+    pub fn key(&self) -> Option<&str> {
+        self.key.get().map(String::as_str)
+    }
+
+    /// 记录请求的模型(仅第一次生效)
+    pub fn set_model(&self, model: &str) {
+        let _ = self.model.set(model.to_owned());
+    }
+
+    /// 请求的模型
+    pub fn model(&self) -> Option<&str> {
+        self.model.get().map(String::as_str)
+    }
+
+    /// 指标中使用的模型标签
+    ///
+    /// 模型名由客户端控制,只有通过权限校验、且解析到的路由表明是已知模型
+    /// (命中显式路由规则、`native_models` 或价格表)时才作为标签,否则记为 `unknown`,
+    /// 避免任意模型名造成标签基数无限增长;未涉及模型的请求为空
+    pub fn model_label(&self) -> &str {
+        let Some(model) = self.model() else {
+            return "";
+        };
+        match self.route.lock().unwrap().as_ref() {
+            Some(route) => route.model_label(model),
+            None => metrics::UNKNOWN_MODEL,
+        }
+    }
+
+    /// 记录解析到的路由
+    pub fn set_route(&self, route: &ResolvedRoute) {
+        *self.route.lock().unwrap() = Some(route.clone());
+    }
+
+    /// 记录上游调用的结果: 成功时更新故障转移后实际使用的区域,并记录 Vertex AI 的状态码
+    pub fn record_upstream(&self, result: &Result<(reqwest::Response, String), GatewayError>) {
+        let status = match result {
+            Ok((response, location)) => {
+                if let Some(route) = self.route.lock().unwrap().as_mut() {
+                    route.location = location.clone();
+                }
+                Some(response.status())
+            }
+            Err(e) => e.upstream_status,
+        };
+        self.set_upstream_status(status);
+    }
+
+    /// 记录 Vertex AI 返回的状态码,多次调用上游时以最后一次为准
+    pub fn set_upstream_status(&self, status: Option<StatusCode>) {
+        *self.upstream_status.lock().unwrap() = status;
+    }
+
+    /// Vertex AI 返回的状态码,未调用上游或没有收到响应时为 `None`
+    pub fn upstream_status(&self) -> Option<StatusCode> {
+        *self.upstream_status.lock().unwrap()
+    }
+
+    /// 解析到的路由
+    pub fn route(&self) -> Option<ResolvedRoute> {
+        self.route.lock().unwrap().clone()
+    }
+
+    /// 为一次上游调用分配用量写入位置,交给流式转换器在翻译前记录
+    pub fn usage_slot(&self) -> UsageSlot {
+        let slot = UsageSlot::default();
+        self.usage.lock().unwrap().push(slot.clone());
+        slot
+    }
+
+    /// 记录一次上游调用的用量(翻译前,不受客户端 `include_usage` 影响)
+    pub fn record_usage(&self, usage: &Usage) {
+        *self.usage_slot().lock().unwrap() = Some(usage.clone());
+    }
+
+    /// 上游报告的用量,多次上游调用(如多个提示的文本补全)时累加;
+    /// 流式响应中途断开时为已收到的部分
+    pub fn usage(&self) -> Option<Usage> {
+        let slots = self.usage.lock().unwrap();
+        let mut total: Option<Usage> = None;
+        for usage in slots.iter().filter_map(|slot| slot.lock().unwrap().clone()) {
+            usage::add_usage(total.get_or_insert_with(Default::default), &usage);
+        }
+        total
+    }
+
+    /// 记录按请求体估算的用量
+    pub fn set_estimated_usage(&self, usage: Usage) {
+        let _ = self.estimated_usage.set(usage);
+    }
+
+    /// 按请求体估算的用量,上游没有报告用量时用于计费
+    pub fn estimated_usage(&self) -> Option<&Usage> {
+        self.estimated_usage.get()
+    }
+
+    /// 计费和 TPM 校正使用的用量,响应体发送完毕后可用
+    ///
+    /// 优先使用响应摘要中的用量(转换前从上游响应中读取,与客户端是否请求 usage 无关);
+    /// 没有用量时,生成请求(包括被取消的流)按请求前的预估计费,
+    /// 计数 token、上下文缓存等不生成内容的请求不计费
+    pub fn billable_usage(&self) -> Option<Usage> {
+        let usage = self.summary().and_then(|s| s.usage.as_ref());
+        match self.model_call {
+            ModelCall::Generation => usage.or(self.estimated_usage()).cloned(),
+            _ => usage.cloned(),
+        }
+    }
+
+    /// 响应摘要,响应体发送完毕(或客户端断开)后才有值
+    pub fn summary(&self) -> Option<&ResponseSummary> {
+        self.summary.get()
+    }
+
+    /// 注册响应体发送完毕后运行的回调,回调中可以读取 [`summary`](Self::summary)
+    ///
+    /// 回调按注册顺序运行;处理器的 future 被丢弃(响应头返回前断开)时不会运行
+    pub fn on_complete(&self, hook: impl FnOnce(&RequestContext) + Send + 'static) {
+        self.hooks.0.lock().unwrap().push(Box::new(hook));
+    }
+
+    /// 记录响应摘要并运行完成回调,上游报告的用量优先于从响应体中解析的用量
+    fn complete(&self, mut summary: ResponseSummary) {
+        if let Some(usage) = self.usage() {
+            summary.usage = Some(usage);
+        }
+        let _ = self.summary.set(summary);
+        let hooks = std::mem::take(&mut *self.hooks.0.lock().unwrap());
+        for hook in hooks {
+            hook(self);
+        }
+    }
+}
+
+/// 取消检测守卫
+///
+/// 客户端断开时 hyper 会直接丢弃处理器的 future,守卫随之被丢弃;
+/// 未被解除就被丢弃说明响应头返回前客户端已断开,此时持有的上游请求已随之丢弃,
+/// reqwest 会关闭对应的连接(HTTP/2 下为重置流),不再继续消耗 token。
+/// 响应体不会再被跟踪,因此由守卫输出这个请求的访问日志
+struct CancelGuard {
+    route: String,
+    method: Method,
+    context: Arc<RequestContext>,
+    start: Instant,
+    armed: bool,
+}
+
+impl CancelGuard {
+    fn new(route: &str, method: &Method, context: &Arc<RequestContext>, start: Instant) -> Self {
+        Self {
+            route: route.to_owned(),
+            method: method.clone(),
+            context: context.clone(),
+            start,
+            armed: true,
+        }
+    }
+
+    /// 请求正常完成
+    fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        record_cancelled(&self.route, &self.context, "request", self.start);
+        access_log::emit(
+            &self.context,
+            &self.method,
+            &self.route,
+            access_log::client_closed_request(),
+            false,
+            self.start,
+        );
+    }
+}
+
+/// 记录客户端断开导致的取消,`phase` 为 `request`(响应头返回前)或 `stream`
+fn record_cancelled(route: &str, context: &RequestContext, phase: &str, start: Instant) {
+    tracing::warn!(
+        "Client disconnected from {} (request_id: {}, model: {}, phase: {}) after {:?}, upstream request cancelled",
+        route,
+        context.request_id(),
+        context.model().unwrap_or(""),
+        phase,
+        start.elapsed()
+    );
+    metrics::record_cancelled(route, context.model_label(), phase);
+}
+
+/// 请求上下文中间件
+///
+/// 为每个请求创建 [`RequestContext`],响应返回后记录请求指标,
+/// 并通过 `x-vertex-route` 和 `x-request-id` 响应头暴露解析到的路由和请求 ID。
+/// 客户端在响应头返回前或流式响应中途断开时记录日志和取消指标。
+/// 响应体由这里统一跟踪,只有调用模型的请求的响应体会被解析
+pub async fn track_request(mut request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let method = request.method().clone();
+    let model_call = ModelCall::classify(&method, &route, request.uri().path());
+    let context = Arc::new(RequestContext::new(
+        access_log::request_id(request.headers()),
+        model_call,
+    ));
+    request.extensions_mut().insert(context.clone());
+
+    let operation = telemetry::operation(&route);
+    let span = request_span(&request, &route, operation);
+
+    let start = Instant::now();
+    let mut guard = CancelGuard::new(&route, &method, &context, start);
+    let mut response = next.run(request).instrument(span.clone()).await;
+    guard.disarm();
+
+    let status = response.status();
+    metrics::record_request(&route, context.model_label(), status);
+    record_response(&span, &context, &response);
+    if let Some(resolved) = context.route() {
+        if let Ok(value) = HeaderValue::from_str(&resolved.header_value()) {
+            response
+                .headers_mut()
+                .insert(HEADER_VERTEX_ROUTE.clone(), value);
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(context.request_id()) {
+        response
+            .headers_mut()
+            .insert(access_log::HEADER_X_REQUEST_ID.clone(), value);
+    }
+    let stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    // 命中响应缓存和失败的请求没有消耗上游 token
+    let consumed = status.is_success() && !cache::is_hit(&response);
+    // 模型调用的 span 持续到响应体发送完毕
+    let stream_span = if stream && operation.is_some() && !span.is_none() {
+        tracing::info_span!(target: telemetry::TARGET, parent: &span, "stream")
+    } else {
+        Span::none()
+    };
+
+    let parse = model_call != ModelCall::None;
+    usage::summarize_response(response, parse, move |summary| {
+        if stream && summary.cancelled() {
+            record_cancelled(&route, &context, "stream", start);
+        }
+        context.complete(summary);
+        let Some(summary) = context.summary() else {
+            return;
+        };
+        if let (true, Some(usage)) = (consumed, &summary.usage) {
+            metrics::record_usage(context.model_label(), usage);
+        }
+        telemetry::record_summary(&span, summary);
+        drop(stream_span);
+        access_log::emit(&context, &method, &route, status, stream, start);
+    })
+}
+
+/// 启用追踪时创建入站请求的 span,延续请求头中的 trace
+fn request_span(request: &Request, route: &str, operation: Option<&str>) -> Span {
+    if !telemetry::enabled() {
+        return Span::none();
+    }
+    let method = request.method().as_str();
+    let span = tracing::info_span!(
+        target: telemetry::TARGET,
+        "request",
+        otel.name = %format!("{method} {route}"),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = method,
+        http.route = route,
+        http.response.status_code = Empty,
+        gen_ai.operation.name = operation,
+        gen_ai.provider.name = operation.map(|_| telemetry::PROVIDER_NAME),
+        gen_ai.request.model = Empty,
+        gen_ai.response.id = Empty,
+        gen_ai.response.finish_reasons = Empty,
+        gen_ai.usage.input_tokens = Empty,
+        gen_ai.usage.output_tokens = Empty,
+        cloud.region = Empty,
+        gcp.project_id = Empty,
+    );
+    telemetry::set_parent(&span, request.headers());
+    span
+}
+
+/// 将响应状态和解析到的路由记录到请求 span
+fn record_response(span: &Span, context: &RequestContext, response: &Response) {
+    if span.is_none() {
+        return;
+    }
+    let status = response.status();
+    span.record("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+    if let Some(model) = context.model() {
+        span.record("gen_ai.request.model", model);
+    }
+    if let Some(route) = context.route() {
+        span.record("cloud.region", route.location.as_str());
+        span.record("gcp.project_id", route.project_id.as_str());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, PriceConfig};
+    use crate::routing::RouteTable;
+
+    #[test]
+    fn test_model_label_requires_known_model() {
+        let mut config = Config::default();
+        config.gcp.project_id = "main".to_owned();
+        config.routing.native_models = vec!["gemini-2.5-*".to_owned()];
+        config.billing.prices = vec![PriceConfig {
+            model: "google/gemini-2.0-flash".to_owned(),
+            input: 0.1,
+            output: 0.4,
+            cached_input: None,
+            thinking: None,
+        }];
+        let routes = RouteTable::new(&config).unwrap();
+        let label = |model: &str, route: Option<&str>| {
+            let context = RequestContext::default();
+            context.set_model(model);
+            if let Some(route) = route {
+                context.set_route(&routes.resolve(route));
+            }
+            context.model_label().to_owned()
+        };
+
+        assert_eq!(RequestContext::default().model_label(), "");
+        assert_eq!(label("gemini-2.5-pro", None), metrics::UNKNOWN_MODEL);
+        // 默认路由不代表模型已知
+        assert_eq!(
+            label("anything-goes", Some("anything-goes")),
+            metrics::UNKNOWN_MODEL
+        );
+        assert_eq!(
+            label("gemini-2.5-pro", Some("gemini-2.5-pro")),
+            "gemini-2.5-pro"
+        );
+        assert_eq!(
+            label("gemini-2.0-flash", Some("gemini-2.0-flash")),
+            "gemini-2.0-flash"
+        );
+    }
+
+    #[test]
+    fn test_complete_prefers_upstream_usage_and_runs_hooks() {
+        let context = RequestContext::default();
+        context.record_usage(&Usage {
+            total_tokens: 12,
+            ..Default::default()
+        });
+        let seen = Arc::new(Mutex::new(None));
+        let hook_seen = seen.clone();
+        context.on_complete(move |context| {
+            let total = context
+                .summary()
+                .and_then(|s| s.usage.as_ref())
+                .map(|u| u.total_tokens);
+            *hook_seen.lock().unwrap() = total;
+        });
+
+        context.complete(ResponseSummary {
+            usage: Some(Usage::default()),
+            ..Default::default()
+        });
+        assert_eq!(*seen.lock().unwrap(), Some(12));
+    }
+
+    #[test]
+    fn test_classify_model_call_by_route() {
+        let classify = |method, route, path| ModelCall::classify(&method, route, path);
+        // GET 聊天完成同样调用模型
+        assert_eq!(
+            classify(Method::GET, "/v1/chat/completions", "/v1/chat/completions"),
+            ModelCall::Generation
+        );
+        assert_eq!(
+            classify(
+                Method::POST,
+                "/v1beta/models/{model}",
+                "/v1beta/models/gemini-2.5-flash:generateContent"
+            ),
+            ModelCall::Generation
+        );
+        assert_eq!(
+            classify(
+                Method::POST,
+                "/v1/models/{model}",
+                "/v1/models/gemini-2.5-flash:countTokens"
+            ),
+            ModelCall::Auxiliary
+        );
+        assert_eq!(
+            classify(Method::POST, "/count_tokens", "/count_tokens"),
+            ModelCall::Auxiliary
+        );
+        assert_eq!(
+            classify(
+                Method::PATCH,
+                "/v1/cached_contents/{*name}",
+                "/v1/cached_contents/c1"
+            ),
+            ModelCall::Auxiliary
+        );
+        assert_eq!(
+            classify(Method::GET, "/v1/cached_contents", "/v1/cached_contents"),
+            ModelCall::None
+        );
+        assert_eq!(
+            classify(Method::GET, "/v1/models", "/v1/models"),
+            ModelCall::None
+        );
+        assert_eq!(
+            classify(Method::GET, "/v1/responses/{id}", "/v1/responses/r1"),
+            ModelCall::None
+        );
+    }
+
+    #[test]
+    fn test_usage_adds_up_concurrent_upstream_calls() {
+        // 两个提示的文本补全在同一个上下文上各调用一次上游
+        let context = RequestContext::default();
+        let usage = |prompt_tokens, completion_tokens| Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            ..Default::default()
+        };
+        let streamed = context.usage_slot();
+        context.record_usage(&usage(10, 20));
+        *streamed.lock().unwrap() = Some(usage(5, 1));
+        *streamed.lock().unwrap() = Some(usage(5, 7));
+
+        let total = context.usage().unwrap();
+        assert_eq!(total.prompt_tokens, 15);
+        assert_eq!(total.completion_tokens, 27);
+        assert_eq!(total.total_tokens, 42);
+        assert!(RequestContext::default().usage().is_none());
+    }
+
+    #[test]
+    fn test_billable_usage_estimates_only_generation() {
+        let usage = |total_tokens| Usage {
+            total_tokens,
+            ..Default::default()
+        };
+        let billable = |model_call, reported: Option<Usage>| {
+            let context = RequestContext::new(String::new(), model_call);
+            context.set_estimated_usage(usage(30));
+            context.complete(ResponseSummary {
+                usage: reported,
+                ..Default::default()
+            });
+            context.billable_usage().map(|u| u.total_tokens)
+        };
+
+        assert_eq!(billable(ModelCall::Generation, Some(usage(10))), Some(10));
+        // 流被取消或上游没有返回用量时按预估计费
+        assert_eq!(billable(ModelCall::Generation, None), Some(30));
+        // 计数 token 和上下文缓存只按响应中的用量计费
+        assert_eq!(billable(ModelCall::Auxiliary, None), None);
+        assert_eq!(billable(ModelCall::Auxiliary, Some(usage(8))), Some(8));
+    }
+
+    /// 收集测试中输出的日志
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Logs {
+        /// 以 JSON 格式收集当前线程的日志,直到返回的 guard 被丢弃
+        fn install() -> (Logs, tracing::subscriber::DefaultGuard) {
+            let logs = Logs::default();
+            let writer = logs.clone();
+            let subscriber = tracing_subscriber::fmt()
+                .json()
+                .with_writer(move || writer.clone())
+                .finish();
+            (logs, tracing::subscriber::set_default(subscriber))
+        }
+
+        /// 以 JSON 格式收集 `f` 执行期间的日志
+        fn capture(f: impl FnOnce()) -> Vec<serde_json::Value> {
+            let (logs, guard) = Logs::install();
+            f();
+            drop(guard);
+            logs.lines()
+        }
+
+        fn lines(&self) -> Vec<serde_json::Value> {
+            let bytes = self.0.lock().unwrap().clone();
+            String::from_utf8(bytes)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    /// 从默认注册表读取取消计数
+    fn cancelled_total(route: &str, phase: &str) -> f64 {
+        prometheus::gather()
+            .iter()
+            .filter(|family| family.name() == "vertex_oai_requests_cancelled_total")
+            .flat_map(|family| family.get_metric())
+            .filter(|metric| {
+                let label = |name| metric.get_label().iter().find(|l| l.name() == name);
+                label("route").is_some_and(|l| l.value() == route)
+                    && label("phase").is_some_and(|l| l.value() == phase)
+            })
+            .map(|metric| metric.get_counter().get_value())
+            .sum()
+    }
+
+    #[test]
+    fn test_cancel_before_headers_writes_access_log() {
+        let logs = Logs::capture(|| {
+            let context = Arc::new(RequestContext::new(
+                "req-cancelled".to_owned(),
+                ModelCall::Generation,
+            ));
+            context.set_model("gemini-2.5-flash");
+            let route = "/v1/chat/completions";
+            drop(CancelGuard::new(
+                route,
+                &Method::POST,
+                &context,
+                Instant::now(),
+            ));
+
+            // 正常返回的请求由响应体跟踪输出日志,守卫不输出
+            let mut guard = CancelGuard::new(route, &Method::POST, &context, Instant::now());
+            guard.disarm();
+        });
+
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0]["level"], "WARN");
+        let fields = &logs[1]["fields"];
+        assert_eq!(fields["message"], "Request completed");
+        assert_eq!(fields["request_id"], "req-cancelled");
+        assert_eq!(fields["status"], 499);
+        assert_eq!(fields["completed"], false);
+        assert_eq!(fields["model"], "gemini-2.5-flash");
+    }
+
+    #[test]
+    fn test_cancel_before_headers_counts_metric() {
+        let route = "/test/cancel-before-headers";
+        let context = Arc::new(RequestContext::default());
+        let before = cancelled_total(route, "request");
+        drop(CancelGuard::new(
+            route,
+            &Method::POST,
+            &context,
+            Instant::now(),
+        ));
+
+        assert_eq!(cancelled_total(route, "request"), before + 1.0);
+        assert_eq!(cancelled_total(route, "stream"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_mid_stream_counts_metric_and_writes_logs() {
+        use axum::body::{Body, Bytes};
+        use axum::routing::get;
+        use axum::{middleware, Extension, Router};
+        use std::time::Duration;
+
+        let route = "/test/cancel-mid-stream";
+        let (logs, _guard) = Logs::install();
+        let app = Router::new()
+            .route(
+                route,
+                get(
+                    |Extension(context): Extension<Arc<RequestContext>>| async move {
+                        context.set_model("gemini-2.5-flash");
+                        // 持续输出数据块,直到客户端断开后写入失败
+                        let body = futures_util::stream::unfold((), |()| async {
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                            Some((Ok::<_, std::io::Error>(Bytes::from("data: {}\n\n")), ()))
+                        });
+                        (
+                            [(CONTENT_TYPE, "text/event-stream")],
+                            Body::from_stream(body),
+                        )
+                    },
+                ),
+            )
+            .layer(middleware::from_fn(track_request));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut response = reqwest::get(format!("http://{addr}{route}")).await.unwrap();
+        assert!(response.chunk().await.unwrap().is_some());
+        drop(response);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while cancelled_total(route, "stream") == 0.0 {
+            assert!(Instant::now() < deadline, "stream cancel was not recorded");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(cancelled_total(route, "request"), 0.0);
+
+        let logs = logs.lines();
+        let warning = logs
+            .iter()
+            .find(|line| line["level"] == "WARN")
+            .expect("cancel warning");
+        assert!(warning["fields"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("phase: stream"));
+        let access = logs
+            .iter()
+            .find(|line| line["fields"]["message"] == "Request completed")
+            .expect("access log");
+        assert_eq!(access["fields"]["status"], 200);
+        assert_eq!(access["fields"]["completed"], false);
+        assert_eq!(access["fields"]["model"], "gemini-2.5-flash");
+    }
+}
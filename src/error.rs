@@ -0,0 +1,357 @@
+//! 网关统一错误类型
+//!
+//! 所有失败路径都以 OpenAI 格式返回:
+//! `{"error": {"message", "type", "param", "code"}}`
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Map, Value};
+use std::fmt;
+
+/// 网关错误
+#[derive(Debug, Clone)]
+pub struct GatewayError {
+    pub status: StatusCode,
+    pub message: String,
+    pub kind: &'static str,
+    pub param: Option<String>,
+    pub code: Option<String>,
+    /// Vertex AI 原始错误的 status 和 details
+    pub upstream: Option<Box<Map<String, Value>>>,
+    /// Vertex AI 返回的原始 HTTP 状态码,用于访问日志
+    pub upstream_status: Option<StatusCode>,
+}
+
+impl GatewayError {
+    pub fn new(status: StatusCode, kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            kind,
+            param: None,
+            code: None,
+            upstream: None,
+            upstream_status: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_param(mut self, param: impl Into<String>) -> Self {
+        self.param = Some(param.into());
+        self
+    }
+
+    /// 400 请求格式错误
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+    }
+
+    /// 401 客户端 API Key 无效
+    pub fn invalid_api_key(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "invalid_request_error", message)
+            .with_code("invalid_api_key")
+    }
+
+    /// 403 无权访问
+    pub fn permission_denied(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "permission_error", message)
+    }
+
+    /// 413 请求体过大
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        Self::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "invalid_request_error",
+            message,
+        )
+        .with_code("request_too_large")
+    }
+
+    /// 500 网关内部错误
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "server_error", message)
+    }
+
+    /// 502 上游请求失败
+    pub fn bad_gateway(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_GATEWAY, "upstream_error", message)
+    }
+
+    /// 504 上游超时
+    pub fn gateway_timeout(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::GATEWAY_TIMEOUT, "upstream_error", message).with_code("timeout")
+    }
+
+    /// 根据 reqwest 错误生成 502/504
+    pub fn from_reqwest(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::gateway_timeout(format!("Request to Vertex AI timed out: {error}"))
+        } else {
+            Self::bad_gateway(format!("Failed to reach Vertex AI: {error}"))
+        }
+    }
+
+    /// 将 Vertex AI 的 Google 风格错误转换为 OpenAI 格式
+    ///
+    /// Google 错误格式: `{"error": {"code", "message", "status", "details"}}`,
+    /// openapi 端点有时会包一层数组。原始的 `status` 和 `details` 保留在 `error.details` 中
+    pub fn from_vertex(status: StatusCode, body: &[u8]) -> Self {
+        let parsed: Option<Value> = serde_json::from_slice(body).ok();
+        let error = parsed.as_ref().and_then(|v| match v {
+            Value::Array(items) => items.first().and_then(|i| i.get("error")),
+            _ => v.get("error"),
+        });
+
+        let message = error
+            .and_then(|e| e.get("message"))
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+            .unwrap_or_else(|| {
+                let text = String::from_utf8_lossy(body);
+                if text.trim().is_empty() {
+                    format!("Vertex AI returned status {status}")
+                } else {
+                    text.into_owned()
+                }
+            });
+        let google_status = error.and_then(|e| e.get("status")).and_then(Value::as_str);
+
+        let mut upstream = Map::new();
+        if let Some(s) = google_status {
+            upstream.insert("status".to_owned(), Value::String(s.to_owned()));
+        }
+        if let Some(details) = error.and_then(|e| e.get("details")) {
+            upstream.insert("details".to_owned(), details.clone());
+        }
+
+        // 客户端错误和限流原样保留状态码,其余上游故障统一为 502/503/504;
+        // 上游 401/403 是网关自身的服务账号凭据问题,不是客户端 Key 无效,按 502 返回
+        let upstream_status = status;
+        let (status, kind) = match status.as_u16() {
+            400 | 404 | 413 => (status, "invalid_request_error"),
+            401 | 403 => (StatusCode::BAD_GATEWAY, "upstream_error"),
+            429 => (status, "rate_limit_error"),
+            503 | 504 => (status, "upstream_error"),
+            _ if status.is_client_error() => (status, "invalid_request_error"),
+            _ => (StatusCode::BAD_GATEWAY, "upstream_error"),
+        };
+
+        Self {
+            status,
+            message,
+            kind,
+            param: None,
+            code: google_status.map(|s| s.to_ascii_lowercase()),
+            upstream: (!upstream.is_empty()).then(|| Box::new(upstream)),
+            upstream_status: Some(upstream_status),
+        }
+    }
+
+    /// 读取 Vertex AI 的错误响应并转换
+    pub async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let body = response.bytes().await.unwrap_or_default();
+        tracing::error!(
+            "Vertex AI returned error status {}: {}",
+            status,
+            String::from_utf8_lossy(&body)
+        );
+        Self::from_vertex(status, &body)
+    }
+
+    /// 是否为配额错误(429 或 RESOURCE_EXHAUSTED)
+    pub fn is_quota_error(&self) -> bool {
+        self.status == StatusCode::TOO_MANY_REQUESTS
+            || self.code.as_deref() == Some("resource_exhausted")
+    }
+
+    /// OpenAI 格式的错误体
+    pub fn body(&self) -> Value {
+        let mut error = json!({
+            "message": self.message,
+            "type": self.kind,
+            "param": self.param,
+            "code": self.code,
+        });
+        if let Some(upstream) = &self.upstream {
+            error["details"] = Value::Object(upstream.as_ref().clone());
+        }
+        json!({ "error": error })
+    }
+}
+
+impl fmt::Display for GatewayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.kind, self.message)
+    }
+}
+
+impl std::error::Error for GatewayError {}
+
+impl IntoResponse for GatewayError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
+
+/// Anthropic 格式的错误响应,用于 `/v1/messages` 接口
+///
+/// `{"type": "error", "error": {"type": "...", "message": "..."}}`
+#[derive(Debug)]
+pub struct AnthropicError(pub GatewayError);
+
+impl AnthropicError {
+    /// 按状态码映射 Anthropic 错误类型
+    fn kind(&self) -> &'static str {
+        match self.0.status.as_u16() {
+            400 | 422 => "invalid_request_error",
+            401 => "authentication_error",
+            403 => "permission_error",
+            404 => "not_found_error",
+            413 => "request_too_large",
+            429 => "rate_limit_error",
+            503 | 529 => "overloaded_error",
+            _ => "api_error",
+        }
+    }
+
+    pub fn body(&self) -> Value {
+        json!({
+            "type": "error",
+            "error": {
+                "type": self.kind(),
+                "message": self.0.message,
+            }
+        })
+    }
+}
+
+impl From<GatewayError> for AnthropicError {
+    fn from(error: GatewayError) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for AnthropicError {
+    fn into_response(self) -> Response {
+        (self.0.status, Json(self.body())).into_response()
+    }
+}
+
+/// Google 格式的错误响应,用于 Gemini API 兼容接口
+///
+/// `{"error": {"code": 400, "message": "...", "status": "INVALID_ARGUMENT", "details": [...]}}`
+#[derive(Debug)]
+pub struct GoogleError(pub GatewayError);
+
+impl GoogleError {
+    /// 上游返回的 Google 状态,没有时按状态码映射
+    fn status(&self) -> &str {
+        if let Some(Value::String(status)) = self.0.upstream.as_ref().and_then(|u| u.get("status"))
+        {
+            return status;
+        }
+        match self.0.status.as_u16() {
+            400 | 413 | 422 => "INVALID_ARGUMENT",
+            401 => "UNAUTHENTICATED",
+            403 => "PERMISSION_DENIED",
+            404 => "NOT_FOUND",
+            429 => "RESOURCE_EXHAUSTED",
+            501 => "UNIMPLEMENTED",
+            503 => "UNAVAILABLE",
+            504 => "DEADLINE_EXCEEDED",
+            _ => "INTERNAL",
+        }
+    }
+
+    pub fn body(&self) -> Value {
+        let mut error = json!({
+            "code": self.0.status.as_u16(),
+            "message": self.0.message,
+            "status": self.status(),
+        });
+        if let Some(details) = self.0.upstream.as_ref().and_then(|u| u.get("details")) {
+            error["details"] = details.clone();
+        }
+        json!({ "error": error })
+    }
+}
+
+impl From<GatewayError> for GoogleError {
+    fn from(error: GatewayError) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for GoogleError {
+    fn into_response(self) -> Response {
+        (self.0.status, Json(self.body())).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_vertex_rewrites_google_envelope() {
+        let body = br#"[{"error": {"code": 429, "message": "Quota exceeded",
+            "status": "RESOURCE_EXHAUSTED", "details": [{"reason": "RATE_LIMIT_EXCEEDED"}]}}]"#;
+        let error = GatewayError::from_vertex(StatusCode::TOO_MANY_REQUESTS, body);
+
+        assert_eq!(error.status, StatusCode::TOO_MANY_REQUESTS);
+        let body = error.body();
+        assert_eq!(body["error"]["message"], "Quota exceeded");
+        assert_eq!(body["error"]["type"], "rate_limit_error");
+        assert_eq!(body["error"]["code"], "resource_exhausted");
+        assert_eq!(body["error"]["details"]["status"], "RESOURCE_EXHAUSTED");
+        assert_eq!(
+            body["error"]["details"]["details"][0]["reason"],
+            "RATE_LIMIT_EXCEEDED"
+        );
+    }
+
+    #[test]
+    fn test_google_error_keeps_upstream_status() {
+        let body = br#"{"error": {"code": 429, "message": "Quota exceeded",
+            "status": "RESOURCE_EXHAUSTED", "details": [{"reason": "RATE_LIMIT_EXCEEDED"}]}}"#;
+        let error = GoogleError(GatewayError::from_vertex(
+            StatusCode::TOO_MANY_REQUESTS,
+            body,
+        ));
+        let body = error.body();
+        assert_eq!(body["error"]["code"], 429);
+        assert_eq!(body["error"]["status"], "RESOURCE_EXHAUSTED");
+        assert_eq!(body["error"]["details"][0]["reason"], "RATE_LIMIT_EXCEEDED");
+
+        let error = GoogleError(GatewayError::bad_request("bad"));
+        assert_eq!(error.body()["error"]["status"], "INVALID_ARGUMENT");
+    }
+
+    #[test]
+    fn test_from_vertex_maps_server_errors_to_bad_gateway() {
+        let error = GatewayError::from_vertex(StatusCode::INTERNAL_SERVER_ERROR, b"oops");
+        assert_eq!(error.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(error.message, "oops");
+        assert!(error.code.is_none());
+    }
+
+    #[test]
+    fn test_from_vertex_maps_credential_errors_to_bad_gateway() {
+        let body = br#"{"error": {"code": 403, "message": "Permission denied on resource",
+            "status": "PERMISSION_DENIED"}}"#;
+        let error = GatewayError::from_vertex(StatusCode::FORBIDDEN, body);
+        assert_eq!(error.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(error.upstream_status, Some(StatusCode::FORBIDDEN));
+        let body = error.body();
+        assert_eq!(body["error"]["type"], "upstream_error");
+        assert_eq!(body["error"]["details"]["status"], "PERMISSION_DENIED");
+    }
+}
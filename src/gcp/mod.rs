@@ -1,3 +1,5 @@
+pub mod pool;
 pub mod token;
 
+pub use pool::{ProjectLease, ProjectPool};
 pub use token::TokenManager;
@@ -0,0 +1,306 @@
+//! GCP 项目池
+//!
+//! 将请求分摊到多个项目以合并 Vertex AI 配额。支持按权重随机、最少进行中请求和轮询三种策略,
+//! 连续返回配额错误的项目会被暂时摘除,摘除期满后自动恢复
+
+use super::TokenManager;
+use crate::config::{BalanceStrategy, GcpConfig};
+use crate::metrics;
+use futures_util::{Stream, StreamExt};
+use reqwest::header::HeaderValue;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// 项目池中的单个项目
+struct Project {
+    id: String,
+    weight: u32,
+    token_manager: TokenManager,
+    in_flight: AtomicUsize,
+    /// 连续配额错误次数
+    failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+impl Project {
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.ejected_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| until > now)
+    }
+}
+
+/// GCP 项目池
+pub struct ProjectPool {
+    projects: Vec<Arc<Project>>,
+    strategy: BalanceStrategy,
+    cursor: AtomicUsize,
+    eject_after_failures: u32,
+    eject_duration: Duration,
+}
+
+impl ProjectPool {
+    /// 根据配置创建项目池
+    ///
+    /// 未配置 `gcp.projects` 时只包含 `gcp.project_id`。`pinned` 中不在池内的项目
+    /// (路由规则显式指定的项目)以权重 0 加入,只在被路由指定时使用。
+    /// 未指定凭据文件的项目共享同一个 ADC 令牌管理器
+    pub async fn new<'a>(
+        config: &GcpConfig,
+        pinned: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let adc = TokenManager::new().await?;
+
+        let mut projects: Vec<Arc<Project>> = Vec::new();
+        let mut add = |id: &str, weight: u32, token_manager: TokenManager| {
+            if !projects.iter().any(|p| p.id == id) {
+                projects.push(Arc::new(Project {
+                    id: id.to_owned(),
+                    weight,
+                    token_manager,
+                    in_flight: AtomicUsize::new(0),
+                    failures: AtomicU32::new(0),
+                    ejected_until: Mutex::new(None),
+                }));
+            }
+        };
+
+        if config.projects.is_empty() {
+            add(&config.project_id, 1, adc.clone());
+        }
+        for project in &config.projects {
+            let token_manager = match &project.credentials_file {
+                Some(path) => TokenManager::from_file(path).await?,
+                None => adc.clone(),
+            };
+            add(&project.project_id, project.weight, token_manager);
+        }
+        // 默认项目不在池中时也需要可用
+        add(&config.project_id, 0, adc.clone());
+        for id in pinned {
+            add(id, 0, adc.clone());
+        }
+
+        Ok(Self {
+            projects,
+            strategy: config.balance,
+            cursor: AtomicUsize::new(0),
+            eject_after_failures: config.eject_after_failures,
+            eject_duration: config.eject_duration(),
+        })
+    }
+
+    /// 参与负载均衡的项目 ID 及权重
+    pub fn members(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.projects
+            .iter()
+            .filter(|p| p.weight > 0)
+            .map(|p| (p.id.as_str(), p.weight))
+    }
+
+    /// 由给定项目组成的项目池,项目不携带凭据,仅用于测试
+    #[cfg(test)]
+    pub fn with_projects(ids: &[&str]) -> Self {
+        let token_manager = TokenManager::anonymous();
+        ProjectPool {
+            projects: ids
+                .iter()
+                .map(|id| {
+                    Arc::new(Project {
+                        id: (*id).to_owned(),
+                        weight: 1,
+                        token_manager: token_manager.clone(),
+                        in_flight: AtomicUsize::new(0),
+                        failures: AtomicU32::new(0),
+                        ejected_until: Mutex::new(None),
+                    })
+                })
+                .collect(),
+            strategy: BalanceStrategy::RoundRobin,
+            cursor: AtomicUsize::new(0),
+            eject_after_failures: 2,
+            eject_duration: Duration::from_secs(60),
+        }
+    }
+
+    /// 为一个请求选择项目
+    ///
+    /// `pinned` 为路由显式指定的项目,此时不做负载均衡
+    pub fn acquire(&self, pinned: Option<&str>) -> ProjectLease {
+        let project = pinned
+            .and_then(|id| self.projects.iter().find(|p| p.id == id))
+            .unwrap_or_else(|| self.select())
+            .clone();
+        project.in_flight.fetch_add(1, Ordering::Relaxed);
+        metrics::record_project_acquired(&project.id);
+        ProjectLease {
+            project,
+            eject_after_failures: self.eject_after_failures,
+            eject_duration: self.eject_duration,
+        }
+    }
+
+    /// 按策略从未被摘除的项目中选择,全部被摘除时忽略摘除状态
+    fn select(&self) -> &Arc<Project> {
+        let now = Instant::now();
+        let members: Vec<&Arc<Project>> = self.projects.iter().filter(|p| p.weight > 0).collect();
+        let healthy: Vec<&Arc<Project>> = members
+            .iter()
+            .copied()
+            .filter(|p| !p.is_ejected(now))
+            .collect();
+        let candidates = if healthy.is_empty() { members } else { healthy };
+
+        match self.strategy {
+            BalanceStrategy::Weighted => {
+                let total: u32 = candidates.iter().map(|p| p.weight).sum();
+                let mut point = fastrand::u32(0..total);
+                for project in &candidates {
+                    if point < project.weight {
+                        return project;
+                    }
+                    point -= project.weight;
+                }
+                candidates[candidates.len() - 1]
+            }
+            BalanceStrategy::LeastInFlight => {
+                // 从轮转的起点开始比较,进行中请求数相同时避免总是选中第一个
+                let offset = self.cursor.fetch_add(1, Ordering::Relaxed);
+                (0..candidates.len())
+                    .map(|i| candidates[(offset + i) % candidates.len()])
+                    .min_by_key(|p| p.in_flight.load(Ordering::Relaxed))
+                    .unwrap()
+            }
+            BalanceStrategy::RoundRobin => {
+                let index = self.cursor.fetch_add(1, Ordering::Relaxed);
+                candidates[index % candidates.len()]
+            }
+        }
+    }
+}
+
+/// 一次请求占用的项目,释放时减少进行中请求数
+pub struct ProjectLease {
+    project: Arc<Project>,
+    eject_after_failures: u32,
+    eject_duration: Duration,
+}
+
+impl ProjectLease {
+    pub fn project_id(&self) -> &str {
+        &self.project.id
+    }
+
+    /// 该项目的 GCP 认证头
+    pub async fn authorization(&self) -> Result<HeaderValue, Box<dyn std::error::Error>> {
+        self.project.token_manager.authorization().await
+    }
+
+    /// 上游请求成功,清零连续配额错误计数
+    pub fn record_success(&self) {
+        metrics::record_project_result(&self.project.id, "success");
+        self.project.failures.store(0, Ordering::Relaxed);
+    }
+
+    /// 上游请求因其他原因失败,不影响项目健康状态
+    pub fn record_error(&self) {
+        metrics::record_project_result(&self.project.id, "error");
+    }
+
+    /// 上游返回配额错误,连续次数达到阈值时摘除项目
+    pub fn record_quota_error(&self) {
+        metrics::record_project_result(&self.project.id, "quota_error");
+        let failures = self.project.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < self.eject_after_failures {
+            return;
+        }
+        self.project.failures.store(0, Ordering::Relaxed);
+        *self.project.ejected_until.lock().unwrap() = Some(Instant::now() + self.eject_duration);
+        metrics::record_project_ejected(&self.project.id);
+        tracing::warn!(
+            "Project {} returned {} consecutive quota errors, ejecting for {:?}",
+            self.project.id,
+            failures,
+            self.eject_duration
+        );
+    }
+
+    /// 将占用延长到响应体结束,用于流式响应
+    pub fn hold<S: Stream>(self, stream: S) -> impl Stream<Item = S::Item> {
+        stream.map(move |item| {
+            let _ = &self;
+            item
+        })
+    }
+}
+
+impl Drop for ProjectLease {
+    fn drop(&mut self) {
+        self.project.in_flight.fetch_sub(1, Ordering::Relaxed);
+        metrics::record_project_released(&self.project.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(strategy: BalanceStrategy, weights: &[u32]) -> ProjectPool {
+        let token_manager = TokenManager::anonymous();
+        ProjectPool {
+            projects: weights
+                .iter()
+                .enumerate()
+                .map(|(i, &weight)| {
+                    Arc::new(Project {
+                        id: format!("p{i}"),
+                        weight,
+                        token_manager: token_manager.clone(),
+                        in_flight: AtomicUsize::new(0),
+                        failures: AtomicU32::new(0),
+                        ejected_until: Mutex::new(None),
+                    })
+                })
+                .collect(),
+            strategy,
+            cursor: AtomicUsize::new(0),
+            eject_after_failures: 2,
+            eject_duration: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_round_robin_skips_zero_weight() {
+        let pool = pool(BalanceStrategy::RoundRobin, &[1, 0, 1]);
+        let ids: Vec<String> = (0..4)
+            .map(|_| pool.acquire(None).project_id().to_owned())
+            .collect();
+        assert_eq!(ids, ["p0", "p2", "p0", "p2"]);
+        assert_eq!(pool.acquire(Some("p1")).project_id(), "p1");
+    }
+
+    #[test]
+    fn test_least_in_flight() {
+        let pool = pool(BalanceStrategy::LeastInFlight, &[1, 1]);
+        let first = pool.acquire(None);
+        let second = pool.acquire(None);
+        assert_ne!(first.project_id(), second.project_id());
+        drop(second);
+        let third = pool.acquire(None);
+        assert_ne!(first.project_id(), third.project_id());
+    }
+
+    #[test]
+    fn test_quota_errors_eject_project() {
+        let pool = pool(BalanceStrategy::RoundRobin, &[1, 1]);
+        let lease = pool.acquire(Some("p0"));
+        lease.record_quota_error();
+        lease.record_quota_error();
+        drop(lease);
+        for _ in 0..4 {
+            assert_eq!(pool.acquire(None).project_id(), "p1");
+        }
+    }
+}
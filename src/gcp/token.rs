@@ -1,8 +1,12 @@
 use axum::http::Extensions;
-use google_cloud_auth::credentials::{CacheableResource, Credentials};
+use google_cloud_auth::credentials::{
+    service_account, user_account, CacheableResource, Credentials,
+};
 use reqwest::header::HeaderValue;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::Instrument;
 
 /// GCP 令牌管理器
 ///
@@ -34,7 +38,61 @@ impl TokenManager {
         })
     }
 
+    /// 从凭据 JSON 文件创建令牌管理器
+    ///
+    /// 支持服务账号密钥(`service_account`)和用户凭据(`authorized_user`)
+    pub async fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)?;
+        let json: serde_json::Value = serde_json::from_str(&content)?;
+        let credentials = match json.get("type").and_then(|t| t.as_str()) {
+            Some("service_account") => service_account::Builder::new(json).build()?,
+            Some("authorized_user") => user_account::Builder::new(json).build()?,
+            other => {
+                return Err(format!(
+                    "不支持的凭据类型 {:?}: {}",
+                    other.unwrap_or(""),
+                    path.display()
+                )
+                .into())
+            }
+        };
+        Ok(Self {
+            credentials: Arc::new(RwLock::new(credentials)),
+            auth: Arc::new(RwLock::new(HeaderValue::from_static(""))),
+        })
+    }
+
+    /// 不携带凭据的令牌管理器,仅用于测试
+    #[cfg(test)]
+    pub fn anonymous() -> Self {
+        Self {
+            credentials: Arc::new(RwLock::new(
+                google_cloud_auth::credentials::anonymous::Builder::new().build(),
+            )),
+            auth: Arc::new(RwLock::new(HeaderValue::from_static(""))),
+        }
+    }
+
     pub async fn authorization(&self) -> Result<HeaderValue, Box<dyn std::error::Error>> {
+        let span = if crate::telemetry::enabled() {
+            tracing::info_span!(
+                target: crate::telemetry::TARGET,
+                "credentials",
+                otel.name = "gcp.credentials",
+                gcp.token.refreshed = false,
+                otel.status_code = tracing::field::Empty,
+            )
+        } else {
+            tracing::Span::none()
+        };
+        let result = self.fetch_authorization().instrument(span.clone()).await;
+        if result.is_err() {
+            span.record("otel.status_code", "ERROR");
+        }
+        result
+    }
+
+    async fn fetch_authorization(&self) -> Result<HeaderValue, Box<dyn std::error::Error>> {
         match self
             .credentials
             .read()
@@ -51,6 +109,8 @@ impl TokenManager {
                     .map(|v| v.to_str().unwrap_or("").to_owned())
                     .unwrap_or(String::new());
                 let hv = HeaderValue::from_str(value.as_str())?;
+                crate::metrics::record_token_refresh();
+                tracing::Span::current().record("gcp.token.refreshed", true);
                 *self.auth.write().await = hv.clone();
                 Ok(hv)
             }
@@ -0,0 +1,107 @@
+use crate::auth::ApiKey;
+use crate::billing::{ledger::totals_by_key, UsageFilter, UsageRow, UsageTotals};
+use crate::error::GatewayError;
+use crate::state::AppState;
+use axum::{
+    extract::{Extension, Query, State},
+    Json,
+};
+use chrono::{Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// `/admin/usage` 查询参数,日期为 UTC 的 `YYYY-MM-DD`,范围为闭区间
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// 默认为当月第一天
+    pub start: Option<NaiveDate>,
+    /// 默认为今天
+    pub end: Option<NaiveDate>,
+    /// 只统计指定名称的 Key
+    pub key: Option<String>,
+    /// 只统计指定模型
+    pub model: Option<String>,
+}
+
+/// 用量报告
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub object: &'static str,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    /// 按 Key、模型和日期汇总
+    pub data: Vec<UsageRow>,
+    /// 按 Key 汇总
+    pub keys: BTreeMap<String, KeyUsage>,
+}
+
+/// 单个 Key 在查询范围内的用量,以及当月费用和预算
+#[derive(Debug, Serialize)]
+pub struct KeyUsage {
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+    pub month_cost: f64,
+    pub monthly_budget: Option<f64>,
+}
+
+/// 用量报告接口 - GET
+///
+/// 需要 `admin: true` 的 API Key(未启用认证时不校验),
+/// 返回指定日期范围内按 Key、模型和日期汇总的 token 用量和费用
+pub async fn usage(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageReport>, GatewayError> {
+    if state.key_store.is_some() && !api_key.as_ref().is_some_and(|k| k.admin) {
+        return Err(GatewayError::permission_denied(
+            "This endpoint requires an admin API key.",
+        ));
+    }
+
+    let now = Utc::now();
+    let today = now.date_naive();
+    let start = query
+        .start
+        .unwrap_or_else(|| today.with_day(1).unwrap_or(today));
+    let end = query.end.unwrap_or(today);
+    if start > end {
+        return Err(
+            GatewayError::bad_request("'start' must not be after 'end'").with_param("start")
+        );
+    }
+
+    let ledger = &state.billing.ledger;
+    let data = ledger.query(&UsageFilter {
+        start: Some(start),
+        end: Some(end),
+        key: query.key,
+        model: query.model,
+    });
+    let keys = totals_by_key(&data)
+        .into_iter()
+        .map(|(name, totals)| {
+            let monthly_budget = state.key_store.as_ref().and_then(|store| {
+                store
+                    .iter()
+                    .find(|k| k.name == name)
+                    .and_then(|k| k.monthly_budget)
+            });
+            let usage = KeyUsage {
+                totals,
+                month_cost: ledger.month_cost(&name, now),
+                monthly_budget,
+            };
+            (name, usage)
+        })
+        .collect();
+
+    Ok(Json(UsageReport {
+        object: "usage_report",
+        start,
+        end,
+        data,
+        keys,
+    }))
+}
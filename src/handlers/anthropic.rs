@@ -0,0 +1,117 @@
+use super::{observe_project, vertex_base_url, CONTENT_TYPE_JSON, HEADER_USER_PROJECT};
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::gcp::ProjectLease;
+use crate::metrics::{self, BodyObserver};
+use crate::models::anthropic::MessagesResponse;
+use crate::models::ChatCompletionRequest;
+use crate::retry;
+use crate::routing::ResolvedRoute;
+use crate::sse::translate_stream;
+use crate::state::AppState;
+use crate::timeout::guard_response;
+use crate::translate::anthropic::{
+    to_chat_completion, to_messages_request, AnthropicStreamTranslator,
+};
+use crate::translate::model_id;
+use crate::usage::ReportUsage;
+use axum::{
+    body::Body,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::header::{HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
+use std::time::Instant;
+
+/// Claude 模型聊天完成
+///
+/// 将 OpenAI 请求翻译为 Anthropic Messages 格式,调用 Vertex AI 的
+/// `rawPredict` / `streamRawPredict`,并将响应翻译回 OpenAI 格式
+pub(super) async fn chat_completions(
+    state: &AppState,
+    context: &RequestContext,
+    auth_header: HeaderValue,
+    route: &ResolvedRoute,
+    lease: ProjectLease,
+    body: &str,
+) -> Result<Response, GatewayError> {
+    let request: ChatCompletionRequest = serde_json::from_str(body).map_err(|e| {
+        tracing::error!("Failed to deserialize chat completion request: {}", e);
+        GatewayError::bad_request(format!("Invalid chat completion request: {e}"))
+    })?;
+    let messages_request = to_messages_request(&request).map_err(|e| {
+        tracing::error!("Failed to translate request to Anthropic format: {}", e);
+        GatewayError::bad_request(e)
+    })?;
+
+    // 1. 构建 Vertex AI URL
+    let project_id = &route.project_id;
+    let model_id = model_id(&request.model)?;
+    let stream = request.is_stream();
+    let method = if stream {
+        "streamRawPredict"
+    } else {
+        "rawPredict"
+    };
+    let build = |location: &str| {
+        let url = format!(
+            "{}/v1/projects/{project_id}/locations/{location}/publishers/anthropic/models/{model_id}:{method}",
+            vertex_base_url(location)
+        );
+        tracing::debug!("Forwarding Claude request to: {}", url);
+        state
+            .http_client
+            .post(url)
+            .header(AUTHORIZATION, auth_header.clone())
+            .header(HEADER_USER_PROJECT.clone(), project_id.as_str())
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone())
+            .json(&messages_request)
+    };
+
+    // 2. 发送请求,错误响应转换为 OpenAI 格式
+    let start = Instant::now();
+    let result = retry::send_with_retry(
+        &state.config.retry,
+        context.model_label(),
+        &route.locations(),
+        route.timeouts,
+        stream,
+        build,
+    )
+    .await;
+    observe_project(&lease, &result);
+    context.record_upstream(&result);
+    let (response, _) = result?;
+    metrics::record_upstream_latency(context.model_label(), start.elapsed());
+
+    // 3. 流式响应逐个事件翻译,上游用量无论客户端是否请求 usage 都记入请求上下文
+    if stream {
+        let translator = ReportUsage::new(
+            AnthropicStreamTranslator::new(&request.model, request.include_usage()),
+            context.usage_slot(),
+        );
+        let observer = BodyObserver::new(context.model_label(), start, true);
+        let body = Body::from_stream(lease.hold(metrics::instrument_body(
+            translate_stream(guard_response(response, route.timeouts), translator),
+            observer,
+        )));
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap());
+    }
+
+    // 4. 非流式响应整体翻译
+    let messages_response: MessagesResponse = response.json().await.map_err(|e| {
+        tracing::error!("Failed to parse Claude response: {}", e);
+        GatewayError::bad_gateway(format!("Invalid response from Vertex AI: {e}"))
+    })?;
+    let completion = to_chat_completion(messages_response, &request.model);
+    if let Some(usage) = &completion.usage {
+        context.record_usage(usage);
+    }
+    Ok(Json(completion).into_response())
+}
@@ -0,0 +1,200 @@
+use super::embeddings::embed_text;
+use super::forward_chat;
+use crate::auth::{check_model, ApiKey};
+use crate::billing::ANONYMOUS;
+use crate::cache::{self, replay, semantic::SemanticQuery, CacheDirectives};
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::metrics;
+use crate::routing::ResolvedRoute;
+use crate::state::AppState;
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::header::{CACHE_CONTROL, CONTENT_TYPE};
+use serde_json::{Map, Value};
+
+/// 语义缓存命中时返回的相似度响应头
+static HEADER_X_CACHE_SIMILARITY: HeaderName = HeaderName::from_static("x-cache-similarity");
+
+/// 带响应缓存的聊天完成
+///
+/// 可缓存的请求先查精确匹配缓存,再查语义缓存,命中时直接返回(流式请求重放为 SSE);
+/// 未命中时转发请求,并缓存成功的非流式响应。
+/// 客户端 `Cache-Control: no-cache` 跳过查询,`no-store` 同时跳过写入
+pub(super) async fn forward_cached(
+    state: &AppState,
+    context: &RequestContext,
+    api_key: Option<&ApiKey>,
+    headers: &HeaderMap,
+    body: String,
+) -> Result<Response, GatewayError> {
+    // 无法解析的请求体交给 forward_chat 报错
+    let Ok(request) = serde_json::from_str::<Map<String, Value>>(&body) else {
+        return forward_chat(state, context, api_key, headers, body).await;
+    };
+    let model = request
+        .get("model")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_owned();
+    let stream = request
+        .get("stream")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let directives = CacheDirectives::from_headers(headers);
+    let route = state.routes.resolve(&model);
+
+    let exact = match (&state.chat_cache, directives.no_store) {
+        (Some(cache), false) => cache.key(&request, &route).map(|key| (cache, key)),
+        _ => None,
+    };
+    let semantic = match (&state.semantic_cache, directives.no_store) {
+        (Some(cache), false) => {
+            let key = api_key.map_or(ANONYMOUS, |k| k.name.as_str());
+            cache
+                .query(key, &model, &request)
+                .map(|query| (cache, query))
+        }
+        _ => None,
+    };
+
+    // 缓存指标在模型通过校验后再记录,标签取自请求上下文
+    if exact.is_none() && semantic.is_none() {
+        let result = forward_chat(state, context, api_key, headers, body).await;
+        metrics::record_chat_cache(context.model_label(), "bypass");
+        let mut response = result?;
+        cache::mark(&mut response, false);
+        return Ok(response);
+    }
+    // 未授权的模型不查缓存,也不计算嵌入
+    context.set_model(&model);
+    check_model(api_key, &model)?;
+
+    if let (Some((cache, key)), false) = (&exact, directives.no_cache) {
+        if let Some(entry) = cache.get(key).await {
+            tracing::debug!("Chat completion cache hit for model {}", model);
+            let response = hit(context, &route, &request, &entry.response, None);
+            metrics::record_chat_cache(context.model_label(), "hit");
+            return Ok(response);
+        }
+    }
+
+    // 语义缓存: 查询失败不影响请求本身
+    let mut vector = None;
+    if let Some((cache, query)) = &semantic {
+        match embed_text(state, cache.embedding_model(), &query.question).await {
+            Ok(embedding) => {
+                if !directives.no_cache {
+                    if let Some(found) = cache.lookup(&query.scope, &embedding) {
+                        tracing::debug!(
+                            "Semantic cache hit for model {} (similarity {:.4})",
+                            model,
+                            found.similarity
+                        );
+                        let response = hit(
+                            context,
+                            &route,
+                            &request,
+                            &found.response,
+                            Some(found.similarity),
+                        );
+                        metrics::record_chat_cache(context.model_label(), "semantic_hit");
+                        return Ok(response);
+                    }
+                }
+                vector = Some(embedding);
+            }
+            Err(e) => tracing::warn!("Semantic cache lookup skipped: {}", e),
+        }
+    }
+
+    let result = forward_chat(state, context, api_key, headers, body).await;
+    metrics::record_chat_cache(
+        context.model_label(),
+        if directives.no_cache {
+            "bypass"
+        } else {
+            "miss"
+        },
+    );
+    let response = result?;
+    let semantic = semantic
+        .zip(vector)
+        .map(|((_, query), vector)| (query, vector));
+    let mut response = store(state, exact.map(|(_, key)| key), semantic, response, stream).await?;
+    cache::mark(&mut response, false);
+    Ok(response)
+}
+
+/// 由缓存的响应构造返回给客户端的响应,流式请求重放为 SSE
+fn hit(
+    context: &RequestContext,
+    route: &ResolvedRoute,
+    request: &Map<String, Value>,
+    cached: &Value,
+    similarity: Option<f32>,
+) -> Response {
+    context.set_route(route);
+    let stream = request
+        .get("stream")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let mut response = if stream {
+        let include_usage = request
+            .get("stream_options")
+            .and_then(|o| o.get("include_usage"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(Body::from(replay::to_sse(cached, include_usage)))
+            .unwrap()
+    } else {
+        Json(cached.clone()).into_response()
+    };
+    cache::mark(&mut response, true);
+    if let Some(similarity) = similarity {
+        if let Ok(value) = HeaderValue::from_str(&format!("{similarity:.4}")) {
+            response
+                .headers_mut()
+                .insert(HEADER_X_CACHE_SIMILARITY.clone(), value);
+        }
+    }
+    response
+}
+
+/// 读取成功的非流式响应体并写入各层缓存,返回内容相同的响应
+async fn store(
+    state: &AppState,
+    key: Option<String>,
+    semantic: Option<(SemanticQuery, Vec<f32>)>,
+    response: Response,
+    stream: bool,
+) -> Result<Response, GatewayError> {
+    if stream || !response.status().is_success() || (key.is_none() && semantic.is_none()) {
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.map_err(|e| {
+        tracing::error!("Failed to read chat completion response: {}", e);
+        GatewayError::bad_gateway(format!("Failed to read response from Vertex AI: {e}"))
+    })?;
+    match serde_json::from_slice::<Value>(&bytes) {
+        Ok(value) if value.get("choices").is_some_and(Value::is_array) => {
+            if let (Some(cache), Some((query, vector))) = (&state.semantic_cache, semantic) {
+                cache.insert(query.scope, &vector, value.clone());
+            }
+            if let (Some(cache), Some(key)) = (&state.chat_cache, key) {
+                cache.insert(key, value).await;
+            }
+        }
+        _ => tracing::warn!("Not caching chat completion response without choices"),
+    }
+    Ok(Response::from_parts(parts, Body::from(bytes)))
+}
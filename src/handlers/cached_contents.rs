@@ -0,0 +1,559 @@
+use super::{
+    authorization, lease_route, observe_project, resolve_route, vertex_base_url, CONTENT_TYPE_JSON,
+    HEADER_USER_PROJECT,
+};
+use crate::auth::{check_model, ApiKey};
+use crate::config::Backend;
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::gcp::ProjectLease;
+use crate::models::cached_contents::{
+    CachedContentList, CachedContentObject, CachedContentUsage, CreateCachedContentRequest,
+    UpdateCachedContentRequest, VertexCachedContent, VertexCachedContentList,
+};
+use crate::models::gemini::GenerateContentRequest;
+use crate::models::ChatCompletionRequest;
+use crate::retry;
+use crate::routing::ResolvedRoute;
+use crate::state::AppState;
+use crate::translate::gemini::to_generate_content;
+use crate::translate::model_id;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::sync::Arc;
+
+/// 列表查询参数
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    /// 按该模型的路由确定项目和区域,默认使用默认路由
+    pub model: Option<String>,
+    pub page_size: Option<u32>,
+    pub page_token: Option<String>,
+}
+
+/// 单个缓存的查询参数
+#[derive(Debug, Deserialize)]
+pub struct NameQuery {
+    /// 短 ID 按该模型的路由补全为完整资源名
+    pub model: Option<String>,
+}
+
+/// 上下文缓存资源名 `projects/{project}/locations/{location}/cachedContents/{id}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CachedContentName {
+    project: String,
+    location: String,
+    id: String,
+}
+
+impl CachedContentName {
+    /// 解析完整资源名或短 ID,短 ID 使用路由配置的项目和主区域
+    ///
+    /// 区域会拼进上游主机名,各段只允许字母、数字、`-` 和 `_`,
+    /// 项目 ID 另外允许域名范围项目中的 `.` 和 `:`
+    fn parse(name: &str, route: &ResolvedRoute) -> Result<Self, GatewayError> {
+        let segments: Vec<&str> = name.trim_matches('/').split('/').collect();
+        let (project, location, id) = match segments.as_slice() {
+            [id] => (route.project_id.as_str(), route.location.as_str(), *id),
+            ["projects", project, "locations", location, "cachedContents", id] => {
+                (*project, *location, *id)
+            }
+            _ => ("", "", ""),
+        };
+        let valid = |segment: &str, extra: &[char]| {
+            !segment.is_empty()
+                && segment.chars().all(|c| {
+                    c.is_ascii_alphanumeric() || c == '-' || c == '_' || extra.contains(&c)
+                })
+        };
+        if !valid(project, &['.', ':']) || !valid(location, &[]) || !valid(id, &[]) {
+            return Err(GatewayError::bad_request(format!(
+                "Invalid cached content name: '{name}'"
+            ))
+            .with_param("cached_content"));
+        }
+        Ok(Self {
+            project: project.to_owned(),
+            location: location.to_owned(),
+            id: id.to_owned(),
+        })
+    }
+
+    /// 缓存只能在创建它的项目和区域中使用,固定路由并去掉备用区域
+    fn pin(&self, route: &mut ResolvedRoute) {
+        route.project_id = self.project.clone();
+        route.project_pinned = true;
+        route.location = self.location.clone();
+        route.fallback_locations.clear();
+    }
+
+    /// 缓存所在的项目必须是本网关配置的项目,否则项目池会选到其他项目
+    fn check_owner(&self, lease: &ProjectLease) -> Result<(), GatewayError> {
+        if lease.project_id() == self.project {
+            return Ok(());
+        }
+        tracing::error!(
+            "Cached content {} belongs to unconfigured project {}",
+            self,
+            self.project
+        );
+        Err(GatewayError::bad_request(format!(
+            "Cached content project '{}' is not configured on this gateway",
+            self.project
+        ))
+        .with_param("cached_content"))
+    }
+}
+
+impl fmt::Display for CachedContentName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "projects/{}/locations/{}/cachedContents/{}",
+            self.project, self.location, self.id
+        )
+    }
+}
+
+/// 聊天请求引用的上下文缓存
+///
+/// 支持顶层的 `cached_content`(OpenAI SDK 的 `extra_body={"cached_content": ...}`),
+/// 以及 Vertex AI openapi 端点的 `extra_body.google.cached_content`
+pub(super) fn requested(request: &Map<String, Value>) -> Option<&str> {
+    request
+        .get("cached_content")
+        .or_else(|| {
+            request
+                .get("extra_body")?
+                .get("google")?
+                .get("cached_content")
+        })
+        .and_then(Value::as_str)
+        .filter(|name| !name.is_empty())
+}
+
+/// 解析模型路由,并固定到上下文缓存所在的项目和区域
+///
+/// 返回路由、项目租约和完整的缓存资源名
+pub(super) fn resolve_cached_route(
+    state: &AppState,
+    context: &RequestContext,
+    model: &str,
+    name: &str,
+) -> Result<(ResolvedRoute, ProjectLease, String), GatewayError> {
+    let mut route = state.routes.resolve(model);
+    let name = CachedContentName::parse(name, &route)?;
+    name.pin(&mut route);
+    let (route, lease) = lease_route(state, context, model, route);
+    name.check_owner(&lease)?;
+    Ok((route, lease, name.to_string()))
+}
+
+/// 创建上下文缓存 - POST
+///
+/// 将 OpenAI 消息和工具翻译为 Gemini 内容后调用 `cachedContents.create`,
+/// 缓存的 token 数记入 `usage`,按输入 token 计费
+pub async fn create_cached_content(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    body: String,
+) -> Result<Json<CachedContentObject>, GatewayError> {
+    let request: CreateCachedContentRequest = serde_json::from_str(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize cached content request: {}", e);
+        GatewayError::bad_request(format!("Invalid cached content request: {e}"))
+    })?;
+    context.set_model(&request.model);
+    check_model(api_key.as_deref().map(Arc::as_ref), &request.model)?;
+    let (ttl, expire_time) = expiration(request.ttl_seconds, request.expire_time, false)?;
+
+    // 1. 复用聊天请求的翻译,只取内容、系统指令和工具
+    let chat = ChatCompletionRequest {
+        model: request.model.clone(),
+        messages: request.messages,
+        tools: request.tools,
+        tool_choice: request.tool_choice,
+        ..Default::default()
+    };
+    let GenerateContentRequest {
+        contents,
+        system_instruction,
+        tools,
+        tool_config,
+        ..
+    } = to_generate_content(&chat).map_err(|e| {
+        tracing::error!("Failed to translate cached content to Gemini format: {}", e);
+        GatewayError::bad_request(e)
+    })?;
+
+    // 2. 缓存属于所选项目和主区域
+    let (route, lease) = resolve_route(&state, &context, &request.model);
+    if route.backend == Backend::Anthropic {
+        return Err(unsupported_backend(&request.model));
+    }
+    let model_id = model_id(&request.model)?;
+    let vertex_request = VertexCachedContent {
+        model: Some(format!(
+            "projects/{}/locations/{}/publishers/google/models/{model_id}",
+            route.project_id, route.location
+        )),
+        display_name: request.display_name,
+        contents,
+        system_instruction,
+        tools,
+        tool_config,
+        ttl,
+        expire_time,
+        ..Default::default()
+    };
+    let url = format!(
+        "{}/v1/projects/{}/locations/{}/cachedContents",
+        vertex_base_url(&route.location),
+        route.project_id,
+        route.location
+    );
+    let created: VertexCachedContent = send(
+        &state,
+        &context,
+        &route,
+        &lease,
+        Method::POST,
+        url,
+        Some(json!(vertex_request)),
+    )
+    .await?;
+
+    let object = to_object(created);
+    tracing::info!(
+        "Created cached content {} for model {} ({} tokens)",
+        object.id,
+        request.model,
+        object.usage.as_ref().map_or(0, |u| u.total_tokens)
+    );
+    Ok(Json(object))
+}
+
+/// 列出上下文缓存 - GET
+///
+/// 列出路由所在项目和区域中的全部缓存,需要 `admin: true` 的 API Key(未启用认证时不校验)
+pub async fn list_cached_contents(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<CachedContentList>, GatewayError> {
+    if state.key_store.is_some() && !api_key.as_ref().is_some_and(|k| k.admin) {
+        return Err(GatewayError::permission_denied(
+            "Listing cached contents requires an admin API key.",
+        ));
+    }
+    let model = query.model.as_deref().unwrap_or_default();
+    let mut route = state.routes.resolve(model);
+    route.project_pinned = true;
+    route.fallback_locations.clear();
+    let (route, lease) = lease_route(&state, &context, model, route);
+
+    let mut url = reqwest::Url::parse(&format!(
+        "{}/v1/projects/{}/locations/{}/cachedContents",
+        vertex_base_url(&route.location),
+        route.project_id,
+        route.location
+    ))
+    .map_err(|e| GatewayError::internal(format!("Invalid cached contents URL: {e}")))?;
+    if let Some(page_size) = query.page_size {
+        url.query_pairs_mut()
+            .append_pair("pageSize", &page_size.to_string());
+    }
+    if let Some(page_token) = &query.page_token {
+        url.query_pairs_mut().append_pair("pageToken", page_token);
+    }
+    let list: VertexCachedContentList = send(
+        &state,
+        &context,
+        &route,
+        &lease,
+        Method::GET,
+        url.to_string(),
+        None,
+    )
+    .await?;
+
+    Ok(Json(CachedContentList {
+        object: "list",
+        data: list.cached_contents.into_iter().map(to_object).collect(),
+        next_page_token: list.next_page_token.filter(|t| !t.is_empty()),
+    }))
+}
+
+/// 查询上下文缓存 - GET
+pub async fn get_cached_content(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    Path(name): Path<String>,
+    Query(query): Query<NameQuery>,
+) -> Result<Json<CachedContentObject>, GatewayError> {
+    let model = query.model.as_deref().unwrap_or_default();
+    let (route, lease, name) = resolve_cached_route(&state, &context, model, &name)?;
+    let url = format!("{}/v1/{name}", vertex_base_url(&route.location));
+    let cached: VertexCachedContent =
+        send(&state, &context, &route, &lease, Method::GET, url, None).await?;
+    Ok(Json(to_object(cached)))
+}
+
+/// 延长上下文缓存 - PATCH
+///
+/// 只能修改过期时间,`ttl_seconds` 从当前时间起算
+pub async fn update_cached_content(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    Path(name): Path<String>,
+    Query(query): Query<NameQuery>,
+    body: String,
+) -> Result<Json<CachedContentObject>, GatewayError> {
+    let request: UpdateCachedContentRequest = serde_json::from_str(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize cached content update: {}", e);
+        GatewayError::bad_request(format!("Invalid cached content update: {e}"))
+    })?;
+    let (ttl, expire_time) = expiration(request.ttl_seconds, request.expire_time, true)?;
+    let update_mask = if ttl.is_some() { "ttl" } else { "expireTime" };
+
+    let model = query.model.as_deref().unwrap_or_default();
+    let (route, lease, name) = resolve_cached_route(&state, &context, model, &name)?;
+    let url = format!(
+        "{}/v1/{name}?updateMask={update_mask}",
+        vertex_base_url(&route.location)
+    );
+    let patch = VertexCachedContent {
+        ttl,
+        expire_time,
+        ..Default::default()
+    };
+    let updated: VertexCachedContent = send(
+        &state,
+        &context,
+        &route,
+        &lease,
+        Method::PATCH,
+        url,
+        Some(json!(patch)),
+    )
+    .await?;
+
+    let object = to_object(updated);
+    tracing::info!(
+        "Extended cached content {} until {:?}",
+        object.id,
+        object.expires_at
+    );
+    Ok(Json(object))
+}
+
+/// 删除上下文缓存 - DELETE
+pub async fn delete_cached_content(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    Path(name): Path<String>,
+    Query(query): Query<NameQuery>,
+) -> Result<Json<Value>, GatewayError> {
+    let model = query.model.as_deref().unwrap_or_default();
+    let (route, lease, name) = resolve_cached_route(&state, &context, model, &name)?;
+    let url = format!("{}/v1/{name}", vertex_base_url(&route.location));
+    let _: Value = send(&state, &context, &route, &lease, Method::DELETE, url, None).await?;
+    tracing::info!("Deleted cached content {}", name);
+    Ok(Json(
+        json!({ "id": name, "object": "cached_content", "deleted": true }),
+    ))
+}
+
+/// 解析过期设置,`required` 时必须设置其中一个
+fn expiration(
+    ttl_seconds: Option<u64>,
+    expire_time: Option<DateTime<Utc>>,
+    required: bool,
+) -> Result<(Option<String>, Option<DateTime<Utc>>), GatewayError> {
+    match (ttl_seconds, expire_time) {
+        (Some(_), Some(_)) => Err(GatewayError::bad_request(
+            "Only one of 'ttl_seconds' and 'expire_time' may be set",
+        )),
+        (None, None) if required => Err(GatewayError::bad_request(
+            "One of 'ttl_seconds' or 'expire_time' is required",
+        )),
+        (Some(0), None) => Err(
+            GatewayError::bad_request("'ttl_seconds' must be greater than 0")
+                .with_param("ttl_seconds"),
+        ),
+        (Some(secs), None) => Ok((Some(format!("{secs}s")), None)),
+        (None, Some(time)) if time <= Utc::now() => Err(GatewayError::bad_request(
+            "'expire_time' must be in the future",
+        )
+        .with_param("expire_time")),
+        (None, expire_time) => Ok((None, expire_time)),
+    }
+}
+
+/// Claude 模型不支持上下文缓存
+pub(super) fn unsupported_backend(model: &str) -> GatewayError {
+    GatewayError::bad_request(format!("Model '{model}' does not support context caching"))
+        .with_param("model")
+}
+
+/// 调用 cachedContents 接口,缓存只存在于一个区域,不切换备用区域
+async fn send<T: DeserializeOwned>(
+    state: &AppState,
+    context: &RequestContext,
+    route: &ResolvedRoute,
+    lease: &ProjectLease,
+    method: Method,
+    url: String,
+    body: Option<Value>,
+) -> Result<T, GatewayError> {
+    let auth_header: HeaderValue = authorization(lease).await?;
+    let project_id = route.project_id.as_str();
+    let build = |_: &str| {
+        tracing::debug!("Forwarding cached content request to: {} {}", method, url);
+        let builder = state
+            .http_client
+            .request(method.clone(), &url)
+            .header(AUTHORIZATION, auth_header.clone())
+            .header(HEADER_USER_PROJECT.clone(), project_id)
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone());
+        match &body {
+            Some(body) => builder.json(body),
+            None => builder,
+        }
+    };
+    let result = retry::send_with_retry(
+        &state.config.retry,
+        "cached-contents",
+        std::slice::from_ref(&route.location),
+        route.timeouts,
+        false,
+        build,
+    )
+    .await;
+    observe_project(lease, &result);
+    context.record_upstream(&result);
+    let (response, _) = result?;
+
+    response.json().await.map_err(|e| {
+        tracing::error!("Failed to parse Vertex AI cached content response: {}", e);
+        GatewayError::bad_gateway(format!(
+            "Invalid cached content response from Vertex AI: {e}"
+        ))
+    })
+}
+
+/// 转换为客户端看到的缓存对象,模型只保留名称部分
+fn to_object(cached: VertexCachedContent) -> CachedContentObject {
+    let model = cached
+        .model
+        .as_deref()
+        .and_then(|m| m.rsplit('/').next())
+        .unwrap_or_default()
+        .to_owned();
+    CachedContentObject {
+        id: cached.name.unwrap_or_default(),
+        object: "cached_content",
+        model,
+        display_name: cached.display_name.filter(|n| !n.is_empty()),
+        created_at: cached.create_time.map(|t| t.timestamp()),
+        updated_at: cached.update_time.map(|t| t.timestamp()),
+        expires_at: cached.expire_time.map(|t| t.timestamp()),
+        usage: cached.usage_metadata.map(|u| CachedContentUsage {
+            prompt_tokens: u.total_token_count,
+            total_tokens: u.total_token_count,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::gcp::ProjectPool;
+    use crate::routing::RouteTable;
+    use axum::http::StatusCode;
+
+    fn route() -> ResolvedRoute {
+        let mut config = Config::default();
+        config.gcp.project_id = "main".to_owned();
+        config.gcp.location = "us-central1".to_owned();
+        RouteTable::new(&config)
+            .unwrap()
+            .resolve("gemini-2.5-flash")
+    }
+
+    #[test]
+    fn test_parse_resolves_short_and_full_names() {
+        let route = route();
+        assert_eq!(
+            CachedContentName::parse("123", &route).unwrap().to_string(),
+            "projects/main/locations/us-central1/cachedContents/123"
+        );
+        let full = "projects/other/locations/europe-west4/cachedContents/456";
+        let name = CachedContentName::parse(&format!("/{full}/"), &route).unwrap();
+        assert_eq!(name.project, "other");
+        assert_eq!(name.location, "europe-west4");
+        assert_eq!(name.to_string(), full);
+
+        let mut pinned = route.clone();
+        pinned.fallback_locations = vec!["us-east5".to_owned()];
+        name.pin(&mut pinned);
+        assert_eq!(pinned.project_id, "other");
+        assert!(pinned.project_pinned);
+        assert_eq!(pinned.locations(), ["europe-west4"]);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_names() {
+        let route = route();
+        for name in [
+            "",
+            "projects/p/locations/l/cachedContents/",
+            "projects/p/locations/l/endpoints/1",
+            "projects/p/locations/l/cachedContents/1/extra",
+            // 区域拼进主机名,不能借此改写上游地址
+            "projects/p/locations/evil.example.com#/cachedContents/1",
+            "projects/p/locations/l/cachedContents/1?alt=json",
+        ] {
+            let error = CachedContentName::parse(name, &route).unwrap_err();
+            assert_eq!(error.status, StatusCode::BAD_REQUEST, "{name}");
+            assert_eq!(error.body()["error"]["param"], "cached_content");
+        }
+    }
+
+    #[test]
+    fn test_check_owner_requires_configured_project() {
+        let pool = ProjectPool::with_projects(&["main", "other"]);
+        let route = route();
+        let owned = CachedContentName::parse(
+            "projects/other/locations/us-central1/cachedContents/1",
+            &route,
+        )
+        .unwrap();
+        assert!(owned
+            .check_owner(&pool.acquire(Some(&owned.project)))
+            .is_ok());
+
+        // 未配置的项目不会被项目池选中,租到的是其他项目
+        let foreign = CachedContentName::parse(
+            "projects/stranger/locations/us-central1/cachedContents/1",
+            &route,
+        )
+        .unwrap();
+        let error = foreign
+            .check_owner(&pool.acquire(Some(&foreign.project)))
+            .unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.body()["error"]["param"], "cached_content");
+    }
+}
@@ -0,0 +1,120 @@
+use super::forward_chat;
+use crate::auth::ApiKey;
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::models::completions::{CompletionRequest, CompletionResponse, Prompt};
+use crate::models::{ChatCompletionResponse, Usage};
+use crate::sse::translate_stream;
+use crate::state::AppState;
+use crate::translate::completions::{
+    completion_id, to_chat_request, to_choices, CompletionStreamTranslator,
+};
+use crate::usage::add_usage;
+use axum::{
+    body::Body,
+    extract::{Extension, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::header::{CACHE_CONTROL, CONTENT_TYPE};
+use std::sync::Arc;
+
+/// 文本补全接口 - POST
+///
+/// 每个提示包装为一条 user 消息,通过与 `/v1/chat/completions` 相同的路由和后端调用,
+/// 结果以 `text_completion` 格式返回。多个提示并发请求,流式只支持单个提示
+pub async fn completions(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    body: String,
+) -> Result<Response, GatewayError> {
+    let request: CompletionRequest = serde_json::from_str(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize completion request: {}", e);
+        GatewayError::bad_request(format!("Invalid completion request: {e}"))
+    })?;
+    let api_key = api_key.as_deref().map(Arc::as_ref);
+
+    // 1. 校验不支持的参数
+    let prompts = match &request.prompt {
+        Prompt::Single(prompt) => vec![prompt.clone()],
+        Prompt::Multiple(prompts) => prompts.clone(),
+        Prompt::Tokens(_) => {
+            return Err(
+                GatewayError::bad_request("Token array prompts are not supported")
+                    .with_param("prompt"),
+            );
+        }
+    };
+    if prompts.is_empty() {
+        return Err(GatewayError::bad_request("'prompt' must not be empty").with_param("prompt"));
+    }
+    if request.logprobs.is_some() {
+        return Err(GatewayError::bad_request("logprobs is not supported").with_param("logprobs"));
+    }
+    if request.suffix.is_some() {
+        return Err(GatewayError::bad_request("suffix is not supported").with_param("suffix"));
+    }
+    if request.is_stream() && prompts.len() > 1 {
+        return Err(
+            GatewayError::bad_request("Streaming is only supported for a single prompt")
+                .with_param("prompt"),
+        );
+    }
+
+    // 2. 流式响应: chat.completion.chunk 逐块转换为 text_completion
+    if request.is_stream() {
+        let prompt = &prompts[0];
+        let chat = serde_json::to_string(&to_chat_request(&request, prompt)).unwrap_or_default();
+        let response = forward_chat(&state, &context, api_key, &HeaderMap::new(), chat).await?;
+        let translator =
+            CompletionStreamTranslator::new(&request.model, request.echo().then(|| prompt.clone()));
+        let body = Body::from_stream(translate_stream(
+            response.into_body().into_data_stream(),
+            translator,
+        ));
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap());
+    }
+
+    // 3. 非流式: 每个提示单独请求,结果按提示顺序合并
+    let requests = prompts.iter().enumerate().map(|(index, prompt)| {
+        let chat = serde_json::to_string(&to_chat_request(&request, prompt)).unwrap_or_default();
+        let (state, context) = (&state, &context);
+        async move {
+            let response = forward_chat(state, context, api_key, &HeaderMap::new(), chat).await?;
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .map_err(|e| GatewayError::bad_gateway(format!("Failed to read response: {e}")))?;
+            let chat: ChatCompletionResponse = serde_json::from_slice(&bytes).map_err(|e| {
+                tracing::error!("Failed to parse chat completion response: {}", e);
+                GatewayError::bad_gateway(format!("Invalid response from Vertex AI: {e}"))
+            })?;
+            Ok::<_, GatewayError>((index as u32, prompt, chat))
+        }
+    });
+    let results = futures_util::future::try_join_all(requests).await?;
+
+    let mut choices = Vec::new();
+    let mut usage = Usage::default();
+    for (index, prompt, chat) in results {
+        if let Some(u) = &chat.usage {
+            add_usage(&mut usage, u);
+        }
+        choices.extend(to_choices(chat, prompt, index, &request));
+    }
+    Ok(Json(CompletionResponse {
+        id: completion_id(),
+        object: "text_completion",
+        created: chrono::Utc::now().timestamp(),
+        model: request.model.clone(),
+        choices,
+        usage: Some(usage),
+    })
+    .into_response())
+}
@@ -0,0 +1,301 @@
+use super::{
+    authorization, observe_project, resolve_route, vertex_base_url, CONTENT_TYPE_JSON,
+    HEADER_USER_PROJECT,
+};
+use crate::auth::{check_model, ApiKey};
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::metrics;
+use crate::models::embeddings::{
+    EmbeddingData, EmbeddingRequest, EmbeddingResponse, EmbeddingUsage, EmbeddingVector,
+    EncodingFormat, VertexEmbeddingInstance, VertexEmbeddingParameters, VertexEmbeddingRequest,
+    VertexEmbeddingResponse,
+};
+use crate::models::Usage;
+use crate::retry;
+use crate::routing::ResolvedRoute;
+use crate::state::AppState;
+use crate::translate::model_id;
+use axum::{
+    extract::{Extension, State},
+    http::StatusCode,
+    Json,
+};
+use base64::Engine;
+use futures_util::{StreamExt, TryStreamExt};
+use reqwest::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// text-embedding 系列模型单次 predict 最多支持的实例数
+const TEXT_EMBEDDING_BATCH_SIZE: usize = 250;
+
+/// 单个请求最多包含的输入数,与 OpenAI 的限制一致
+const MAX_INPUTS: usize = 2048;
+
+/// 同时进行的 predict 调用数
+const MAX_CONCURRENT_BATCHES: usize = 4;
+
+/// 嵌入接口 - POST
+///
+/// 将 OpenAI 嵌入请求转换为 Vertex AI `:predict` 调用,并以 OpenAI 格式返回
+pub async fn embeddings(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    body: String,
+) -> Result<Json<EmbeddingResponse>, GatewayError> {
+    let request: EmbeddingRequest = serde_json::from_str(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize embedding request: {}", e);
+        GatewayError::bad_request(format!("Invalid embedding request: {e}"))
+    })?;
+
+    // 1. 校验模型名称,兼容 /v1/models 返回的 "google/xxx" 格式
+    context.set_model(&request.model);
+    let model_id = model_id(&request.model)?.to_owned();
+    check_model(api_key.as_deref().map(Arc::as_ref), &model_id)?;
+    if !model_id.contains("embedding") {
+        tracing::error!("Model {} is not an embedding model", request.model);
+        return Err(GatewayError::bad_request(format!(
+            "Model '{}' does not support embeddings",
+            request.model
+        ))
+        .with_param("model"));
+    }
+
+    let inputs = request.input.into_vec();
+    if inputs.is_empty() {
+        tracing::error!("Embedding input must not be empty");
+        return Err(GatewayError::bad_request("'input' must not be empty").with_param("input"));
+    }
+    if inputs.len() > MAX_INPUTS {
+        tracing::error!("Embedding request has {} inputs", inputs.len());
+        return Err(GatewayError::bad_request(format!(
+            "'input' must not contain more than {MAX_INPUTS} items, got {}",
+            inputs.len()
+        ))
+        .with_param("input"));
+    }
+
+    // 2. 解析路由并获取所选项目的认证令牌
+    let (route, lease) = resolve_route(&state, &context, &request.model);
+    let auth_header = authorization(&lease).await?;
+
+    // 3. 按批次调用 predict,gemini-embedding 系列每次只接受一个实例;
+    // 最多同时进行 MAX_CONCURRENT_BATCHES 个调用,结果保持输入顺序
+    let batch_size = if model_id.starts_with("gemini-embedding") {
+        1
+    } else {
+        TEXT_EMBEDDING_BATCH_SIZE
+    };
+    let batches: Vec<_> = inputs
+        .chunks(batch_size)
+        .map(|chunk| {
+            predict(
+                &state,
+                &route,
+                &model_id,
+                context.model_label(),
+                &auth_header,
+                chunk,
+                request.dimensions,
+            )
+        })
+        .collect();
+    let start = Instant::now();
+    let results: Result<Vec<_>, _> = futures_util::stream::iter(batches)
+        .buffered(MAX_CONCURRENT_BATCHES)
+        .try_collect()
+        .await;
+    observe_project(&lease, &results);
+    context.set_upstream_status(match &results {
+        Ok(_) => Some(StatusCode::OK),
+        Err(e) => e.upstream_status,
+    });
+    let results = results?;
+    metrics::record_upstream_latency(context.model_label(), start.elapsed());
+
+    // 4. 转换为 OpenAI 格式
+    let mut data = Vec::with_capacity(inputs.len());
+    let mut prompt_tokens = 0u64;
+    for prediction in results.into_iter().flat_map(|r| r.predictions) {
+        if let Some(statistics) = &prediction.embeddings.statistics {
+            prompt_tokens += statistics.token_count as u64;
+        }
+        let values = prediction.embeddings.values;
+        let embedding = match request.encoding_format {
+            EncodingFormat::Float => EmbeddingVector::Float(values),
+            EncodingFormat::Base64 => {
+                let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+                EmbeddingVector::Base64(base64::engine::general_purpose::STANDARD.encode(bytes))
+            }
+        };
+        data.push(EmbeddingData {
+            object: "embedding",
+            index: data.len(),
+            embedding,
+        });
+    }
+
+    tracing::info!(
+        "Embedded {} inputs with {} ({} tokens)",
+        data.len(),
+        model_id,
+        prompt_tokens
+    );
+
+    Ok(Json(EmbeddingResponse {
+        object: "list",
+        data,
+        model: request.model,
+        usage: EmbeddingUsage {
+            prompt_tokens,
+            total_tokens: prompt_tokens,
+        },
+    }))
+}
+
+/// 调用一次 Vertex AI predict 接口,`label` 为指标中使用的模型标签
+async fn predict(
+    state: &AppState,
+    route: &ResolvedRoute,
+    model_id: &str,
+    label: &str,
+    auth_header: &HeaderValue,
+    inputs: &[String],
+    dimensions: Option<u32>,
+) -> Result<VertexEmbeddingResponse, GatewayError> {
+    let vertex_request = VertexEmbeddingRequest {
+        instances: inputs
+            .iter()
+            .map(|content| VertexEmbeddingInstance {
+                content: content.clone(),
+            })
+            .collect(),
+        parameters: VertexEmbeddingParameters {
+            output_dimensionality: dimensions,
+            auto_truncate: true,
+        },
+    };
+
+    let project_id = &route.project_id;
+    let build = |location: &str| {
+        let url = format!(
+            "{}/v1/projects/{project_id}/locations/{location}/publishers/google/models/{model_id}:predict",
+            vertex_base_url(location)
+        );
+        tracing::debug!("Forwarding embedding request to: {}", url);
+        state
+            .http_client
+            .post(url)
+            .header(AUTHORIZATION, auth_header.clone())
+            .header(HEADER_USER_PROJECT.clone(), project_id.as_str())
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone())
+            .json(&vertex_request)
+    };
+    let (response, _) = retry::send_with_retry(
+        &state.config.retry,
+        label,
+        &route.locations(),
+        route.timeouts,
+        false,
+        build,
+    )
+    .await?;
+
+    let response: VertexEmbeddingResponse = response.json().await.map_err(|e| {
+        tracing::error!("Failed to parse Vertex AI embedding response: {}", e);
+        GatewayError::bad_gateway(format!("Invalid embedding response from Vertex AI: {e}"))
+    })?;
+    check_prediction_count(&response, inputs.len())?;
+    Ok(response)
+}
+
+/// 校验 predict 返回的结果数与发送的实例数一致,否则结果无法按位置对应到输入
+fn check_prediction_count(
+    response: &VertexEmbeddingResponse,
+    expected: usize,
+) -> Result<(), GatewayError> {
+    if response.predictions.len() == expected {
+        return Ok(());
+    }
+    tracing::error!(
+        "Vertex AI returned {} embeddings for {} inputs",
+        response.predictions.len(),
+        expected
+    );
+    Err(GatewayError::bad_gateway(format!(
+        "Vertex AI returned {} embeddings for {} inputs",
+        response.predictions.len(),
+        expected
+    )))
+}
+
+/// 计算单段文本的嵌入向量,供语义缓存使用
+///
+/// 不改写请求上下文中的模型和路由,用量只记入指标
+pub(super) async fn embed_text(
+    state: &AppState,
+    model: &str,
+    text: &str,
+) -> Result<Vec<f32>, GatewayError> {
+    let model_id = model_id(model)?;
+    let mut route = state.routes.resolve(model);
+    let lease = state
+        .projects
+        .acquire(route.project_pinned.then_some(route.project_id.as_str()));
+    route.project_id = lease.project_id().to_owned();
+    let auth_header = authorization(&lease).await?;
+
+    let result = predict(
+        state,
+        &route,
+        model_id,
+        route.model_label(model),
+        &auth_header,
+        &[text.to_owned()],
+        None,
+    )
+    .await;
+    observe_project(&lease, &result);
+    let prediction = result?.predictions.into_iter().next().ok_or_else(|| {
+        tracing::error!("Vertex AI returned no embedding for model {}", model_id);
+        GatewayError::bad_gateway("Empty embedding response from Vertex AI")
+    })?;
+
+    if let Some(statistics) = &prediction.embeddings.statistics {
+        let prompt_tokens = statistics.token_count as u64;
+        metrics::record_usage(
+            route.model_label(model),
+            &Usage {
+                prompt_tokens,
+                total_tokens: prompt_tokens,
+                ..Default::default()
+            },
+        );
+    }
+    Ok(prediction.embeddings.values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(count: usize) -> VertexEmbeddingResponse {
+        let predictions: Vec<_> = (0..count)
+            .map(|_| serde_json::json!({"embeddings": {"values": [0.1, 0.2]}}))
+            .collect();
+        serde_json::from_value(serde_json::json!({ "predictions": predictions })).unwrap()
+    }
+
+    #[test]
+    fn test_prediction_count_must_match_inputs() {
+        assert!(check_prediction_count(&response(2), 2).is_ok());
+
+        let error = check_prediction_count(&response(1), 2).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(error.body()["error"]["type"], "upstream_error");
+
+        assert!(check_prediction_count(&response(0), 1).is_err());
+    }
+}
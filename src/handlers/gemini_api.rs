@@ -0,0 +1,269 @@
+use super::{
+    authorization, observe_project, resolve_route, vertex_base_url, CONTENT_TYPE_JSON,
+    HEADER_USER_PROJECT,
+};
+use crate::auth::{check_model, ApiKey};
+use crate::config::Backend;
+use crate::context::RequestContext;
+use crate::error::{GatewayError, GoogleError};
+use crate::metrics::{self, BodyObserver};
+use crate::retry;
+use crate::sse::{format_data, passthrough_stream};
+use crate::state::AppState;
+use crate::timeout::guard_response;
+use crate::translate::model_id;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Path, RawQuery, State},
+    http::StatusCode,
+    response::Response,
+};
+use reqwest::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 支持透传的 Gemini API 模型方法
+const METHODS: [&str; 4] = [
+    "generateContent",
+    "streamGenerateContent",
+    "countTokens",
+    "embedContent",
+];
+
+/// Gemini API 兼容接口 - POST `/v1beta/models/{model}:{method}`
+///
+/// 供 Google GenAI SDK 等使用 API Key 方式的客户端调用,请求改写为
+/// `projects/{p}/locations/{l}/publishers/google/models/{m}:{method}` 后转发,
+/// 使用网关的 GCP 凭据认证,响应原样返回,错误以 Google 格式返回
+pub async fn gemini_api(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    Path(target): Path<String>,
+    RawQuery(query): RawQuery,
+    body: Bytes,
+) -> Result<Response, GoogleError> {
+    let start = Instant::now();
+
+    // 1. 解析模型和方法
+    let Some((model, method)) = target
+        .split_once(':')
+        .filter(|(model, method)| !model.is_empty() && METHODS.contains(method))
+    else {
+        return Err(GatewayError::new(
+            StatusCode::NOT_FOUND,
+            "invalid_request_error",
+            format!("Unsupported model method: {target}"),
+        )
+        .into());
+    };
+    let model = model_id(model)?;
+    context.set_model(model);
+    check_model(api_key.as_deref().map(Arc::as_ref), model)?;
+
+    let mut request: Map<String, Value> = serde_json::from_slice(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize Gemini API request: {}", e);
+        GatewayError::bad_request(format!("Invalid JSON body: {e}"))
+    })?;
+    normalize_request(method, &mut request);
+    let body = Bytes::from(serde_json::to_vec(&request).unwrap_or_default());
+
+    // 2. 解析路由并获取所选项目的认证令牌
+    let (route, lease) = resolve_route(&state, &context, model);
+    if route.backend == Backend::Anthropic {
+        return Err(
+            GatewayError::bad_request(format!("Model '{model}' is not a Gemini model")).into(),
+        );
+    }
+    let auth_header = authorization(&lease).await?;
+
+    // 3. 只保留允许的查询参数(如 alt=sse),API Key 等其余参数不转发
+    let params = forwarded_params(query.as_deref());
+    let sse = params.contains(&"alt=sse");
+    let query = if params.is_empty() {
+        String::new()
+    } else {
+        format!("?{}", params.join("&"))
+    };
+    let project_id = &route.project_id;
+    let build = |location: &str| {
+        let url = upstream_url(project_id, location, model, method, &query);
+        tracing::debug!("Forwarding Gemini API request to: {}", url);
+        state
+            .http_client
+            .post(url)
+            .header(AUTHORIZATION, auth_header.clone())
+            .header(HEADER_USER_PROJECT.clone(), project_id.as_str())
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone())
+            .body(body.clone())
+    };
+
+    // 4. 发送请求
+    let stream = method == "streamGenerateContent";
+    let result = retry::send_with_retry(
+        &state.config.retry,
+        context.model_label(),
+        &route.locations(),
+        route.timeouts,
+        stream,
+        build,
+    )
+    .await;
+    observe_project(&lease, &result);
+    context.record_upstream(&result);
+    let (response, _) = result?;
+    metrics::record_upstream_latency(context.model_label(), start.elapsed());
+
+    // 5. 透传响应体,流式响应统计首字节时间
+    let observer = BodyObserver::new(context.model_label(), start, stream);
+    let mut builder = Response::builder().status(response.status());
+    if let Some(content_type) = response.headers().get(CONTENT_TYPE) {
+        builder = builder.header(CONTENT_TYPE, content_type.clone());
+    }
+    if stream {
+        builder = builder.header(CACHE_CONTROL, "no-cache");
+    }
+    // 流式响应受首字节和空闲超时限制,SSE 格式超时后以错误事件结束
+    let body = match (stream, sse) {
+        (true, true) => {
+            let upstream = passthrough_stream(guard_response(response, route.timeouts), |error| {
+                let mut out = String::new();
+                format_data(&mut out, &GoogleError(error).body().to_string());
+                out
+            });
+            Body::from_stream(lease.hold(metrics::instrument_body(upstream, observer)))
+        }
+        (true, false) => {
+            let upstream = guard_response(response, route.timeouts);
+            Body::from_stream(lease.hold(metrics::instrument_body(upstream, observer)))
+        }
+        _ => Body::from_stream(
+            lease.hold(metrics::instrument_body(response.bytes_stream(), observer)),
+        ),
+    };
+    Ok(builder.body(body).unwrap())
+}
+
+/// Vertex AI 上对应的模型方法 URL,`model` 需已通过 [`model_id`] 校验
+fn upstream_url(
+    project_id: &str,
+    location: &str,
+    model: &str,
+    method: &str,
+    query: &str,
+) -> String {
+    format!(
+        "{}/v1beta1/projects/{project_id}/locations/{location}/publishers/google/models/{model}:{method}{query}",
+        vertex_base_url(location)
+    )
+}
+
+/// 转发给 Vertex AI 的查询参数: 只保留取值为字母数字的 `alt`
+fn forwarded_params(query: Option<&str>) -> Vec<&str> {
+    query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| {
+            p.strip_prefix("alt=")
+                .is_some_and(|v| !v.is_empty() && v.bytes().all(|b| b.is_ascii_alphanumeric()))
+        })
+        .collect()
+}
+
+/// 将 Gemini API 请求体调整为 Vertex AI 格式
+///
+/// - 去掉请求体中的 `model` 字段,模型由 URL 指定
+/// - countTokens 的 `generateContentRequest` 展开到顶层
+/// - 补全 contents 中缺省的 role(Gemini API 默认为 user,Vertex AI 要求显式指定)
+fn normalize_request(method: &str, request: &mut Map<String, Value>) {
+    request.remove("model");
+    if method == "countTokens" {
+        if let Some(Value::Object(mut inner)) = request.remove("generateContentRequest") {
+            for key in ["contents", "systemInstruction", "tools", "generationConfig"] {
+                if let Some(value) = inner.remove(key) {
+                    request.insert(key.to_owned(), value);
+                }
+            }
+        }
+    }
+    if let Some(Value::Array(contents)) = request.get_mut("contents") {
+        for content in contents.iter_mut().filter_map(Value::as_object_mut) {
+            content.entry("role").or_insert_with(|| Value::from("user"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_params_keep_only_alt() {
+        assert_eq!(
+            forwarded_params(Some("alt=sse&key=AIza-secret")),
+            vec!["alt=sse"]
+        );
+        assert_eq!(
+            forwarded_params(Some("key=AIza&$fields=name&alt=json")),
+            vec!["alt=json"]
+        );
+        assert!(forwarded_params(Some("alt=sse%26key%3Dx&alt=")).is_empty());
+        assert!(forwarded_params(None).is_empty());
+    }
+
+    #[test]
+    fn test_upstream_url_rewrites_model_path() {
+        let model = model_id("models/gemini-2.5-flash").unwrap();
+        assert_eq!(
+            upstream_url("my-project", "us-central1", model, "streamGenerateContent", "?alt=sse"),
+            "https://us-central1-aiplatform.googleapis.com/v1beta1/projects/my-project/locations/us-central1/publishers/google/models/gemini-2.5-flash:streamGenerateContent?alt=sse"
+        );
+        assert_eq!(
+            upstream_url("p", "global", model, "countTokens", ""),
+            "https://aiplatform.googleapis.com/v1beta1/projects/p/locations/global/publishers/google/models/gemini-2.5-flash:countTokens"
+        );
+        // 解码后的路径分隔符和查询字符不能进入 URL
+        assert!(model_id("models/../../endpoints/x").is_err());
+        assert!(model_id("gemini?alt=json").is_err());
+    }
+
+    #[test]
+    fn test_normalize_count_tokens_flattens_generate_content_request() {
+        let mut request = serde_json::json!({
+            "model": "models/gemini-2.5-flash",
+            "generateContentRequest": {
+                "model": "models/gemini-2.5-flash",
+                "contents": [{"parts": [{"text": "hi"}]}],
+                "systemInstruction": {"parts": [{"text": "be brief"}]},
+                "tools": [{"googleSearch": {}}],
+                "generationConfig": {"temperature": 0.2},
+                "safetySettings": []
+            }
+        });
+        normalize_request("countTokens", request.as_object_mut().unwrap());
+
+        assert_eq!(
+            request,
+            serde_json::json!({
+                "contents": [{"role": "user", "parts": [{"text": "hi"}]}],
+                "systemInstruction": {"parts": [{"text": "be brief"}]},
+                "tools": [{"googleSearch": {}}],
+                "generationConfig": {"temperature": 0.2}
+            })
+        );
+    }
+
+    #[test]
+    fn test_normalize_keeps_generate_content_request_for_other_methods() {
+        let mut request = serde_json::json!({
+            "contents": [{"role": "model", "parts": [{"text": "hi"}]}, {"parts": []}],
+            "generateContentRequest": {"contents": []}
+        });
+        normalize_request("generateContent", request.as_object_mut().unwrap());
+
+        assert_eq!(request["contents"][0]["role"], "model");
+        assert_eq!(request["contents"][1]["role"], "user");
+        assert!(request.get("generateContentRequest").is_some());
+    }
+}
@@ -0,0 +1,169 @@
+use super::{
+    authorization, observe_project, resolve_route, vertex_base_url, CONTENT_TYPE_JSON,
+    HEADER_USER_PROJECT,
+};
+use crate::auth::{check_model, ApiKey};
+use crate::config::Backend;
+use crate::context::RequestContext;
+use crate::error::{AnthropicError, GatewayError};
+use crate::metrics::{self, BodyObserver};
+use crate::models::anthropic::{AnthropicUsage, MessagesRequest, VERTEX_ANTHROPIC_VERSION};
+use crate::models::gemini::GenerateContentResponse;
+use crate::retry;
+use crate::sse::{format_event, passthrough_stream, translate_stream};
+use crate::state::AppState;
+use crate::timeout::guard_response;
+use crate::translate::anthropic::usage_from_anthropic;
+use crate::translate::messages::{
+    to_generate_content, to_messages_response, MessagesStreamTranslator,
+};
+use crate::translate::model_id;
+use crate::usage::ReportUsage;
+use axum::{
+    body::Body,
+    extract::{Extension, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Anthropic Messages 接口 - POST
+///
+/// Gemini 模型: 将 Anthropic 请求翻译为 `generateContent` / `streamGenerateContent`,
+/// 并将响应翻译回 Anthropic 格式(包括 `message_start`、`content_block_delta` 等流式事件);
+/// Claude 模型: 直接转发到 Vertex AI 的 `rawPredict` / `streamRawPredict`
+pub async fn messages(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    body: String,
+) -> Result<Response, AnthropicError> {
+    let mut raw: Map<String, Value> = serde_json::from_str(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize messages request: {}", e);
+        GatewayError::bad_request(format!("Invalid JSON body: {e}"))
+    })?;
+    let model = raw
+        .get("model")
+        .and_then(Value::as_str)
+        .filter(|m| !m.is_empty())
+        .ok_or_else(|| GatewayError::bad_request("'model' is required").with_param("model"))?
+        .to_owned();
+    let stream = raw.get("stream").and_then(Value::as_bool).unwrap_or(false);
+    context.set_model(&model);
+    let model_id = model_id(&model)?;
+    check_model(api_key.as_deref().map(Arc::as_ref), &model)?;
+
+    // 1. 解析路由并获取所选项目的认证令牌
+    let (route, lease) = resolve_route(&state, &context, &model);
+    let auth_header = authorization(&lease).await?;
+    let claude = route.backend == Backend::Anthropic;
+
+    // 2. 构建上游请求体: Claude 原样转发,Gemini 翻译为 generateContent
+    let upstream_body = if claude {
+        raw.remove("model");
+        raw.entry("anthropic_version")
+            .or_insert_with(|| Value::from(VERTEX_ANTHROPIC_VERSION));
+        Value::Object(raw)
+    } else {
+        let request: MessagesRequest = serde_json::from_value(Value::Object(raw)).map_err(|e| {
+            tracing::error!("Failed to deserialize messages request: {}", e);
+            GatewayError::bad_request(format!("Invalid messages request: {e}"))
+        })?;
+        let gemini_request = to_generate_content(&request, model_id).map_err(|e| {
+            tracing::error!("Failed to translate request to Gemini format: {}", e);
+            GatewayError::bad_request(e)
+        })?;
+        serde_json::to_value(gemini_request)
+            .map_err(|e| GatewayError::internal(format!("Failed to encode Gemini request: {e}")))?
+    };
+    let (version, publisher, method) = match (claude, stream) {
+        (true, true) => ("v1", "anthropic", "streamRawPredict"),
+        (true, false) => ("v1", "anthropic", "rawPredict"),
+        (false, true) => ("v1beta1", "google", "streamGenerateContent?alt=sse"),
+        (false, false) => ("v1beta1", "google", "generateContent"),
+    };
+    let project_id = &route.project_id;
+    let build = |location: &str| {
+        let url = format!(
+            "{}/{version}/projects/{project_id}/locations/{location}/publishers/{publisher}/models/{model_id}:{method}",
+            vertex_base_url(location)
+        );
+        tracing::debug!("Forwarding messages request to: {}", url);
+        state
+            .http_client
+            .post(url)
+            .header(AUTHORIZATION, auth_header.clone())
+            .header(HEADER_USER_PROJECT.clone(), project_id.as_str())
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone())
+            .json(&upstream_body)
+    };
+
+    // 3. 发送请求
+    let start = Instant::now();
+    let result = retry::send_with_retry(
+        &state.config.retry,
+        context.model_label(),
+        &route.locations(),
+        route.timeouts,
+        stream,
+        build,
+    )
+    .await;
+    observe_project(&lease, &result);
+    context.record_upstream(&result);
+    let (response, _) = result?;
+    metrics::record_upstream_latency(context.model_label(), start.elapsed());
+
+    // 4. 流式响应: Claude 直接透传,Gemini 逐块翻译为 Anthropic 事件
+    if stream {
+        let observer = BodyObserver::new(context.model_label(), start, true);
+        let upstream = guard_response(response, route.timeouts);
+        let body = if claude {
+            let upstream = passthrough_stream(upstream, |error| {
+                let mut out = String::new();
+                format_event(&mut out, "error", &AnthropicError(error).body().to_string());
+                out
+            });
+            Body::from_stream(lease.hold(metrics::instrument_body(upstream, observer)))
+        } else {
+            Body::from_stream(lease.hold(metrics::instrument_body(
+                translate_stream(
+                    upstream,
+                    ReportUsage::new(MessagesStreamTranslator::new(&model), context.usage_slot()),
+                ),
+                observer,
+            )))
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap());
+    }
+
+    // 5. 非流式响应,Claude 的响应体原样返回
+    if claude {
+        let message: Value = response.json().await.map_err(|e| {
+            tracing::error!("Failed to parse Claude response: {}", e);
+            GatewayError::bad_gateway(format!("Invalid response from Vertex AI: {e}"))
+        })?;
+        if let Ok(usage) = serde_json::from_value::<AnthropicUsage>(message["usage"].clone()) {
+            let usage = usage_from_anthropic(&usage);
+            context.record_usage(&usage);
+        }
+        return Ok(Json(message).into_response());
+    }
+    let gemini_response: GenerateContentResponse = response.json().await.map_err(|e| {
+        tracing::error!("Failed to parse Gemini response: {}", e);
+        GatewayError::bad_gateway(format!("Invalid response from Vertex AI: {e}"))
+    })?;
+    let message = to_messages_response(gemini_response, &model);
+    let usage = usage_from_anthropic(&message.usage);
+    context.record_usage(&usage);
+    Ok(Json(message).into_response())
+}
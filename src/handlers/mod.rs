@@ -1,109 +1,311 @@
-use crate::models::ModelsResponse;
+mod admin;
+mod anthropic;
+mod cached;
+mod cached_contents;
+mod completions;
+mod embeddings;
+mod gemini_api;
+mod messages;
+mod native;
+mod responses;
+mod tokens;
+
+pub use admin::usage;
+pub use cached_contents::{
+    create_cached_content, delete_cached_content, get_cached_content, list_cached_contents,
+    update_cached_content,
+};
+pub use completions::completions;
+pub use embeddings::embeddings;
+pub use gemini_api::gemini_api;
+pub use messages::messages;
+pub use responses::{create_response, delete_response, get_response};
+pub use tokens::count_tokens;
+
+use crate::auth::{check_model, ApiKey};
+use crate::config::Backend;
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::gcp::ProjectLease;
+use crate::metrics::{self, BodyObserver};
+use crate::models::{ModelsResponse, VertexModel, VertexModelsResponse};
+use crate::retry;
+use crate::routing::ResolvedRoute;
+use crate::sse::{openai_error_event, passthrough_stream, translate_stream, with_keepalive};
 use crate::state::AppState;
+use crate::telemetry;
+use crate::timeout::guard_response;
+use crate::translate::openapi::OpenapiStreamNormalizer;
 use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
+    body::Bytes,
+    extract::{Extension, State},
+    http::HeaderMap,
     response::Response,
     Json,
 };
 use lazy_static::lazy_static;
-use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
 use serde_json::{Map, Value};
 use std::sync::Arc;
+use std::time::Instant;
 
 lazy_static! {
     static ref HEADER_AUTHORIZATION: HeaderName = HeaderName::from_static("authorization");
     static ref HEADER_CONTENT_TYPE: HeaderName = HeaderName::from_static("content-type");
     static ref HEADER_USER_PROJECT: HeaderName = HeaderName::from_static("x-goog-user-project");
     static ref CONTENT_TYPE_JSON: HeaderValue = HeaderValue::from_static("application/json");
-    static ref GLOBAL: String = "global".to_owned();
 }
 
 const MODLES_URL: &str =
     "https://us-central1-aiplatform.googleapis.com/v1beta1/publishers/google/models";
+const ANTHROPIC_MODELS_URL: &str =
+    "https://us-central1-aiplatform.googleapis.com/v1beta1/publishers/anthropic/models";
+
+/// 转发给 Vertex AI 的客户端请求头
+const FORWARDED_HEADERS: &[&str] = &["accept", "accept-language", "user-agent", "x-request-id"];
+
+/// 客户端请求头是否转发给上游
+///
+/// 启用追踪时 `traceparent` 由网关重新生成,不再原样转发
+fn forwarded_header(name: &HeaderName) -> bool {
+    let name = name.as_str();
+    FORWARDED_HEADERS.contains(&name)
+        || (!telemetry::enabled() && telemetry::PROPAGATION_HEADERS.contains(&name))
+}
+
+/// 根据区域获取 Vertex AI API 主机地址
+///
+/// `global` 区域使用不带区域前缀的主机名
+fn vertex_base_url(location: &str) -> String {
+    if location == "global" {
+        "https://aiplatform.googleapis.com".to_owned()
+    } else {
+        format!("https://{location}-aiplatform.googleapis.com")
+    }
+}
+
+/// 解析模型路由并选择 GCP 项目,记录到请求上下文并输出日志
+fn resolve_route(
+    state: &AppState,
+    context: &RequestContext,
+    model: &str,
+) -> (ResolvedRoute, ProjectLease) {
+    lease_route(state, context, model, state.routes.resolve(model))
+}
+
+/// 为已解析的路由选择 GCP 项目,记录到请求上下文并输出日志
+fn lease_route(
+    state: &AppState,
+    context: &RequestContext,
+    model: &str,
+    mut route: ResolvedRoute,
+) -> (ResolvedRoute, ProjectLease) {
+    let lease = state
+        .projects
+        .acquire(route.project_pinned.then_some(route.project_id.as_str()));
+    route.project_id = lease.project_id().to_owned();
+    tracing::info!(
+        "Routing model {} via route {} (location={}, project={}, backend={:?})",
+        model,
+        route.name,
+        route.location,
+        route.project_id,
+        route.backend
+    );
+    context.set_route(&route);
+    (route, lease)
+}
+
+/// 获取所选项目的 GCP 认证头
+async fn authorization(lease: &ProjectLease) -> Result<HeaderValue, GatewayError> {
+    lease.authorization().await.map_err(|e| {
+        tracing::error!(
+            "Failed to get authorization token for project {}: {}",
+            lease.project_id(),
+            e
+        );
+        GatewayError::internal("Failed to obtain Google Cloud credentials")
+    })
+}
+
+/// 根据上游结果更新项目健康状态,连续配额错误会使项目被暂时摘除
+fn observe_project<T>(lease: &ProjectLease, result: &Result<T, GatewayError>) {
+    match result {
+        Ok(_) => lease.record_success(),
+        Err(e) if e.is_quota_error() => lease.record_quota_error(),
+        Err(_) => lease.record_error(),
+    }
+}
 
 /// 根路径健康检查
 pub async fn root() -> &'static str {
     "Hello, this is Simple Vertex Bridge! UwU"
 }
 
+/// 未知路由,返回 OpenAI 格式的 404
+pub async fn not_found(uri: axum::http::Uri) -> GatewayError {
+    GatewayError::new(
+        axum::http::StatusCode::NOT_FOUND,
+        "invalid_request_error",
+        format!("Unknown request URL: {}", uri.path()),
+    )
+    .with_code("unknown_url")
+}
+
 /// 聊天完成接口 - GET/POST
 ///
-/// 直接透传 Vertex AI 的响应,包括所有响应头
+/// 直接透传 Vertex AI 的响应,包括所有响应头;启用响应缓存时先查询缓存
 pub async fn chat_completions(
     State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
     headers: HeaderMap,
     body: String,
-) -> Result<Response, StatusCode> {
+) -> Result<Response, GatewayError> {
+    let api_key = api_key.as_deref().map(Arc::as_ref);
+    if state.chat_cache.is_some() || state.semantic_cache.is_some() {
+        cached::forward_cached(&state, &context, api_key, &headers, body).await
+    } else {
+        forward_chat(&state, &context, api_key, &headers, body).await
+    }
+}
+
+/// 按路由转发一个 OpenAI 聊天完成请求,返回 OpenAI 格式的响应
+///
+/// 供 `/v1/chat/completions` 和基于聊天完成实现的其他接口共用
+async fn forward_chat(
+    state: &AppState,
+    context: &RequestContext,
+    api_key: Option<&ApiKey>,
+    headers: &HeaderMap,
+    body: String,
+) -> Result<Response, GatewayError> {
     use axum::body::Body;
 
-    // 1. 获取认证令牌
-    let auth_header = state.token_manager.authorization().await.map_err(|e| {
-        tracing::error!("Failed to get authorization token: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let start = Instant::now();
 
     let request_body: Map<String, Value> = serde_json::from_str(&body).map_err(|e| {
         tracing::error!("Failed to deserialize body: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+        GatewayError::bad_request(format!("Invalid JSON body: {e}"))
     })?;
+    let model_id = request_body
+        .get("model")
+        .and_then(|m| m.as_str())
+        .unwrap_or("");
+    let stream = request_body
+        .get("stream")
+        .and_then(|s| s.as_bool())
+        .unwrap_or(false);
+    context.set_model(model_id);
+    check_model(api_key, model_id)?;
 
-    // 2. 构建 Vertex AI URL
-    let mut location = &state.config.location;
-    let project_id = state.config.project_id;
-    let endpoint_id = state.config.endpoint_id;
-    if let Some(model) = request_body.get("model") {
-        let model_id = model.as_str().unwrap_or("");
-        if model_id.contains("gemini-3") {
-            location = &GLOBAL;
+    // 1. 解析路由并获取所选项目的认证令牌,引用上下文缓存时固定到缓存所在的项目和区域
+    let (route, lease, cached_content) = match cached_contents::requested(&request_body) {
+        Some(name) => {
+            let (route, lease, name) =
+                cached_contents::resolve_cached_route(state, context, model_id, name)?;
+            (route, lease, Some(name))
+        }
+        None => {
+            let (route, lease) = resolve_route(state, context, model_id);
+            (route, lease, None)
         }
-    }
-    let url = if location.eq("global") {
-        format!(
-            "https://aiplatform.googleapis.com/v1beta1/projects/{project_id}/locations/{location}/endpoints/{endpoint_id}/chat/completions"
-        )
-    } else {
-        format!(
-            "https://{location}-aiplatform.googleapis.com/v1beta1/projects/{project_id}/locations/{location}/endpoints/{endpoint_id}/chat/completions"
-        )
     };
+    let auth_header = authorization(&lease).await?;
 
-    tracing::debug!("Forwarding chat completion request to: {}", url);
-
-    // 3. 构建请求,先设置我们的认证头
-    let mut request_builder = state
-        .http_client
-        .post(&url)
-        .header(AUTHORIZATION, auth_header)
-        .header(HEADER_USER_PROJECT.clone(), project_id)
-        .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone());
-
-    // 4. 转发客户端的其他请求头(排除敏感头和我们自己设置的头)
-    for (key, value) in headers.iter() {
-        let key_str = key.as_str().to_lowercase();
-        // 跳过这些头:我们会自己设置或者不应该转发
-        if key_str == "host"
-            || key_str == "authorization"
-            || key_str == "content-length"
-            || key_str == "content-type"
-            || key_str.starts_with("x-goog-")
-        {
-            continue;
+    // 2. 构建 Vertex AI URL,原生模式的模型走 generateContent 翻译,Claude 走 rawPredict 翻译;
+    // 引用上下文缓存的请求也走 generateContent,以便在 usage 中返回缓存命中的 token 数
+    let backend = match route.backend {
+        Backend::Openapi if cached_content.is_some() => Backend::Native,
+        Backend::Anthropic if cached_content.is_some() => {
+            return Err(cached_contents::unsupported_backend(model_id));
+        }
+        backend => backend,
+    };
+    match backend {
+        Backend::Native => {
+            return native::chat_completions(
+                state,
+                context,
+                auth_header,
+                &route,
+                lease,
+                &body,
+                cached_content,
+            )
+            .await;
+        }
+        Backend::Anthropic => {
+            return anthropic::chat_completions(state, context, auth_header, &route, lease, &body)
+                .await;
         }
-        request_builder = request_builder.header(key, value);
+        Backend::Openapi => {}
     }
+    let ResolvedRoute {
+        project_id,
+        endpoint_id,
+        ..
+    } = &route;
+    let body = Bytes::from(body);
 
-    // 5. 发送请求
-    let response = request_builder.body(body).send().await.map_err(|e| {
-        tracing::error!("Failed to forward request to Vertex AI: {}", e);
-        StatusCode::BAD_GATEWAY
-    })?;
+    // 3. 构建请求,先设置我们的认证头,失败时按区域重试
+    let build = |location: &str| {
+        let url = format!(
+            "{}/v1beta1/projects/{project_id}/locations/{location}/endpoints/{endpoint_id}/chat/completions",
+            vertex_base_url(location)
+        );
+        tracing::debug!("Forwarding chat completion request to: {}", url);
+
+        let mut request_builder = state
+            .http_client
+            .post(url)
+            .header(AUTHORIZATION, auth_header.clone())
+            .header(HEADER_USER_PROJECT.clone(), project_id.as_str())
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone());
+
+        // 4. 只转发白名单中的客户端请求头,客户端的 API Key 等凭据不会发给 Vertex AI
+        for (key, value) in headers.iter() {
+            if forwarded_header(key) {
+                request_builder = request_builder.header(key, value);
+            }
+        }
+        request_builder.body(body.clone())
+    };
 
-    // 6. 检查响应状态
+    // 5. 发送请求,错误转换为 OpenAI 格式
+    let result = retry::send_with_retry(
+        &state.config.retry,
+        context.model_label(),
+        &route.locations(),
+        route.timeouts,
+        stream,
+        build,
+    )
+    .await;
+    observe_project(&lease, &result);
+    context.record_upstream(&result);
+    let (response, _) = result?;
+    metrics::record_upstream_latency(context.model_label(), start.elapsed());
     let status = response.status();
-    if !status.is_success() {
-        tracing::error!("Vertex AI returned error status: {}", status);
-        // 即使是错误也透传完整响应
+
+    // 6. 规范化模式: 逐个事件改写为 OpenAI 格式,按配置插入心跳,保证以 [DONE] 或错误事件结束
+    if stream && state.config.streaming.normalize {
+        let observer = BodyObserver::new(context.model_label(), start, true);
+        let upstream = translate_stream(
+            guard_response(response, route.timeouts),
+            OpenapiStreamNormalizer::new(model_id),
+        );
+        let upstream = metrics::instrument_body(upstream, observer);
+        let body = match state.config.streaming.keepalive_interval() {
+            Some(interval) => Body::from_stream(lease.hold(with_keepalive(upstream, interval))),
+            None => Body::from_stream(lease.hold(upstream)),
+        };
+        return Ok(Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap());
     }
 
     // 7. 复制响应头
@@ -114,8 +316,17 @@ pub async fn chat_completions(
         response_builder = response_builder.header(key, value);
     }
 
-    // 8. 直接透传响应体(支持流式和非流式)
-    let body = Body::from_stream(response.bytes_stream());
+    // 8. 直接透传响应体(支持流式和非流式),同时统计首字节时间
+    // 流式响应受首字节和空闲超时限制,超时后以错误事件结束;
+    // 客户端断开时整个响应体流被丢弃,上游响应随之关闭,不再继续生成
+    let observer = BodyObserver::new(context.model_label(), start, stream);
+    let body = if stream {
+        let upstream =
+            passthrough_stream(guard_response(response, route.timeouts), openai_error_event);
+        Body::from_stream(lease.hold(metrics::instrument_body(upstream, observer)))
+    } else {
+        Body::from_stream(lease.hold(metrics::instrument_body(response.bytes_stream(), observer)))
+    };
     Ok(response_builder.body(body).unwrap())
 }
 
@@ -124,40 +335,85 @@ pub async fn chat_completions(
 /// 从 Vertex AI 获取可用模型并转换为 OpenAI 格式,使用缓存减少 API 调用
 pub async fn models(
     State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
     _headers: HeaderMap,
-) -> Result<Json<ModelsResponse>, StatusCode> {
-    use crate::models::VertexModelsResponse;
+) -> Result<Json<ModelsResponse>, GatewayError> {
+    // 只返回客户端 Key 有权访问的模型
+    let visible = |models: Vec<crate::models::Model>| -> Vec<crate::models::Model> {
+        match api_key.as_deref() {
+            Some(key) => models
+                .into_iter()
+                .filter(|m| key.allows_model(&m.id))
+                .collect(),
+            None => models,
+        }
+    };
 
     // 1. 先检查缓存
-    if let Some(cached_models) = state.models_cache.get("vertex_models").await {
+    let cached = state.models_cache.get("vertex_models").await;
+    metrics::record_models_cache(cached.is_some());
+    if let Some(cached_models) = cached {
         tracing::debug!("Returning {} models from cache", cached_models.len());
         return Ok(Json(ModelsResponse {
             object: "list",
-            data: cached_models,
+            data: visible(cached_models),
         }));
     }
 
     tracing::debug!("Cache miss, fetching models from Vertex AI");
 
     // 2. 获取 GCP 访问令牌
-    let auth_header = state.token_manager.authorization().await.map_err(|e| {
-        tracing::error!("Failed to get authorization token: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let lease = state.projects.acquire(None);
+    let auth_header = authorization(&lease).await?;
 
-    // 3. 构建 Vertex AI API URL
-    let project_id = state.config.project_id;
-    let url = MODLES_URL;
+    // 3. 请求 Google 模型列表,Anthropic 模型列表获取失败时不影响结果
+    let project_id = lease.project_id();
+    let mut publisher_models =
+        fetch_publisher_models(&state, MODLES_URL, &auth_header, project_id).await?;
+    match fetch_publisher_models(&state, ANTHROPIC_MODELS_URL, &auth_header, project_id).await {
+        Ok(models) => publisher_models.extend(models),
+        Err(e) => tracing::warn!("Failed to fetch Anthropic models from Vertex AI: {}", e),
+    }
 
+    // 4. 转换为 OpenAI 格式并过滤
+    let models: Vec<_> = publisher_models
+        .into_iter()
+        .filter(|m| m.should_include())
+        .map(|m| m.to_openai_model())
+        .collect();
+
+    tracing::info!("Fetched {} models from Vertex AI", models.len());
+
+    // 5. 缓存结果
+    state.models_cache.invalidate_all();
+    state
+        .models_cache
+        .insert("vertex_models".to_owned(), models.clone())
+        .await;
+    tracing::debug!("Models cached for 1 hour");
+
+    Ok(Json(ModelsResponse {
+        object: "list",
+        data: visible(models),
+    }))
+}
+
+/// 请求一个发布商的 Vertex AI 模型列表
+async fn fetch_publisher_models(
+    state: &AppState,
+    url: &str,
+    auth_header: &HeaderValue,
+    project_id: &str,
+) -> Result<Vec<VertexModel>, GatewayError> {
     tracing::debug!("Requesting models from: {}", url);
 
-    // 4. 请求 Vertex AI 模型列表
     let response = state
         .http_client
         .get(url)
-        .header(AUTHORIZATION, auth_header)
+        .header(AUTHORIZATION, auth_header.clone())
         .header(HEADER_USER_PROJECT.clone(), project_id)
         .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone())
+        .timeout(state.config.upstream.timeouts().total)
         .send()
         .await
         .map_err(|e| {
@@ -173,46 +429,19 @@ pub async fn models(
                 tracing::error!("Request error");
             }
 
-            StatusCode::BAD_GATEWAY
+            GatewayError::from_reqwest(&e)
         })?;
 
-    // 5. 检查响应状态
+    // 检查响应状态
     let status = response.status();
     if !status.is_success() {
-        let error_body = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "Unable to read error body".to_string());
-        tracing::error!("Vertex AI returned error status {}: {}", status, error_body);
-        return Err(StatusCode::BAD_GATEWAY);
+        return Err(GatewayError::from_response(response).await);
     }
 
-    // 6. 解析响应
+    // 解析响应
     let vertex_response: VertexModelsResponse = response.json().await.map_err(|e| {
         tracing::error!("Failed to parse Vertex AI response: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+        GatewayError::bad_gateway(format!("Invalid models response from Vertex AI: {e}"))
     })?;
-
-    // 7. 转换为 OpenAI 格式并过滤
-    let models: Vec<_> = vertex_response
-        .publisher_models
-        .into_iter()
-        .filter(|m| m.should_include())
-        .map(|m| m.to_openai_model())
-        .collect();
-
-    tracing::info!("Fetched {} models from Vertex AI", models.len());
-
-    // 8. 缓存结果
-    state.models_cache.invalidate_all();
-    state
-        .models_cache
-        .insert("vertex_models".to_owned(), models.clone())
-        .await;
-    tracing::debug!("Models cached for 1 hour");
-
-    Ok(Json(ModelsResponse {
-        object: "list",
-        data: models,
-    }))
+    Ok(vertex_response.publisher_models)
 }
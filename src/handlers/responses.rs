@@ -0,0 +1,150 @@
+use super::forward_chat;
+use crate::auth::ApiKey;
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::models::responses::{ResponseObject, ResponsesRequest, StoredResponse};
+use crate::models::ChatCompletionResponse;
+use crate::sse::translate_stream;
+use crate::state::AppState;
+use crate::translate::responses::{
+    in_progress, output_to_message, response_id, to_chat_request, to_response,
+    ResponsesStreamTranslator,
+};
+use axum::{
+    body::Body,
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::header::{CACHE_CONTROL, CONTENT_TYPE};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Responses 接口 - POST
+///
+/// 将请求翻译为聊天完成请求,复用 `/v1/chat/completions` 的路由和后端,
+/// 再将结果翻译为 Responses 对象或类型化流式事件。
+/// `store` 不为 false 时保存响应,供后续请求通过 `previous_response_id` 续接对话
+pub async fn create_response(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    body: String,
+) -> Result<Response, GatewayError> {
+    let request: ResponsesRequest = serde_json::from_str(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize responses request: {}", e);
+        GatewayError::bad_request(format!("Invalid responses request: {e}"))
+    })?;
+    let api_key = api_key.as_deref().map(Arc::as_ref);
+    let owner = api_key.map(|k| k.key.clone());
+
+    // 1. 读取上一个响应的对话历史
+    let history = match &request.previous_response_id {
+        Some(id) => find(&state, id, owner.as_deref())
+            .map_err(|e| e.with_param("previous_response_id"))?
+            .messages
+            .clone(),
+        None => Vec::new(),
+    };
+
+    // 2. 翻译为聊天完成请求并按路由转发
+    let (chat, conversation) = to_chat_request(&request, history).map_err(|e| {
+        tracing::error!("Failed to translate responses request: {}", e);
+        GatewayError::bad_request(e)
+    })?;
+    let chat_body = serde_json::to_string(&chat)
+        .map_err(|e| GatewayError::internal(format!("Failed to encode chat request: {e}")))?;
+    let response = forward_chat(&state, &context, api_key, &HeaderMap::new(), chat_body).await?;
+
+    // 3. 响应完成后保存对话,包括本次输入和助手输出
+    let skeleton = in_progress(&request, response_id());
+    let store = request
+        .store
+        .unwrap_or(true)
+        .then(|| (state.responses.clone(), owner, conversation));
+    let save = move |response: &ResponseObject| {
+        let Some((responses, owner, mut messages)) = store else {
+            return;
+        };
+        messages.extend(output_to_message(&response.output));
+        let stored = StoredResponse {
+            owner,
+            messages,
+            response: response.clone(),
+        };
+        responses.insert(response.id.clone(), Arc::new(stored));
+    };
+
+    // 4. 流式响应: chat.completion.chunk 逐块翻译为 Responses 事件
+    if request.is_stream() {
+        let translator = ResponsesStreamTranslator::new(skeleton).on_complete(save);
+        let body = Body::from_stream(translate_stream(
+            response.into_body().into_data_stream(),
+            translator,
+        ));
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(body)
+            .unwrap());
+    }
+
+    // 5. 非流式响应
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| GatewayError::bad_gateway(format!("Failed to read response: {e}")))?;
+    let chat_response: ChatCompletionResponse = serde_json::from_slice(&bytes).map_err(|e| {
+        tracing::error!("Failed to parse chat completion response: {}", e);
+        GatewayError::bad_gateway(format!("Invalid response from Vertex AI: {e}"))
+    })?;
+    let response = to_response(chat_response, skeleton);
+    save(&response);
+    Ok(Json(response).into_response())
+}
+
+/// 查询已保存的响应 - GET
+pub async fn get_response(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    Path(id): Path<String>,
+) -> Result<Json<ResponseObject>, GatewayError> {
+    let owner = api_key.as_deref().map(|k| k.key.as_str());
+    let stored = find(&state, &id, owner)?;
+    Ok(Json(stored.response.clone()))
+}
+
+/// 删除已保存的响应 - DELETE
+pub async fn delete_response(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, GatewayError> {
+    let owner = api_key.as_deref().map(|k| k.key.as_str());
+    find(&state, &id, owner)?;
+    state.responses.invalidate(&id);
+    Ok(Json(
+        json!({ "id": id, "object": "response", "deleted": true }),
+    ))
+}
+
+/// 按 ID 查找已保存的响应,其他 API Key 创建的响应视为不存在
+fn find(
+    state: &AppState,
+    id: &str,
+    owner: Option<&str>,
+) -> Result<Arc<StoredResponse>, GatewayError> {
+    state
+        .responses
+        .get(id)
+        .filter(|stored| stored.owner.as_deref() == owner)
+        .ok_or_else(|| {
+            GatewayError::new(
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                format!("Response with id '{id}' not found."),
+            )
+            .with_code("response_not_found")
+        })
+}
@@ -0,0 +1,94 @@
+use super::{
+    authorization, observe_project, resolve_route, vertex_base_url, CONTENT_TYPE_JSON,
+    HEADER_USER_PROJECT,
+};
+use crate::auth::{check_model, ApiKey};
+use crate::config::Backend;
+use crate::context::RequestContext;
+use crate::error::GatewayError;
+use crate::metrics;
+use crate::models::gemini::CountTokensResponse;
+use crate::models::{ChatCompletionRequest, TokenCount};
+use crate::retry;
+use crate::state::AppState;
+use crate::translate::gemini::{to_count_tokens, to_token_count};
+use crate::translate::model_id;
+use axum::{
+    extract::{Extension, State},
+    Json,
+};
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// 令牌计数接口 - POST
+///
+/// 接受 OpenAI 聊天请求,将消息转换为 Gemini contents 后调用 Vertex AI `:countTokens`,
+/// 返回总 token 数和按模态的明细。与聊天接口使用同样的路由解析
+pub async fn count_tokens(
+    State(state): State<Arc<AppState>>,
+    Extension(context): Extension<Arc<RequestContext>>,
+    api_key: Option<Extension<Arc<ApiKey>>>,
+    body: String,
+) -> Result<Json<TokenCount>, GatewayError> {
+    let start = Instant::now();
+    let request: ChatCompletionRequest = serde_json::from_str(&body).map_err(|e| {
+        tracing::error!("Failed to deserialize count tokens request: {}", e);
+        GatewayError::bad_request(format!("Invalid count tokens request: {e}"))
+    })?;
+    context.set_model(&request.model);
+    let model_id = model_id(&request.model)?;
+    check_model(api_key.as_deref().map(Arc::as_ref), &request.model)?;
+    let count_request = to_count_tokens(&request).map_err(|e| {
+        tracing::error!("Failed to translate request to Gemini format: {}", e);
+        GatewayError::bad_request(e)
+    })?;
+
+    // 1. 解析路由并获取所选项目的认证令牌
+    let (route, lease) = resolve_route(&state, &context, &request.model);
+    if route.backend == Backend::Anthropic {
+        return Err(GatewayError::bad_request(format!(
+            "Token counting is not supported for model '{}'",
+            request.model
+        ))
+        .with_param("model"));
+    }
+    let auth_header = authorization(&lease).await?;
+
+    // 2. 调用 countTokens,失败时按区域重试
+    let project_id = &route.project_id;
+    let build = |location: &str| {
+        let url = format!(
+            "{}/v1beta1/projects/{project_id}/locations/{location}/publishers/google/models/{model_id}:countTokens",
+            vertex_base_url(location)
+        );
+        tracing::debug!("Forwarding count tokens request to: {}", url);
+        state
+            .http_client
+            .post(url)
+            .header(AUTHORIZATION, auth_header.clone())
+            .header(HEADER_USER_PROJECT.clone(), project_id.as_str())
+            .header(CONTENT_TYPE, CONTENT_TYPE_JSON.clone())
+            .json(&count_request)
+    };
+    let result = retry::send_with_retry(
+        &state.config.retry,
+        context.model_label(),
+        &route.locations(),
+        route.timeouts,
+        false,
+        build,
+    )
+    .await;
+    observe_project(&lease, &result);
+    context.record_upstream(&result);
+    let (response, _) = result?;
+    metrics::record_upstream_latency(context.model_label(), start.elapsed());
+
+    // 3. 转换响应
+    let count: CountTokensResponse = response.json().await.map_err(|e| {
+        tracing::error!("Failed to parse countTokens response: {}", e);
+        GatewayError::bad_gateway(format!("Invalid response from Vertex AI: {e}"))
+    })?;
+    Ok(Json(to_token_count(count, &request.model)))
+}
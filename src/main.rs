@@ -1,15 +1,35 @@
+mod access_log;
+mod auth;
+mod billing;
+mod cache;
+mod config;
+mod context;
+mod error;
 mod gcp;
 mod handlers;
+mod metrics;
 mod models;
+mod ratelimit;
+mod retry;
 mod routes;
+mod routing;
+mod sse;
 mod state;
+mod telemetry;
+mod timeout;
+mod translate;
+mod usage;
 
 use clap::Parser;
 use std::fs::File;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tracing_subscriber::fmt::writer::MakeWriterExt;
+use tracing_subscriber::filter::{filter_fn, FilterExt};
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriterExt};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 
 #[cfg(unix)]
 use daemonize::Daemonize;
@@ -17,6 +37,7 @@ use daemonize::Daemonize;
 #[cfg(unix)]
 use std::process::exit;
 
+use crate::config::{Config, LogFormat};
 use crate::routes::create_routes;
 use crate::state::AppState;
 
@@ -36,6 +57,10 @@ struct Args {
     /// 工作目录
     #[arg(long, default_value = ".")]
     working_dir: PathBuf,
+
+    /// 配置文件路径(TOML/YAML),环境变量优先于配置文件
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
 }
 
 #[cfg(unix)]
@@ -101,6 +126,10 @@ struct Args {
     /// 工作目录
     #[arg(long, default_value = ".")]
     working_dir: PathBuf,
+
+    /// 配置文件路径(TOML/YAML),环境变量优先于配置文件
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
 }
 
 #[cfg(not(unix))]
@@ -120,13 +149,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     load_env();
 
     match args.command {
-        Some(Command::Start) => start_daemon(args),
+        Some(Command::Start) => {
+            let config = load_config(&args);
+            start_daemon(args, config)
+        }
         Some(Command::Stop) => stop_daemon(args),
-        Some(Command::Restart) => restart_daemon(args),
+        Some(Command::Restart) => {
+            let config = load_config(&args);
+            restart_daemon(args, config)
+        }
         Some(Command::Status) => show_status(args),
         None => {
             // 无子命令时,前台运行
-            run_foreground(args)
+            let config = load_config(&args);
+            run_foreground(args, config)
         }
     }
 }
@@ -153,7 +189,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     eprintln!();
 
     // 前台运行
-    run_foreground(args)
+    let config = load_config(&args);
+    run_foreground(args, config)
 }
 
 // ============= 通用函数 =============
@@ -172,10 +209,21 @@ fn load_env() {
     }
 }
 
+/// 加载并校验配置,失败时打印错误并退出
+fn load_config(args: &Args) -> Config {
+    match Config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("✗ 配置错误: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 // ============= Unix 平台进程管理函数 =============
 #[cfg(unix)]
 /// 启动守护进程
-fn start_daemon(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+fn start_daemon(args: Args, config: Config) -> Result<(), Box<dyn std::error::Error>> {
     if args.is_running() {
         eprintln!("✗ 服务已经在运行中 (PID: {})", args.read_pid().unwrap());
         exit(1);
@@ -186,14 +234,20 @@ fn start_daemon(args: Args) -> Result<(), Box<dyn std::error::Error>> {
     // 关键:在创建 Tokio 运行时之前先 daemonize
     daemonize_process(&args)?;
 
-    // 初始化日志(在 daemonize 之后)
-    init_logging(&args, true)?;
-
     // 现在启动 Tokio 运行时
-    tokio::runtime::Builder::new_multi_thread()
+    let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
-        .build()?
-        .block_on(async_main(args, true))
+        .build()?;
+
+    // 初始化日志(在 daemonize 之后,OTLP 导出器需要运行时上下文)
+    {
+        let _guard = runtime.enter();
+        init_logging(&args, &config, true)?;
+    }
+
+    let result = runtime.block_on(async_main(args, config, true));
+    telemetry::shutdown();
+    result
 }
 
 #[cfg(unix)]
@@ -234,7 +288,7 @@ fn stop_daemon(args: Args) -> Result<(), Box<dyn std::error::Error>> {
 
 #[cfg(unix)]
 /// 重启守护进程
-fn restart_daemon(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+fn restart_daemon(args: Args, config: Config) -> Result<(), Box<dyn std::error::Error>> {
     println!("正在重启 vertex-oai...");
 
     if args.is_running() {
@@ -242,7 +296,7 @@ fn restart_daemon(args: Args) -> Result<(), Box<dyn std::error::Error>> {
         std::thread::sleep(std::time::Duration::from_secs(1));
     }
 
-    start_daemon(args)
+    start_daemon(args, config)
 }
 
 #[cfg(unix)]
@@ -265,28 +319,37 @@ fn show_status(args: Args) -> Result<(), Box<dyn std::error::Error>> {
 
 // ============= 通用函数 =============
 /// 前台运行
-fn run_foreground(args: Args) -> Result<(), Box<dyn std::error::Error>> {
-    // 初始化日志
-    init_logging(&args, false)?;
-
+fn run_foreground(args: Args, config: Config) -> Result<(), Box<dyn std::error::Error>> {
     // 启动 Tokio 运行时
-    tokio::runtime::Builder::new_multi_thread()
+    let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
-        .build()?
-        .block_on(async_main(args, false))
+        .build()?;
+
+    // 初始化日志(OTLP 导出器需要运行时上下文)
+    {
+        let _guard = runtime.enter();
+        init_logging(&args, &config, false)?;
+    }
+
+    let result = runtime.block_on(async_main(args, config, false));
+    telemetry::shutdown();
+    result
 }
 
-async fn async_main(args: Args, daemon: bool) -> Result<(), Box<dyn std::error::Error>> {
+async fn async_main(
+    args: Args,
+    config: Config,
+    daemon: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = config.listen_addr();
+
     // 创建应用状态
-    let state = Arc::new(AppState::new().await?);
+    let state = Arc::new(AppState::new(config).await?);
 
     // 构建路由
     let app = create_routes(state);
 
-    // 从环境变量获取端口,默认为 8087
-    let port = std::env::var("PORT").unwrap_or_else(|_| "8087".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    let listener = TcpListener::bind(&addr).await?;
+    let listener = TcpListener::bind(addr).await?;
 
     tracing::info!("========================================");
     tracing::info!("Vertex-OAI v{}", env!("CARGO_PKG_VERSION"));
@@ -300,6 +363,9 @@ async fn async_main(args: Args, daemon: bool) -> Result<(), Box<dyn std::error::
     tracing::info!("========================================");
     tracing::info!("Server listening on: http://{}", addr);
     tracing::info!("Daemon mode: {}", daemon);
+    if let Some(config_file) = &args.config {
+        tracing::info!("Config file: {}", config_file.display());
+    }
     if daemon {
         tracing::info!("PID file: {}", args.pid_file().display());
         tracing::info!("Log file: {}", args.log_file.display());
@@ -412,17 +478,39 @@ fn daemonize_process(_args: &Args) -> Result<(), Box<dyn std::error::Error>> {
 }
 
 /// 初始化日志系统
-fn init_logging(args: &Args, daemon: bool) -> Result<(), Box<dyn std::error::Error>> {
-    if daemon {
+fn init_logging(
+    args: &Args,
+    config: &Config,
+    daemon: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = tracing_subscriber::EnvFilter::try_new(&config.logging.level)?;
+    let writer = if daemon {
         // 守护进程模式:日志输出到文件
         let log_file = File::create(&args.log_file)?;
-        tracing_subscriber::fmt()
-            .with_writer(log_file.and(std::io::stdout))
-            .with_ansi(false) // 文件日志不需要颜色
-            .init();
+        BoxMakeWriter::new(log_file.and(std::io::stdout))
     } else {
         // 前台模式:日志输出到控制台
-        tracing_subscriber::fmt().with_ansi(true).init();
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(!daemon && config.logging.format == LogFormat::Human); // 文件日志不需要颜色
+    let fmt = match config.logging.format {
+        LogFormat::Human => fmt.boxed(),
+        // 每行一个 JSON 对象,事件字段平铺在顶层,便于日志系统采集和检索
+        LogFormat::Json => fmt.json().flatten_event(true).boxed(),
+    };
+    // 日志级别只作用于日志输出,追踪导出层有自己的过滤规则
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(filter.and(filter_fn(|m| m.target() != telemetry::TARGET))))
+        .with(telemetry::layer(&config.telemetry)?)
+        .init();
+    if config.telemetry.enabled {
+        tracing::info!(
+            "OpenTelemetry tracing enabled ({:?}, endpoint: {})",
+            config.telemetry.protocol,
+            config.telemetry.endpoint.as_deref().unwrap_or("default")
+        );
     }
     Ok(())
 }
@@ -0,0 +1,260 @@
+//! Prometheus 指标
+//!
+//! 通过 `/metrics` 暴露,包括请求计数、上游延迟、流式首字节时间、
+//! 令牌刷新次数、模型缓存命中率、token 用量以及各 GCP 项目的负载
+
+use crate::models::Usage;
+use axum::{
+    body::Bytes,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::{Stream, StreamExt};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::time::{Duration, Instant};
+
+/// 未通过校验的模型在指标中的标签
+pub const UNKNOWN_MODEL: &str = "unknown";
+
+/// 延迟直方图的桶(秒),覆盖从毫秒级到长时间思考的请求
+const LATENCY_BUCKETS: &[f64] = &[
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+lazy_static! {
+    static ref REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_requests_total",
+        "Total number of requests handled by the gateway",
+        &["route", "model", "status"]
+    )
+    .unwrap();
+    static ref REQUESTS_CANCELLED: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_requests_cancelled_total",
+        "Requests abandoned by the client before completion",
+        &["route", "model", "phase"]
+    )
+    .unwrap();
+    static ref UPSTREAM_LATENCY: HistogramVec = register_histogram_vec!(
+        "vertex_oai_upstream_latency_seconds",
+        "Time until Vertex AI response headers are received",
+        &["model"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap();
+    static ref STREAM_TTFB: HistogramVec = register_histogram_vec!(
+        "vertex_oai_stream_ttfb_seconds",
+        "Time from request start to the first streamed body chunk",
+        &["model"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap();
+    static ref UPSTREAM_RETRIES: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_upstream_retries_total",
+        "Upstream attempts that failed with a retryable error",
+        &["model", "location", "reason"]
+    )
+    .unwrap();
+    static ref PROJECT_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_project_requests_total",
+        "Upstream requests by GCP project and outcome",
+        &["project", "outcome"]
+    )
+    .unwrap();
+    static ref PROJECT_IN_FLIGHT: IntGaugeVec = register_int_gauge_vec!(
+        "vertex_oai_project_in_flight",
+        "Requests currently assigned to each GCP project",
+        &["project"]
+    )
+    .unwrap();
+    static ref PROJECT_EJECTIONS: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_project_ejections_total",
+        "Times a GCP project was ejected after repeated quota errors",
+        &["project"]
+    )
+    .unwrap();
+    static ref TOKEN_REFRESHES: IntCounter = register_int_counter!(
+        "vertex_oai_token_refresh_total",
+        "Number of GCP access token refreshes"
+    )
+    .unwrap();
+    static ref MODELS_CACHE: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_models_cache_total",
+        "Models cache lookups by result",
+        &["result"]
+    )
+    .unwrap();
+    static ref CHAT_CACHE: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_chat_cache_total",
+        "Chat completion cache lookups by result",
+        &["model", "result"]
+    )
+    .unwrap();
+    static ref RATE_LIMITED: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_rate_limited_total",
+        "Requests rejected by client rate limits",
+        &["scope", "limit"]
+    )
+    .unwrap();
+    static ref TOKENS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "vertex_oai_tokens_total",
+        "Tokens reported in response usage",
+        &["model", "type"]
+    )
+    .unwrap();
+}
+
+/// 记录上游响应头到达时间
+pub fn record_upstream_latency(model: &str, elapsed: Duration) {
+    UPSTREAM_LATENCY
+        .with_label_values(&[model])
+        .observe(elapsed.as_secs_f64());
+}
+
+/// 记录一次可重试的上游失败
+pub fn record_retry(model: &str, location: &str, reason: &str) {
+    UPSTREAM_RETRIES
+        .with_label_values(&[model, location, reason])
+        .inc();
+}
+
+/// 请求分配到项目
+pub fn record_project_acquired(project: &str) {
+    PROJECT_IN_FLIGHT.with_label_values(&[project]).inc();
+}
+
+/// 项目上的请求结束
+pub fn record_project_released(project: &str) {
+    PROJECT_IN_FLIGHT.with_label_values(&[project]).dec();
+}
+
+/// 记录项目上一次上游请求的结果(success / quota_error / error)
+pub fn record_project_result(project: &str, outcome: &str) {
+    PROJECT_REQUESTS
+        .with_label_values(&[project, outcome])
+        .inc();
+}
+
+/// 记录一次项目摘除
+pub fn record_project_ejected(project: &str) {
+    PROJECT_EJECTIONS.with_label_values(&[project]).inc();
+}
+
+/// 记录一次令牌刷新
+pub fn record_token_refresh() {
+    TOKEN_REFRESHES.inc();
+}
+
+/// 记录模型缓存命中或未命中
+pub fn record_models_cache(hit: bool) {
+    MODELS_CACHE
+        .with_label_values(&[if hit { "hit" } else { "miss" }])
+        .inc();
+}
+
+/// 记录聊天补全缓存查询,`result` 为 hit、semantic_hit、miss 或 bypass(不可缓存或客户端要求跳过)
+pub fn record_chat_cache(model: &str, result: &str) {
+    CHAT_CACHE.with_label_values(&[model, result]).inc();
+}
+
+/// 记录响应中的 token 用量
+pub fn record_usage(model: &str, usage: &Usage) {
+    TOKENS_TOTAL
+        .with_label_values(&[model, "prompt"])
+        .inc_by(usage.prompt_tokens);
+    TOKENS_TOTAL
+        .with_label_values(&[model, "completion"])
+        .inc_by(usage.completion_tokens);
+    if let Some(details) = &usage.prompt_tokens_details {
+        TOKENS_TOTAL
+            .with_label_values(&[model, "cached"])
+            .inc_by(details.cached_tokens);
+    }
+    if let Some(details) = &usage.completion_tokens_details {
+        TOKENS_TOTAL
+            .with_label_values(&[model, "reasoning"])
+            .inc_by(details.reasoning_tokens);
+    }
+}
+
+/// 记录一次被限流拒绝的请求
+pub fn record_rate_limited(scope: &str, limit: &str) {
+    RATE_LIMITED.with_label_values(&[scope, limit]).inc();
+}
+
+/// 记录一次请求
+pub fn record_request(route: &str, model: &str, status: StatusCode) {
+    REQUESTS_TOTAL
+        .with_label_values(&[route, model, status.as_str()])
+        .inc();
+}
+
+/// 记录一次客户端断开导致的取消,`phase` 为 `request`(响应头返回前)或 `stream`
+pub fn record_cancelled(route: &str, model: &str, phase: &str) {
+    REQUESTS_CANCELLED
+        .with_label_values(&[route, model, phase])
+        .inc();
+}
+
+/// `/metrics` 接口,输出 Prometheus 文本格式
+pub async fn metrics_handler() -> Response {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        tracing::error!("Failed to encode metrics: {}", e);
+    }
+    ([(CONTENT_TYPE, encoder.format_type().to_owned())], buffer).into_response()
+}
+
+/// 响应体观察者,统计流式响应的首字节时间
+///
+/// token 用量由请求上下文中间件从响应摘要中统一记录
+pub struct BodyObserver {
+    model: String,
+    start: Instant,
+    stream: bool,
+    first_byte_seen: bool,
+}
+
+impl BodyObserver {
+    /// `model` 为模型标签,见 [`crate::context::RequestContext::model_label`]
+    pub fn new(model: &str, start: Instant, stream: bool) -> Self {
+        Self {
+            model: model.to_owned(),
+            start,
+            stream,
+            first_byte_seen: false,
+        }
+    }
+
+    fn on_chunk(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() || self.first_byte_seen {
+            return;
+        }
+        self.first_byte_seen = true;
+        if self.stream {
+            STREAM_TTFB
+                .with_label_values(&[self.model.as_str()])
+                .observe(self.start.elapsed().as_secs_f64());
+        }
+    }
+}
+
+/// 为响应体附加观察者
+pub fn instrument_body<S, E>(
+    stream: S,
+    mut observer: BodyObserver,
+) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send,
+{
+    stream.map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            observer.on_chunk(bytes);
+        }
+        chunk
+    })
+}
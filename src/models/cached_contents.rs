@@ -0,0 +1,116 @@
+use super::gemini::{Content, GeminiTool, ToolConfig};
+use super::{Message, Tool, ToolChoice};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// ============= 客户端请求和响应 =============
+
+/// 创建上下文缓存请求,内容使用 OpenAI 消息格式
+#[derive(Debug, Deserialize)]
+pub struct CreateCachedContentRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default)]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// 存活时间(秒),与 `expire_time` 二选一,都未设置时由 Vertex AI 决定(默认 1 小时)
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+    #[serde(default)]
+    pub expire_time: Option<DateTime<Utc>>,
+}
+
+/// 延长上下文缓存请求,`ttl_seconds` 与 `expire_time` 必须且只能设置一个
+#[derive(Debug, Deserialize)]
+pub struct UpdateCachedContentRequest {
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+    #[serde(default)]
+    pub expire_time: Option<DateTime<Utc>>,
+}
+
+/// 上下文缓存对象
+#[derive(Debug, Serialize)]
+pub struct CachedContentObject {
+    /// 完整的资源名,聊天请求通过 `cached_content` 引用
+    pub id: String,
+    pub object: &'static str,
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<CachedContentUsage>,
+}
+
+/// 缓存内容的 token 数,创建时按输入 token 计费
+#[derive(Debug, Serialize)]
+pub struct CachedContentUsage {
+    pub prompt_tokens: u64,
+    pub total_tokens: u64,
+}
+
+/// 上下文缓存列表
+#[derive(Debug, Serialize)]
+pub struct CachedContentList {
+    pub object: &'static str,
+    pub data: Vec<CachedContentObject>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+
+// ============= Vertex AI cachedContents 结构 =============
+
+/// Vertex AI `cachedContents` 资源
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct VertexCachedContent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contents: Vec<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<GeminiTool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
+    /// 形如 "3600s"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing)]
+    pub create_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing)]
+    pub update_time: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing)]
+    pub usage_metadata: Option<CachedContentUsageMetadata>,
+}
+
+/// 缓存内容的用量元数据
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedContentUsageMetadata {
+    #[serde(default)]
+    pub total_token_count: u64,
+}
+
+/// Vertex AI `cachedContents.list` 响应
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct VertexCachedContentList {
+    #[serde(default)]
+    pub cached_contents: Vec<VertexCachedContent>,
+    #[serde(default)]
+    pub next_page_token: Option<String>,
+}
@@ -0,0 +1,90 @@
+//! OpenAI 文本补全(legacy completions)数据结构
+//!
+//! 用于 `/v1/completions` 接口,请求包装为聊天完成后再调用 Vertex AI
+
+use super::{StopSequences, StreamOptions, Usage};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 文本补全请求
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub prompt: Prompt,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub n: Option<u32>,
+    #[serde(default)]
+    pub stop: Option<StopSequences>,
+    /// 在补全结果前附加原始提示
+    #[serde(default)]
+    pub echo: Option<bool>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub seed: Option<i64>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default)]
+    pub user: Option<String>,
+    /// 不支持,设置时返回 400
+    #[serde(default)]
+    pub logprobs: Option<u32>,
+    /// 不支持,设置时返回 400
+    #[serde(default)]
+    pub suffix: Option<String>,
+}
+
+impl CompletionRequest {
+    /// 是否为流式请求
+    pub fn is_stream(&self) -> bool {
+        self.stream.unwrap_or(false)
+    }
+
+    /// 是否需要在结果前附加提示
+    pub fn echo(&self) -> bool {
+        self.echo.unwrap_or(false)
+    }
+}
+
+/// 提示: 单个字符串、字符串数组或 token 数组
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Prompt {
+    Single(String),
+    Multiple(Vec<String>),
+    /// token ID 形式的提示,Vertex AI 不支持
+    Tokens(IgnoredAny),
+}
+
+/// 文本补全响应,流式块使用同样的结构
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionResponse {
+    pub id: String,
+    pub object: &'static str,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// 单个补全结果
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionChoice {
+    pub text: String,
+    pub index: u32,
+    /// 不支持 logprobs,始终为 null
+    pub logprobs: Option<Value>,
+    pub finish_reason: Option<String>,
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+// ============= OpenAI 嵌入接口结构 =============
+
+/// OpenAI 嵌入请求
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub input: EmbeddingInput,
+    pub dimensions: Option<u32>,
+    #[serde(default)]
+    pub encoding_format: EncodingFormat,
+    #[allow(dead_code)]
+    pub user: Option<String>,
+}
+
+/// 嵌入输入,支持单个字符串或字符串数组
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl EmbeddingInput {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            EmbeddingInput::Single(s) => vec![s],
+            EmbeddingInput::Multiple(v) => v,
+        }
+    }
+}
+
+/// 嵌入向量的编码格式
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EncodingFormat {
+    #[default]
+    Float,
+    Base64,
+}
+
+/// OpenAI 嵌入响应
+#[derive(Debug, Serialize)]
+pub struct EmbeddingResponse {
+    pub object: &'static str,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+/// 单条嵌入结果
+#[derive(Debug, Serialize)]
+pub struct EmbeddingData {
+    pub object: &'static str,
+    pub index: usize,
+    pub embedding: EmbeddingVector,
+}
+
+/// 嵌入向量,float 格式为数组,base64 格式为小端 f32 字节的 base64 编码
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EmbeddingVector {
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+/// 嵌入接口的 token 用量
+#[derive(Debug, Serialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: u64,
+    pub total_tokens: u64,
+}
+
+// ============= Vertex AI predict 接口结构 =============
+
+/// Vertex AI 嵌入 predict 请求
+#[derive(Debug, Serialize)]
+pub struct VertexEmbeddingRequest {
+    pub instances: Vec<VertexEmbeddingInstance>,
+    pub parameters: VertexEmbeddingParameters,
+}
+
+/// Vertex AI 嵌入实例
+#[derive(Debug, Serialize)]
+pub struct VertexEmbeddingInstance {
+    pub content: String,
+}
+
+/// Vertex AI 嵌入参数
+#[derive(Debug, Serialize)]
+pub struct VertexEmbeddingParameters {
+    #[serde(
+        rename = "outputDimensionality",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_dimensionality: Option<u32>,
+    #[serde(rename = "autoTruncate")]
+    pub auto_truncate: bool,
+}
+
+/// Vertex AI 嵌入 predict 响应
+#[derive(Debug, Deserialize)]
+pub struct VertexEmbeddingResponse {
+    #[serde(default)]
+    pub predictions: Vec<VertexEmbeddingPrediction>,
+}
+
+/// Vertex AI 单条预测结果
+#[derive(Debug, Deserialize)]
+pub struct VertexEmbeddingPrediction {
+    pub embeddings: VertexEmbedding,
+}
+
+/// Vertex AI 嵌入向量及统计信息
+#[derive(Debug, Deserialize)]
+pub struct VertexEmbedding {
+    pub values: Vec<f32>,
+    pub statistics: Option<VertexEmbeddingStatistics>,
+}
+
+/// Vertex AI 嵌入统计信息
+#[derive(Debug, Deserialize)]
+pub struct VertexEmbeddingStatistics {
+    #[serde(default)]
+    pub token_count: f64,
+}
@@ -1,21 +1,337 @@
+pub mod anthropic;
+pub mod cached_contents;
+pub mod completions;
+pub mod embeddings;
+pub mod gemini;
+pub mod responses;
+
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 
 /// OpenAI 消息格式
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<MessageContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// 消息内容,支持纯文本或多模态内容片段数组
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// 拼接所有文本片段,忽略非文本内容
+    pub fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(""),
+        }
+    }
+}
+
+/// 多模态内容片段
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    InputAudio { input_audio: InputAudio },
+}
+
+/// 图片地址,支持 http(s)/gs 链接和 data URL
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageUrl {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// base64 编码的音频输入
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InputAudio {
+    pub data: String,
+    pub format: String,
+}
+
+/// 工具调用
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolCall {
+    /// 仅在流式增量中使用
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    /// 流式增量中只在第一块出现
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(rename = "type", default = "default_tool_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+/// 工具调用的函数名及 JSON 字符串参数
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FunctionCall {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+/// 工具定义
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tool {
+    #[serde(rename = "type", default = "default_tool_type")]
+    pub kind: String,
+    pub function: FunctionDefinition,
+}
+
+/// 函数定义
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+fn default_tool_type() -> String {
+    "function".to_string()
+}
+
+/// 工具选择策略: `none`/`auto`/`required` 或指定函数
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ToolChoice {
+    Mode(String),
+    Named(NamedToolChoice),
+}
+
+/// 指定调用某个函数
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NamedToolChoice {
+    #[serde(rename = "type", default = "default_tool_type")]
+    pub kind: String,
+    pub function: FunctionName,
+}
+
+/// 函数名
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FunctionName {
+    pub name: String,
+}
+
+/// 停止序列,支持单个字符串或数组
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum StopSequences {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl StopSequences {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            StopSequences::Single(s) => vec![s.clone()],
+            StopSequences::Multiple(v) => v.clone(),
+        }
+    }
+}
+
+/// 响应格式
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema { json_schema: JsonSchemaFormat },
+}
+
+/// 结构化输出的 JSON Schema
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+/// 流式选项
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
 }
 
 /// OpenAI 聊天完成请求
-#[derive(Debug, Serialize, Deserialize)]
-#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct ChatCompletionRequest {
     pub model: String,
     pub messages: Vec<Message>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<StopSequences>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Gemini 安全设置,原样透传给 generateContent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety_settings: Option<Vec<Value>>,
+}
+
+impl ChatCompletionRequest {
+    /// 是否为流式请求
+    pub fn is_stream(&self) -> bool {
+        self.stream.unwrap_or(false)
+    }
+
+    /// 流式响应是否需要附带 usage 块
+    pub fn include_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .is_some_and(|o| o.include_usage)
+    }
+}
+
+/// OpenAI 聊天完成响应
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<Choice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// 非流式响应中的单个选项
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Choice {
+    pub index: u32,
+    pub message: Message,
+    pub finish_reason: Option<String>,
+}
+
+/// OpenAI 流式响应块
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: String,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<ChunkChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// 流式响应块中的单个选项
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChunkChoice {
+    pub index: u32,
+    pub delta: Delta,
+    pub finish_reason: Option<String>,
+}
+
+/// 流式增量内容
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Delta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+/// token 用量
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub total_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+/// 输入 token 明细
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
+}
+
+/// 输出 token 明细
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CompletionTokensDetails {
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+}
+
+/// 令牌计数响应
+#[derive(Debug, Serialize, Clone)]
+pub struct TokenCount {
+    pub object: &'static str,
+    pub model: String,
+    pub total_tokens: u64,
+    /// 按模态(text、image、audio、video、document)统计的 token 数
+    pub modalities: BTreeMap<String, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_billable_characters: Option<u64>,
 }
 
 /// OpenAI 模型信息
@@ -75,16 +391,17 @@ impl VertexModel {
             id: model_id,
             object: "model".to_string(),
             created: chrono::Utc::now().timestamp(),
-            owned_by: "google".to_string(),
+            owned_by: parts.get(1).unwrap_or(&"google").to_string(),
         }
     }
 
     /// 判断模型是否应该被包含在列表中
     ///
     /// 过滤条件:
+    /// - 只包含 Gemini 和 Claude 模型
     /// - 只包含 GA (正式发布) 和 PUBLIC_PREVIEW (公开预览) 的模型
     pub fn should_include(&self) -> bool {
-        self.name.contains("gemini")
+        (self.name.contains("gemini") || self.name.contains("claude"))
             && matches!(self.launch_stage.as_deref(), Some("GA") | Some("PUBLIC_PREVIEW"))
     }
 }
@@ -0,0 +1,249 @@
+//! OpenAI Responses API 数据结构
+//!
+//! 用于 `/v1/responses` 接口,请求翻译为聊天完成后再调用 Vertex AI
+
+use super::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Responses 请求
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    #[serde(default)]
+    pub input: Option<ResponseInput>,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    #[serde(default)]
+    pub previous_response_id: Option<String>,
+    #[serde(default)]
+    pub tools: Option<Vec<ResponseTool>>,
+    #[serde(default)]
+    pub tool_choice: Option<ResponseToolChoice>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub reasoning: Option<ReasoningParam>,
+    #[serde(default)]
+    pub text: Option<TextParam>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// 是否保存响应供 `previous_response_id` 使用,默认保存
+    #[serde(default)]
+    pub store: Option<bool>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+impl ResponsesRequest {
+    /// 是否为流式请求
+    pub fn is_stream(&self) -> bool {
+        self.stream.unwrap_or(false)
+    }
+}
+
+/// 输入: 纯文本或输入项列表
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ResponseInput {
+    Text(String),
+    Items(Vec<InputItem>),
+}
+
+/// 输入项
+///
+/// 消息项可以省略 `type`,因此不使用带标签的枚举
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InputItem {
+    /// message、function_call、function_call_output、reasoning 等,缺省为 message
+    #[serde(rename = "type", default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub content: Option<InputContent>,
+    #[serde(default)]
+    pub call_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub arguments: Option<String>,
+    /// 函数调用结果,字符串或内容片段数组
+    #[serde(default)]
+    pub output: Option<Value>,
+}
+
+/// 消息内容: 纯文本或内容片段列表
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum InputContent {
+    Text(String),
+    Parts(Vec<InputPart>),
+}
+
+/// 输入内容片段
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputPart {
+    InputText {
+        text: String,
+    },
+    /// 历史中的助手输出
+    OutputText {
+        text: String,
+    },
+    InputImage {
+        #[serde(default)]
+        image_url: Option<String>,
+        #[serde(default)]
+        detail: Option<String>,
+    },
+    Refusal {
+        refusal: String,
+    },
+    /// 暂不支持的片段类型
+    #[serde(other)]
+    Unknown,
+}
+
+/// 工具定义,只支持 function 类型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseTool {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+/// 工具选择: `none`/`auto`/`required` 或指定函数
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ResponseToolChoice {
+    Mode(String),
+    Function { name: String },
+}
+
+/// 推理参数
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReasoningParam {
+    #[serde(default)]
+    pub effort: Option<String>,
+}
+
+/// 文本输出参数
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextParam {
+    #[serde(default)]
+    pub format: Option<TextFormat>,
+}
+
+/// 文本输出格式
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextFormat {
+    Text,
+    JsonObject,
+    JsonSchema {
+        name: String,
+        #[serde(default)]
+        schema: Option<Value>,
+        #[serde(default)]
+        strict: Option<bool>,
+    },
+}
+
+/// Responses 响应对象
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseObject {
+    pub id: String,
+    pub object: &'static str,
+    pub created_at: i64,
+    /// in_progress、completed、incomplete 或 failed
+    pub status: &'static str,
+    pub model: String,
+    pub output: Vec<OutputItem>,
+    pub usage: Option<ResponseUsage>,
+    pub previous_response_id: Option<String>,
+    pub instructions: Option<String>,
+    pub incomplete_details: Option<Value>,
+    pub error: Option<Value>,
+    pub metadata: Value,
+}
+
+/// 输出项
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputItem {
+    Message {
+        id: String,
+        status: &'static str,
+        role: &'static str,
+        content: Vec<OutputContent>,
+    },
+    FunctionCall {
+        id: String,
+        call_id: String,
+        name: String,
+        arguments: String,
+        status: &'static str,
+    },
+    Reasoning {
+        id: String,
+        summary: Vec<OutputContent>,
+    },
+}
+
+/// 输出内容片段
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputContent {
+    OutputText {
+        text: String,
+        annotations: Vec<Value>,
+    },
+    SummaryText {
+        text: String,
+    },
+}
+
+/// token 用量
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResponseUsage {
+    pub input_tokens: u64,
+    pub input_tokens_details: InputTokensDetails,
+    pub output_tokens: u64,
+    pub output_tokens_details: OutputTokensDetails,
+    pub total_tokens: u64,
+}
+
+/// 输入 token 明细
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InputTokensDetails {
+    pub cached_tokens: u64,
+}
+
+/// 输出 token 明细
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OutputTokensDetails {
+    pub reasoning_tokens: u64,
+}
+
+/// 已保存的响应,用于 `previous_response_id` 续接对话和按 ID 查询
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    /// 创建该响应的客户端 API Key,只有同一个 Key 可以访问
+    pub owner: Option<String>,
+    /// 截至该响应的完整对话(不含 instructions),包括助手输出
+    pub messages: Vec<Message>,
+    pub response: ResponseObject,
+}
@@ -0,0 +1,550 @@
+//! 客户端限流
+//!
+//! 按 API Key 和全局两个维度限制每分钟请求数(RPM)、每分钟 token 数(TPM)和并发请求数。
+//! RPM/TPM 使用令牌桶,容量为每分钟限额并按秒平滑补充;token 数在请求前按请求体估算,
+//! 响应结束后按返回的 usage 校正。超限时返回 OpenAI 格式的 429,
+//! 并通过 `x-ratelimit-*` 响应头告知剩余额度
+
+use crate::auth::{ApiKey, KeyStore};
+use crate::cache;
+use crate::config::{LimitsConfig, RateLimitConfig};
+use crate::context::{ModelCall, RequestContext};
+use crate::error::GatewayError;
+use crate::metrics;
+use crate::models::Usage;
+use crate::state::AppState;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::LengthLimitError;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+static HEADER_LIMIT_REQUESTS: HeaderName = HeaderName::from_static("x-ratelimit-limit-requests");
+static HEADER_REMAINING_REQUESTS: HeaderName =
+    HeaderName::from_static("x-ratelimit-remaining-requests");
+static HEADER_RESET_REQUESTS: HeaderName = HeaderName::from_static("x-ratelimit-reset-requests");
+static HEADER_LIMIT_TOKENS: HeaderName = HeaderName::from_static("x-ratelimit-limit-tokens");
+static HEADER_REMAINING_TOKENS: HeaderName =
+    HeaderName::from_static("x-ratelimit-remaining-tokens");
+static HEADER_RESET_TOKENS: HeaderName = HeaderName::from_static("x-ratelimit-reset-tokens");
+static HEADER_RETRY_AFTER: HeaderName = HeaderName::from_static("retry-after");
+
+/// 令牌桶,容量为 0 表示不限制
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u64, now: Instant) -> Self {
+        Self {
+            capacity: per_minute as f64,
+            available: per_minute as f64,
+            updated: now,
+        }
+    }
+
+    fn is_limited(&self) -> bool {
+        self.capacity > 0.0
+    }
+
+    /// 按经过的时间补充额度
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// 额度足够取出 `amount` 还需等待的时间
+    fn wait_for(&self, amount: f64) -> Duration {
+        if !self.is_limited() || self.available >= amount {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((amount - self.available) * 60.0 / self.capacity)
+    }
+
+    /// 调整额度,校正用量时可能为负
+    fn adjust(&mut self, amount: f64) {
+        self.available = (self.available + amount).min(self.capacity);
+    }
+
+    fn remaining(&self) -> u64 {
+        self.available.max(0.0) as u64
+    }
+}
+
+/// 一个限流维度(全局或单个 Key)的状态
+#[derive(Debug)]
+struct LimiterState {
+    requests: Bucket,
+    tokens: Bucket,
+    in_flight: u64,
+}
+
+/// 一个限流维度
+#[derive(Debug)]
+struct Limiter {
+    /// 用于指标和错误信息: global 或 key
+    scope: &'static str,
+    limits: LimitsConfig,
+    state: Mutex<LimiterState>,
+}
+
+impl Limiter {
+    fn new(scope: &'static str, limits: LimitsConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            scope,
+            limits,
+            state: Mutex::new(LimiterState {
+                requests: Bucket::new(limits.rpm, now),
+                tokens: Bucket::new(limits.tpm, now),
+                in_flight: 0,
+            }),
+        }
+    }
+
+    /// 检查一次请求是否超限,不修改状态
+    fn check(&self, state: &LimiterState, tokens: u64) -> Result<(), GatewayError> {
+        let limits = &self.limits;
+        if limits.max_concurrent > 0 && state.in_flight >= limits.max_concurrent {
+            metrics::record_rate_limited(self.scope, "concurrency");
+            return Err(rate_limit_error(format!(
+                "Concurrency limit reached ({} scope): Limit {}, In flight {}. Please retry after an in-flight request completes.",
+                self.scope, limits.max_concurrent, state.in_flight
+            )));
+        }
+        let wait = state.requests.wait_for(1.0);
+        if !wait.is_zero() {
+            metrics::record_rate_limited(self.scope, "requests");
+            return Err(rate_limit_error(format!(
+                "Rate limit reached for requests per min ({} scope): Limit {}, Remaining {}. Please try again in {}.",
+                self.scope,
+                limits.rpm,
+                state.requests.remaining(),
+                format_reset(wait)
+            )));
+        }
+        if limits.tpm > 0 && tokens > limits.tpm {
+            metrics::record_rate_limited(self.scope, "tokens");
+            return Err(rate_limit_error(format!(
+                "Request too large for tokens per min ({} scope): Limit {}, Requested {}. Please reduce the input or max_tokens.",
+                self.scope, limits.tpm, tokens
+            )));
+        }
+        let wait = state.tokens.wait_for(tokens as f64);
+        if !wait.is_zero() {
+            metrics::record_rate_limited(self.scope, "tokens");
+            return Err(rate_limit_error(format!(
+                "Rate limit reached for tokens per min ({} scope): Limit {}, Remaining {}, Requested {}. Please try again in {}.",
+                self.scope,
+                limits.tpm,
+                state.tokens.remaining(),
+                tokens,
+                format_reset(wait)
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn rate_limit_error(message: String) -> GatewayError {
+    GatewayError::new(StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", message)
+        .with_code("rate_limit_exceeded")
+}
+
+/// 限流器
+///
+/// 全局维度在启动时创建,Key 维度在首次请求时按 Key 的阈值创建
+#[derive(Debug)]
+pub struct RateLimiter {
+    global: Option<Arc<Limiter>>,
+    per_key: LimitsConfig,
+    keys: Mutex<HashMap<String, Arc<Limiter>>>,
+}
+
+impl RateLimiter {
+    /// 创建限流器,未配置任何限制时返回 `None`
+    pub fn new(config: &RateLimitConfig, key_store: Option<&KeyStore>) -> Option<Self> {
+        let keys_limited = key_store.is_some_and(|store| {
+            store
+                .iter()
+                .any(|key| !key.limits(&config.per_key).is_unlimited())
+        });
+        if config.global.is_unlimited() && !keys_limited {
+            return None;
+        }
+        Some(Self {
+            global: (!config.global.is_unlimited())
+                .then(|| Arc::new(Limiter::new("global", config.global))),
+            per_key: config.per_key,
+            keys: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Key 维度的限流器,该 Key 未设置限制时返回 `None`
+    fn key_limiter(&self, api_key: &ApiKey) -> Option<Arc<Limiter>> {
+        let limits = api_key.limits(&self.per_key);
+        if limits.is_unlimited() {
+            return None;
+        }
+        let mut keys = self.keys.lock().unwrap();
+        let limiter = keys
+            .entry(api_key.key.clone())
+            .or_insert_with(|| Arc::new(Limiter::new("key", limits)));
+        Some(limiter.clone())
+    }
+
+    /// 为一次请求申请额度,所有维度都通过后才扣减
+    pub fn acquire(&self, api_key: Option<&ApiKey>, tokens: u64) -> Result<Permit, Box<Rejection>> {
+        let limiters: Vec<Arc<Limiter>> = api_key
+            .and_then(|key| self.key_limiter(key))
+            .into_iter()
+            .chain(self.global.clone())
+            .collect();
+        let now = Instant::now();
+        // 固定按 Key、全局的顺序加锁
+        let mut states: Vec<MutexGuard<LimiterState>> =
+            limiters.iter().map(|l| l.state.lock().unwrap()).collect();
+        for state in states.iter_mut() {
+            state.requests.refill(now);
+            state.tokens.refill(now);
+        }
+
+        for (limiter, state) in limiters.iter().zip(&states) {
+            if let Err(error) = limiter.check(state, tokens) {
+                let mut headers = snapshot(&limiters, &states);
+                let retry_after = state
+                    .requests
+                    .wait_for(1.0)
+                    .max(state.tokens.wait_for(tokens as f64))
+                    .max(Duration::from_secs(1));
+                headers.insert(
+                    HEADER_RETRY_AFTER.clone(),
+                    HeaderValue::from(retry_after.as_secs_f64().ceil() as u64),
+                );
+                return Err(Box::new(Rejection { error, headers }));
+            }
+        }
+
+        for state in states.iter_mut() {
+            state.requests.adjust(-1.0);
+            state.tokens.adjust(-(tokens as f64));
+            state.in_flight += 1;
+        }
+        let headers = snapshot(&limiters, &states);
+        drop(states);
+        Ok(Permit {
+            limiters,
+            estimated: tokens,
+            headers,
+        })
+    }
+}
+
+/// 生成 `x-ratelimit-*` 响应头,每一项取第一个设置了该限制的维度(Key 优先)
+fn snapshot(limiters: &[Arc<Limiter>], states: &[MutexGuard<LimiterState>]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let pairs = || limiters.iter().zip(states);
+    if let Some((limiter, state)) = pairs().find(|(l, _)| l.limits.rpm > 0) {
+        headers.insert(
+            HEADER_LIMIT_REQUESTS.clone(),
+            HeaderValue::from(limiter.limits.rpm),
+        );
+        headers.insert(
+            HEADER_REMAINING_REQUESTS.clone(),
+            HeaderValue::from(state.requests.remaining()),
+        );
+        let reset = state.requests.wait_for(state.requests.capacity);
+        if let Ok(value) = HeaderValue::from_str(&format_reset(reset)) {
+            headers.insert(HEADER_RESET_REQUESTS.clone(), value);
+        }
+    }
+    if let Some((limiter, state)) = pairs().find(|(l, _)| l.limits.tpm > 0) {
+        headers.insert(
+            HEADER_LIMIT_TOKENS.clone(),
+            HeaderValue::from(limiter.limits.tpm),
+        );
+        headers.insert(
+            HEADER_REMAINING_TOKENS.clone(),
+            HeaderValue::from(state.tokens.remaining()),
+        );
+        let reset = state.tokens.wait_for(state.tokens.capacity);
+        if let Ok(value) = HeaderValue::from_str(&format_reset(reset)) {
+            headers.insert(HEADER_RESET_TOKENS.clone(), value);
+        }
+    }
+    headers
+}
+
+/// 按 OpenAI 的格式输出重置时间,如 `120ms`、`1s`、`6m0s`
+fn format_reset(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        return format!("{millis}ms");
+    }
+    let secs = duration.as_secs_f64().ceil() as u64;
+    if secs < 60 {
+        format!("{secs}s")
+    } else {
+        format!("{}m{}s", secs / 60, secs % 60)
+    }
+}
+
+/// 超限拒绝,带 `x-ratelimit-*` 和 `retry-after` 响应头
+#[derive(Debug)]
+pub struct Rejection {
+    error: GatewayError,
+    headers: HeaderMap,
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        (self.headers, self.error).into_response()
+    }
+}
+
+/// 已申请的额度
+///
+/// 持有期间计入并发数,丢弃时释放
+#[derive(Debug)]
+pub struct Permit {
+    limiters: Vec<Arc<Limiter>>,
+    /// 申请时预估的 token 数
+    estimated: u64,
+    headers: HeaderMap,
+}
+
+impl Permit {
+    /// 按实际用量校正 TPM 额度,多退少补
+    fn settle(&mut self, used: u64) {
+        let delta = self.estimated as f64 - used as f64;
+        let now = Instant::now();
+        for limiter in &self.limiters {
+            let mut state = limiter.state.lock().unwrap();
+            state.tokens.refill(now);
+            state.tokens.adjust(delta);
+        }
+        self.estimated = used;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        for limiter in &self.limiters {
+            let mut state = limiter.state.lock().unwrap();
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+/// 估算请求消耗的 token 数
+///
+/// 输入按文本约 4 字符 1 个 token 估算(跳过 base64 图片等内联数据),
+/// 输出按请求的最大输出 token 数估算;无法解析为 JSON 时按字节数估算输入
+pub fn estimate_usage(body: &[u8]) -> Usage {
+    let (prompt_tokens, completion_tokens) = match serde_json::from_slice::<Value>(body) {
+        Ok(value) => {
+            let max_output = ["max_tokens", "max_completion_tokens", "max_output_tokens"]
+                .iter()
+                .find_map(|key| value.get(key)?.as_u64())
+                .or_else(|| value.pointer("/generationConfig/maxOutputTokens")?.as_u64())
+                .unwrap_or(0);
+            (text_len(&value).div_ceil(4) as u64, max_output)
+        }
+        Err(_) => (body.len().div_ceil(4) as u64, 0),
+    };
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+        ..Default::default()
+    }
+}
+
+/// JSON 中文本的总字符数,跳过 `data` 字段和 data URL
+fn text_len(value: &Value) -> usize {
+    match value {
+        Value::String(s) if s.starts_with("data:") => 0,
+        Value::String(s) => s.chars().count(),
+        Value::Array(items) => items.iter().map(text_len).sum(),
+        Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| key.as_str() != "data")
+            .map(|(_, v)| text_len(v))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// 读取请求体,超过 `limit` 字节时返回 413
+async fn read_body(body: Body, limit: usize) -> Result<Bytes, GatewayError> {
+    axum::body::to_bytes(body, limit).await.map_err(|e| {
+        let e = e.into_inner();
+        if e.is::<LengthLimitError>() {
+            GatewayError::payload_too_large(format!(
+                "Request body exceeds the limit of {limit} bytes"
+            ))
+        } else {
+            GatewayError::bad_request(format!("Failed to read request body: {e}"))
+        }
+    })
+}
+
+/// 限流中间件,位于认证和计费之后
+///
+/// 只处理调用模型的路由(不论请求方法)。请求体被完整读取用于估算 token 数,估算值记入请求上下文供计费使用;
+/// 启用限流时成功响应在响应体结束后按 usage 校正,失败响应退还预估的 token 额度
+pub async fn enforce(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let context = request.extensions().get::<Arc<RequestContext>>().cloned();
+    if context
+        .as_ref()
+        .map_or(true, |c| c.model_call() == ModelCall::None)
+    {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = match read_body(body, state.config.server.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(e) => return e.into_response(),
+    };
+    let estimated = estimate_usage(&bytes);
+    let tokens = estimated.total_tokens;
+    if let Some(context) = &context {
+        context.set_estimated_usage(estimated);
+    }
+    let request = Request::from_parts(parts, Body::from(bytes));
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(request).await;
+    };
+
+    let api_key = request.extensions().get::<Arc<ApiKey>>().cloned();
+    let mut permit = match limiter.acquire(api_key.as_deref(), tokens) {
+        Ok(permit) => permit,
+        Err(rejection) => {
+            tracing::warn!(
+                "Rate limited request from {}: {}",
+                api_key.as_ref().map_or("anonymous", |k| k.name.as_str()),
+                rejection.error.message
+            );
+            return rejection.into_response();
+        }
+    };
+
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .extend(std::mem::take(&mut permit.headers));
+    // 失败和命中响应缓存的请求不消耗上游 token
+    if !response.status().is_success() || cache::is_hit(&response) {
+        permit.settle(0);
+        return response;
+    }
+
+    let Some(context) = context else {
+        return response;
+    };
+    // 许可随回调一起保留到响应体发送完毕,按计费用量校正: 生成请求没有 usage
+    // (如客户端提前断开)时保留预估值,计数 token 等不生成内容的请求没有 usage 时退还
+    context.on_complete(move |context| {
+        permit.settle(context.billable_usage().map_or(0, |u| u.total_tokens));
+    });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn limiter(global: LimitsConfig) -> RateLimiter {
+        let config = RateLimitConfig {
+            global,
+            ..Default::default()
+        };
+        RateLimiter::new(&config, None).unwrap()
+    }
+
+    #[test]
+    fn test_acquire_enforces_requests_and_concurrency() {
+        let limiter = limiter(LimitsConfig {
+            rpm: 2,
+            max_concurrent: 1,
+            ..Default::default()
+        });
+
+        let first = limiter.acquire(None, 10).unwrap();
+        assert_eq!(first.headers[&HEADER_LIMIT_REQUESTS], "2");
+        assert_eq!(first.headers[&HEADER_REMAINING_REQUESTS], "1");
+        let rejected = limiter.acquire(None, 10).unwrap_err();
+        assert!(rejected.error.message.starts_with("Concurrency limit"));
+
+        drop(first);
+        let _second = limiter.acquire(None, 10).unwrap();
+        // 并发额度已释放,但 RPM 已用完
+        let rejected = limiter.acquire(None, 10).unwrap_err();
+        assert_eq!(rejected.error.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(rejected.error.code.as_deref(), Some("rate_limit_exceeded"));
+        assert!(rejected.headers.contains_key(&HEADER_RETRY_AFTER));
+    }
+
+    #[test]
+    fn test_settle_refunds_estimated_tokens() {
+        let limiter = limiter(LimitsConfig {
+            tpm: 1000,
+            ..Default::default()
+        });
+
+        let mut permit = limiter.acquire(None, 900).unwrap();
+        assert_eq!(permit.headers[&HEADER_REMAINING_TOKENS], "100");
+        assert!(limiter.acquire(None, 200).is_err());
+        permit.settle(100);
+        let permit = limiter.acquire(None, 200).unwrap();
+        assert_eq!(permit.headers[&HEADER_REMAINING_TOKENS], "700");
+        assert!(limiter
+            .acquire(None, 2000)
+            .unwrap_err()
+            .error
+            .message
+            .starts_with("Request too large"));
+    }
+
+    #[test]
+    fn test_estimate_usage_skips_inline_data() {
+        let body = json!({
+            "model": "gemini-2.5-flash",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "12345678"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+            ]}]
+        });
+        let expected = "gemini-2.5-flashuser12345678textimage_url"
+            .len()
+            .div_ceil(4) as u64
+            + 100;
+        let usage = estimate_usage(body.to_string().as_bytes());
+        assert_eq!(usage.total_tokens, expected);
+        assert_eq!(usage.completion_tokens, 100);
+    }
+
+    #[tokio::test]
+    async fn test_read_body_rejects_oversized_body() {
+        let bytes = read_body(Body::from("12345678"), 8).await.unwrap();
+        assert_eq!(bytes.as_ref(), b"12345678");
+
+        let error = read_body(Body::from("123456789"), 8).await.unwrap_err();
+        assert_eq!(error.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(error.body()["error"]["code"], "request_too_large");
+    }
+}
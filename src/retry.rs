@@ -0,0 +1,242 @@
+//! 上游请求重试与多区域故障转移
+//!
+//! 按路由中的区域顺序依次尝试,每个区域内对可重试的状态码(默认 429/503)
+//! 和连接错误进行指数退避重试,并遵循上游返回的 `Retry-After`。
+//! 重试只发生在拿到成功响应之前,因此不会重复发送已经流式返回给客户端的数据
+
+use crate::config::RetryConfig;
+use crate::error::GatewayError;
+use crate::metrics;
+use crate::telemetry;
+use crate::timeout::{FirstByteDeadline, Timeouts};
+use reqwest::header::RETRY_AFTER;
+use std::time::Duration;
+use tracing::{field::Empty, Instrument, Span};
+
+/// 发送请求,必要时重试或切换区域
+///
+/// `model` 为指标和日志中使用的模型标签(见 [`crate::context::RequestContext::model_label`]),
+/// `build` 根据区域构建请求,每次尝试都会重新调用。
+/// 非流式请求的总超时覆盖读取响应体;流式请求的首字节超时从发送时开始计算,
+/// 截止时间记入响应扩展,响应体由 [`crate::timeout::guard_response`] 按余下的时间限制。
+/// 成功时返回响应以及实际使用的区域
+pub async fn send_with_retry<F>(
+    policy: &RetryConfig,
+    model: &str,
+    locations: &[String],
+    timeouts: Timeouts,
+    stream: bool,
+    build: F,
+) -> Result<(reqwest::Response, String), GatewayError>
+where
+    F: Fn(&str) -> reqwest::RequestBuilder,
+{
+    let mut last_error = GatewayError::bad_gateway("No upstream location available");
+
+    for (index, location) in locations.iter().enumerate() {
+        if index > 0 {
+            tracing::warn!("Failing over model {} to location {}", model, location);
+        }
+        for attempt in 0..policy.attempts_per_location {
+            let span = upstream_span(model, location, attempt);
+            let request = span.in_scope(|| telemetry::inject(build(location)));
+            let result = send(request, timeouts, stream)
+                .instrument(span.clone())
+                .await;
+            record_attempt(&span, &result);
+            let retry_after = match result {
+                Err(limit) => {
+                    tracing::error!(
+                        "Vertex AI in {} did not respond within {:?}",
+                        location,
+                        limit
+                    );
+                    last_error = GatewayError::gateway_timeout(format!(
+                        "Vertex AI did not respond within {limit:?}"
+                    ));
+                    metrics::record_retry(model, location, "timeout");
+                    None
+                }
+                Ok(Ok(response)) if response.status().is_success() => {
+                    return Ok((response, location.clone()));
+                }
+                Ok(Ok(response)) if policy.is_retryable(response.status().as_u16()) => {
+                    let retry_after = parse_retry_after(&response);
+                    let status = response.status();
+                    last_error = GatewayError::from_response(response).await;
+                    metrics::record_retry(model, location, status.as_str());
+                    retry_after
+                }
+                Ok(Ok(response)) => return Err(GatewayError::from_response(response).await),
+                Ok(Err(e)) if e.is_connect() || e.is_timeout() => {
+                    tracing::error!("Failed to reach Vertex AI in {}: {}", location, e);
+                    last_error = GatewayError::from_reqwest(&e);
+                    metrics::record_retry(model, location, "connection");
+                    None
+                }
+                Ok(Err(e)) => {
+                    tracing::error!("Failed to forward request to Vertex AI: {}", e);
+                    return Err(GatewayError::from_reqwest(&e));
+                }
+            };
+
+            if attempt + 1 >= policy.attempts_per_location {
+                break;
+            }
+            let delay = backoff(policy, attempt, retry_after);
+            if delay > policy.max_backoff() {
+                tracing::warn!(
+                    "Retry-After {:?} for {} in {} exceeds max backoff, skipping location",
+                    delay,
+                    model,
+                    location
+                );
+                break;
+            }
+            tracing::warn!(
+                "Retrying model {} in {} after {:?} (attempt {}/{})",
+                model,
+                location,
+                delay,
+                attempt + 2,
+                policy.attempts_per_location
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    Err(last_error)
+}
+
+/// 按超时配置发送一次请求,流式请求等待响应头超时时返回所用的超时时间
+async fn send(
+    request: reqwest::RequestBuilder,
+    timeouts: Timeouts,
+    stream: bool,
+) -> Result<reqwest::Result<reqwest::Response>, Duration> {
+    if stream {
+        let deadline = tokio::time::Instant::now() + timeouts.first_byte;
+        let mut result = tokio::time::timeout_at(deadline, request.send())
+            .await
+            .map_err(|_| timeouts.first_byte)?;
+        if let Ok(response) = &mut result {
+            response
+                .extensions_mut()
+                .insert(FirstByteDeadline(deadline));
+        }
+        Ok(result)
+    } else {
+        Ok(request.timeout(timeouts.total).send().await)
+    }
+}
+
+/// 启用追踪时为每次上游调用创建 span,流式请求只覆盖到响应头返回
+fn upstream_span(model: &str, location: &str, attempt: u32) -> Span {
+    if !telemetry::enabled() {
+        return Span::none();
+    }
+    tracing::info_span!(
+        target: telemetry::TARGET,
+        "upstream",
+        otel.name = "vertex_ai",
+        otel.kind = "client",
+        otel.status_code = Empty,
+        gen_ai.provider.name = telemetry::PROVIDER_NAME,
+        gen_ai.request.model = model,
+        cloud.region = location,
+        http.request.resend_count = attempt,
+        http.response.status_code = Empty,
+        error.type = Empty,
+    )
+}
+
+/// 记录上游调用的结果
+fn record_attempt(span: &Span, result: &Result<reqwest::Result<reqwest::Response>, Duration>) {
+    if span.is_none() {
+        return;
+    }
+    match result {
+        Ok(Ok(response)) => {
+            span.record("http.response.status_code", response.status().as_u16());
+            if !response.status().is_success() {
+                span.record("otel.status_code", "ERROR");
+                span.record("error.type", response.status().as_str());
+            }
+        }
+        Ok(Err(e)) => {
+            span.record("otel.status_code", "ERROR");
+            span.record(
+                "error.type",
+                if e.is_timeout() {
+                    "timeout"
+                } else if e.is_connect() {
+                    "connection"
+                } else {
+                    "request"
+                },
+            );
+        }
+        Err(_) => {
+            span.record("otel.status_code", "ERROR");
+            span.record("error.type", "timeout");
+        }
+    }
+}
+
+/// 计算退避时间: 指数退避加抖动,且不少于 Retry-After
+fn backoff(policy: &RetryConfig, attempt: u32, retry_after: Option<Duration>) -> Duration {
+    let base = policy
+        .initial_backoff()
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(policy.max_backoff());
+    // 在 [base/2, base] 区间内随机抖动,避免多个请求同时重试
+    let half = base / 2;
+    let jitter = Duration::from_millis(fastrand::u64(0..=half.as_millis() as u64));
+    let delay = half + jitter;
+    match retry_after {
+        Some(retry_after) => delay.max(retry_after),
+        None => delay,
+    }
+}
+
+/// 解析 `Retry-After` 响应头(仅支持秒数格式)
+fn parse_retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_exponentially_within_bounds() {
+        let policy = RetryConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1000,
+            ..Default::default()
+        };
+        for attempt in 0..6 {
+            let base = (100u64 << attempt).min(1000);
+            let delay = backoff(&policy, attempt, None).as_millis() as u64;
+            assert!(
+                delay >= base / 2 && delay <= base,
+                "attempt {attempt}: {delay}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_backoff_honors_retry_after() {
+        let policy = RetryConfig::default();
+        let delay = backoff(&policy, 0, Some(Duration::from_secs(3)));
+        assert_eq!(delay, Duration::from_secs(3));
+    }
+}
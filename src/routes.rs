@@ -1,24 +1,48 @@
 use axum::{
-    routing::get,
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post},
     Router,
 };
 use std::sync::Arc;
 
+use crate::auth;
+use crate::billing;
+use crate::context;
 use crate::handlers;
+use crate::metrics;
+use crate::ratelimit;
 use crate::state::AppState;
 
 /// 创建应用路由
 /// 
 /// 包含所有 API 端点:
 /// - `/` - 健康检查
+/// - `/metrics` - Prometheus 指标
 /// - `/chat/completions` - 聊天完成接口 (GET/POST)
 /// - `/v1/chat/completions` - 聊天完成接口 (GET/POST)
+/// - `/completions` - 文本补全接口 (POST)
+/// - `/v1/completions` - 文本补全接口 (POST)
 /// - `/models` - 模型列表接口
 /// - `/v1/models` - 模型列表接口
+/// - `/embeddings` - 嵌入接口 (POST)
+/// - `/v1/embeddings` - 嵌入接口 (POST)
+/// - `/messages` - Anthropic Messages 接口 (POST)
+/// - `/v1/messages` - Anthropic Messages 接口 (POST)
+/// - `/responses` - OpenAI Responses 接口 (POST)
+/// - `/v1/responses` - OpenAI Responses 接口 (POST)
+/// - `/v1/responses/{id}` - 查询或删除已保存的响应 (GET/DELETE)
+/// - `/count_tokens` - 令牌计数接口 (POST)
+/// - `/v1/count_tokens` - 令牌计数接口 (POST)
+/// - `/v1beta/models/{model}:{method}` - Gemini API 兼容接口 (POST)
+/// - `/v1/models/{model}:{method}` - Gemini API 兼容接口 (POST)
+/// - `/v1/cached_contents` - 创建 (POST) 或列出 (GET,需要管理员 Key) Vertex AI 上下文缓存
+/// - `/v1/cached_contents/{name}` - 查询、延长或删除上下文缓存 (GET/PATCH/DELETE)
+/// - `/admin/usage` - 按 Key、模型和日期汇总的用量报告 (GET,需要管理员 Key)
+///
+/// 除健康检查和指标外,所有接口都需要通过客户端 API Key 认证;调用模型的接口(不论请求方法)受限流和预算约束
 pub fn create_routes(state: Arc<AppState>) -> Router {
-    Router::new()
-        // 根路径
-        .route("/", get(handlers::root))
+    let api = Router::new()
         // 聊天完成接口 (支持 GET 和 POST)
         .route(
             "/chat/completions",
@@ -28,8 +52,80 @@ pub fn create_routes(state: Arc<AppState>) -> Router {
             "/v1/chat/completions",
             get(handlers::chat_completions).post(handlers::chat_completions),
         )
+        // 文本补全接口
+        .route("/completions", post(handlers::completions))
+        .route("/v1/completions", post(handlers::completions))
         // 模型列表接口
         .route("/models", get(handlers::models))
         .route("/v1/models", get(handlers::models))
+        // 嵌入接口
+        .route("/embeddings", post(handlers::embeddings))
+        .route("/v1/embeddings", post(handlers::embeddings))
+        // Anthropic Messages 接口
+        .route("/messages", post(handlers::messages))
+        .route("/v1/messages", post(handlers::messages))
+        // OpenAI Responses 接口
+        .route("/responses", post(handlers::create_response))
+        .route("/v1/responses", post(handlers::create_response))
+        .route(
+            "/responses/{id}",
+            get(handlers::get_response).delete(handlers::delete_response),
+        )
+        .route(
+            "/v1/responses/{id}",
+            get(handlers::get_response).delete(handlers::delete_response),
+        )
+        // 令牌计数接口
+        .route("/count_tokens", post(handlers::count_tokens))
+        .route("/v1/count_tokens", post(handlers::count_tokens))
+        // Gemini API 兼容接口,路径参数形如 gemini-2.5-flash:generateContent
+        .route("/v1beta/models/{model}", post(handlers::gemini_api))
+        .route("/v1/models/{model}", post(handlers::gemini_api))
+        // 上下文缓存管理接口,资源名包含斜杠
+        .route(
+            "/cached_contents",
+            get(handlers::list_cached_contents).post(handlers::create_cached_content),
+        )
+        .route(
+            "/v1/cached_contents",
+            get(handlers::list_cached_contents).post(handlers::create_cached_content),
+        )
+        .route(
+            "/cached_contents/{*name}",
+            get(handlers::get_cached_content)
+                .patch(handlers::update_cached_content)
+                .delete(handlers::delete_cached_content),
+        )
+        .route(
+            "/v1/cached_contents/{*name}",
+            get(handlers::get_cached_content)
+                .patch(handlers::update_cached_content)
+                .delete(handlers::delete_cached_content),
+        )
+        // 用量报告
+        .route("/admin/usage", get(handlers::usage))
+        // 限流和计费在认证之后执行,需要读取认证得到的 API Key
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            ratelimit::enforce,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            billing::enforce,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_api_key,
+        ));
+
+    Router::new()
+        // 根路径
+        .route("/", get(handlers::root))
+        // Prometheus 指标
+        .route("/metrics", get(metrics::metrics_handler))
+        .merge(api)
+        .fallback(handlers::not_found)
+        .layer(DefaultBodyLimit::max(state.config.server.max_body_bytes))
+        .layer(middleware::from_fn(context::track_request))
         .with_state(state)
 }
@@ -0,0 +1,316 @@
+//! 模型路由表
+//!
+//! 根据模型名称选择 Vertex AI 的区域、项目、端点和后端模式
+
+use crate::config::{Backend, Config, ConfigError, MatchType};
+use crate::metrics;
+use crate::state::model_matches;
+use crate::timeout::Timeouts;
+use crate::translate::strip_publisher;
+use regex::Regex;
+use std::time::Duration;
+
+/// 路由解析结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedRoute {
+    pub name: String,
+    pub location: String,
+    pub project_id: String,
+    /// 路由是否显式指定了项目,未指定时由项目池选择
+    pub project_pinned: bool,
+    pub endpoint_id: String,
+    pub backend: Backend,
+    /// 备用区域,按顺序尝试
+    pub fallback_locations: Vec<String>,
+    pub timeouts: Timeouts,
+    /// 模型是否命中显式路由规则、`native_models` 或价格表,只有这样的模型名才作为指标标签
+    pub known_model: bool,
+}
+
+impl ResolvedRoute {
+    /// 指标中使用的模型标签: 已知模型使用模型名,否则记为 `unknown`,
+    /// 避免客户端传入任意模型名导致标签基数无限增长
+    pub fn model_label<'a>(&self, model: &'a str) -> &'a str {
+        if self.known_model {
+            model
+        } else {
+            metrics::UNKNOWN_MODEL
+        }
+    }
+
+    /// 主区域及备用区域(去重)
+    pub fn locations(&self) -> Vec<String> {
+        let mut locations = vec![self.location.clone()];
+        for location in &self.fallback_locations {
+            if !locations.contains(location) {
+                locations.push(location.clone());
+            }
+        }
+        locations
+    }
+
+    /// `x-vertex-route` 响应头的值
+    pub fn header_value(&self) -> String {
+        format!(
+            "name={}; location={}; project={}; endpoint={}",
+            self.name, self.location, self.project_id, self.endpoint_id
+        )
+    }
+}
+
+/// 模型匹配器
+#[derive(Debug)]
+enum Matcher {
+    Exact(String),
+    Prefix(String),
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn matches(&self, model: &str) -> bool {
+        match self {
+            Matcher::Exact(m) => model == m,
+            Matcher::Prefix(p) => model.starts_with(p.as_str()),
+            Matcher::Glob(g) => glob_match(g, model),
+            Matcher::Regex(r) => r.is_match(model),
+        }
+    }
+}
+
+/// 单条已编译的路由
+#[derive(Debug)]
+struct Route {
+    matcher: Matcher,
+    name: String,
+    location: Option<String>,
+    project_id: Option<String>,
+    endpoint_id: Option<String>,
+    backend: Option<Backend>,
+    fallback_locations: Vec<String>,
+    timeouts: Timeouts,
+}
+
+/// 模型路由表
+#[derive(Debug)]
+pub struct RouteTable {
+    routes: Vec<Route>,
+    default_location: String,
+    default_project_id: String,
+    default_endpoint_id: String,
+    default_timeouts: Timeouts,
+    native_models: Vec<String>,
+    /// 价格表中的模型模式
+    priced_models: Vec<String>,
+}
+
+impl RouteTable {
+    /// 根据配置构建路由表,正则表达式在此时编译
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+        let default_timeouts = config.upstream.timeouts();
+        let routes = config
+            .routing
+            .routes
+            .iter()
+            .map(|route| {
+                let pattern = strip_publisher(&route.model).to_owned();
+                let matcher = match route.match_type {
+                    MatchType::Exact => Matcher::Exact(pattern),
+                    MatchType::Prefix => Matcher::Prefix(pattern),
+                    MatchType::Glob => Matcher::Glob(pattern),
+                    MatchType::Regex => {
+                        let regex = Regex::new(&format!("^(?:{})$", route.model)).map_err(|e| {
+                            ConfigError(format!(
+                                "routing.routes 中的正则表达式 {:?} 无效: {}",
+                                route.model, e
+                            ))
+                        })?;
+                        Matcher::Regex(regex)
+                    }
+                };
+                Ok(Route {
+                    matcher,
+                    name: route.name.clone().unwrap_or_else(|| route.model.clone()),
+                    location: route.location.clone(),
+                    project_id: route.project_id.clone(),
+                    endpoint_id: route.endpoint_id.clone(),
+                    backend: route.backend,
+                    fallback_locations: route.fallback_locations.clone(),
+                    timeouts: Timeouts {
+                        total: route
+                            .timeout_secs
+                            .map_or(default_timeouts.total, Duration::from_secs),
+                        first_byte: route
+                            .first_byte_timeout_secs
+                            .map_or(default_timeouts.first_byte, Duration::from_secs),
+                        stream_idle: route
+                            .stream_idle_timeout_secs
+                            .map_or(default_timeouts.stream_idle, Duration::from_secs),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        Ok(Self {
+            routes,
+            default_location: config.gcp.location.clone(),
+            default_project_id: config.gcp.project_id.clone(),
+            default_endpoint_id: config.gcp.endpoint_id.clone(),
+            default_timeouts,
+            native_models: config.routing.native_models.clone(),
+            priced_models: config
+                .billing
+                .prices
+                .iter()
+                .map(|p| p.model.clone())
+                .collect(),
+        })
+    }
+
+    /// 解析模型对应的路由,未命中任何规则时使用默认路由
+    pub fn resolve(&self, model: &str) -> ResolvedRoute {
+        let model_id = strip_publisher(model);
+        let route = self.routes.iter().find(|r| r.matcher.matches(model_id));
+        let native = self.native_models.iter().any(|p| model_matches(p, model));
+        let default_backend = if model_id.starts_with("claude") {
+            Backend::Anthropic
+        } else if native {
+            Backend::Native
+        } else {
+            Backend::Openapi
+        };
+
+        let resolved = ResolvedRoute {
+            name: route.map_or("default", |r| r.name.as_str()).to_owned(),
+            location: route
+                .and_then(|r| r.location.clone())
+                .unwrap_or_else(|| self.default_location.clone()),
+            project_id: route
+                .and_then(|r| r.project_id.clone())
+                .unwrap_or_else(|| self.default_project_id.clone()),
+            project_pinned: route.is_some_and(|r| r.project_id.is_some()),
+            endpoint_id: route
+                .and_then(|r| r.endpoint_id.clone())
+                .unwrap_or_else(|| self.default_endpoint_id.clone()),
+            backend: route.and_then(|r| r.backend).unwrap_or(default_backend),
+            fallback_locations: route
+                .map(|r| r.fallback_locations.clone())
+                .unwrap_or_default(),
+            timeouts: route.map_or(self.default_timeouts, |r| r.timeouts),
+            known_model: route.is_some()
+                || native
+                || self.priced_models.iter().any(|p| model_matches(p, model)),
+        };
+        tracing::debug!(
+            "Resolved model {} to route {} ({})",
+            model,
+            resolved.name,
+            resolved.header_value()
+        );
+        resolved
+    }
+}
+
+/// 通配符匹配,`*` 匹配任意字符序列,`?` 匹配单个字符
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 最近一次 `*` 的位置及其对应的文本位置,用于回溯
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RouteConfig;
+
+    fn route(model: &str, match_type: MatchType, location: &str) -> RouteConfig {
+        RouteConfig {
+            name: None,
+            model: model.to_owned(),
+            match_type,
+            location: Some(location.to_owned()),
+            project_id: None,
+            endpoint_id: None,
+            backend: None,
+            fallback_locations: Vec::new(),
+            timeout_secs: None,
+            first_byte_timeout_secs: None,
+            stream_idle_timeout_secs: None,
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("gemini-3*", "gemini-3-pro-preview"));
+        assert!(glob_match("gemini-*-flash", "gemini-2.5-flash"));
+        assert!(glob_match("gemini-2.?-pro", "gemini-2.5-pro"));
+        assert!(!glob_match("gemini-*-flash", "gemini-2.5-flash-lite"));
+        assert!(!glob_match("gemini-3*", "google-gemini-3"));
+    }
+
+    #[test]
+    fn test_resolve_uses_first_match_and_default() {
+        let mut config = Config::default();
+        config.gcp.project_id = "main".to_owned();
+        config.gcp.location = "us-central1".to_owned();
+        config.routing.routes = vec![
+            route("gemini-2.5-pro", MatchType::Exact, "europe-west4"),
+            route(r"gemini-2\.5-.*", MatchType::Regex, "asia-northeast1"),
+            route("gemini-3", MatchType::Prefix, "global"),
+        ];
+        let table = RouteTable::new(&config).unwrap();
+
+        assert_eq!(table.resolve("gemini-2.5-pro").location, "europe-west4");
+        assert_eq!(
+            table.resolve("google/gemini-2.5-flash").location,
+            "asia-northeast1"
+        );
+        assert_eq!(table.resolve("gemini-3-pro-preview").location, "global");
+
+        let fallback = table.resolve("gemini-2.0-flash");
+        assert_eq!(fallback.name, "default");
+        assert_eq!(fallback.location, "us-central1");
+        assert_eq!(fallback.project_id, "main");
+    }
+
+    #[test]
+    fn test_route_overrides_timeouts() {
+        let mut config = Config::default();
+        let mut slow = route("gemini-2.5-pro", MatchType::Exact, "global");
+        slow.stream_idle_timeout_secs = Some(600);
+        config.routing.routes = vec![slow];
+        let table = RouteTable::new(&config).unwrap();
+
+        let defaults = config.upstream.timeouts();
+        let timeouts = table.resolve("gemini-2.5-pro").timeouts;
+        assert_eq!(timeouts.stream_idle, Duration::from_secs(600));
+        assert_eq!(timeouts.first_byte, defaults.first_byte);
+        assert_eq!(table.resolve("gemini-2.0-flash").timeouts, defaults);
+    }
+
+    #[test]
+    fn test_invalid_regex_is_rejected() {
+        let mut config = Config::default();
+        config.routing.routes = vec![route("gemini-(", MatchType::Regex, "global")];
+        assert!(RouteTable::new(&config).is_err());
+    }
+}
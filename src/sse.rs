@@ -0,0 +1,283 @@
+//! Server-Sent Events 解析与转换工具
+
+use crate::error::GatewayError;
+use crate::models::Usage;
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+use std::io;
+use std::time::Duration;
+
+/// 一个完整的 SSE 事件
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// 增量 SSE 解析器
+///
+/// 上游字节流可能在任意位置被切分,解析器负责缓存不完整的行,
+/// 每遇到一个空行就产出一个事件
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    current: SseEvent,
+    has_data: bool,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 写入一段字节,返回其中包含的完整事件
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if let Some(event) = self.process_line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// 上游结束时调用,返回缓冲区中残留的最后一个事件
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+            self.process_line(line.trim_end_matches('\r'));
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            // 注释行
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => {
+                if self.has_data {
+                    self.current.data.push('\n');
+                }
+                self.current.data.push_str(value);
+                self.has_data = true;
+            }
+            "event" => self.current.event = Some(value.to_owned()),
+            _ => {}
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        if !self.has_data {
+            self.current = SseEvent::default();
+            return None;
+        }
+        self.has_data = false;
+        Some(std::mem::take(&mut self.current))
+    }
+}
+
+/// 格式化一个带事件名的 SSE 事件
+pub fn format_event(out: &mut String, event: &str, data: &str) {
+    out.push_str("event: ");
+    out.push_str(event);
+    out.push('\n');
+    format_data(out, data);
+}
+
+/// 格式化一个只有 data 字段的 SSE 事件
+pub fn format_data(out: &mut String, data: &str) {
+    out.push_str("data: ");
+    out.push_str(data);
+    out.push_str("\n\n");
+}
+
+/// SSE 事件转换器
+///
+/// 用于将上游的 SSE 事件逐个翻译为客户端期望的协议格式
+pub trait EventTranslator: Send + 'static {
+    /// 处理一个上游事件,将输出写入 `out`
+    fn on_event(&mut self, event: SseEvent, out: &mut String);
+
+    /// 上游正常结束
+    fn on_end(&mut self, out: &mut String);
+
+    /// 上游读取失败
+    fn on_error(&mut self, message: &str, out: &mut String);
+
+    /// 目前为止从上游事件中读取到的用量,与客户端是否请求 usage 无关
+    fn usage(&self) -> Option<Usage> {
+        None
+    }
+}
+
+/// 将上游字节流按 SSE 事件解析,并通过转换器生成新的字节流
+pub fn translate_stream<S, E, T>(
+    upstream: S,
+    translator: T,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: std::fmt::Display,
+    T: EventTranslator,
+{
+    struct State<S, T> {
+        upstream: S,
+        decoder: SseDecoder,
+        translator: T,
+    }
+
+    let state = State {
+        upstream: Box::pin(upstream),
+        decoder: SseDecoder::new(),
+        translator,
+    };
+
+    futures_util::stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        let mut out = String::new();
+        match state.upstream.next().await {
+            Some(Ok(bytes)) => {
+                for event in state.decoder.push(&bytes) {
+                    state.translator.on_event(event, &mut out);
+                }
+                Some((Ok(Bytes::from(out)), Some(state)))
+            }
+            Some(Err(e)) => {
+                tracing::error!("Upstream stream failed: {}", e);
+                state.translator.on_error(&e.to_string(), &mut out);
+                Some((Ok(Bytes::from(out)), None))
+            }
+            None => {
+                if let Some(event) = state.decoder.finish() {
+                    state.translator.on_event(event, &mut out);
+                }
+                state.translator.on_end(&mut out);
+                Some((Ok(Bytes::from(out)), None))
+            }
+        }
+    })
+}
+
+/// 透传上游字节流,读取失败(包括超时)时以 `on_error` 生成的 SSE 错误事件结束
+pub fn passthrough_stream<S, F>(
+    upstream: S,
+    on_error: F,
+) -> impl Stream<Item = Result<Bytes, io::Error>> + Send
+where
+    S: Stream<Item = Result<Bytes, io::Error>> + Send + 'static,
+    F: FnOnce(GatewayError) -> String + Send + 'static,
+{
+    let state = (Box::pin(upstream), on_error);
+    futures_util::stream::unfold(Some(state), |state| async move {
+        let (mut upstream, on_error) = state?;
+        match upstream.next().await {
+            Some(Ok(bytes)) => Some((Ok(bytes), Some((upstream, on_error)))),
+            Some(Err(e)) => {
+                tracing::error!("Upstream stream failed: {}", e);
+                let error = if e.kind() == io::ErrorKind::TimedOut {
+                    GatewayError::gateway_timeout(e.to_string())
+                } else {
+                    GatewayError::bad_gateway(e.to_string())
+                };
+                Some((Ok(Bytes::from(on_error(error))), None))
+            }
+            None => None,
+        }
+    })
+}
+
+/// 上游超过 `interval` 没有数据时插入 `: keepalive` 注释,防止负载均衡器关闭空闲连接
+///
+/// 上游的每一项都必须是完整的 SSE 事件,否则注释可能插入到事件中间
+pub fn with_keepalive<S, E>(
+    upstream: S,
+    interval: Duration,
+) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+{
+    futures_util::stream::unfold(Box::pin(upstream), move |mut upstream| async move {
+        match tokio::time::timeout(interval, upstream.next()).await {
+            Ok(item) => item.map(|item| (item, upstream)),
+            Err(_) => Some((Ok(Bytes::from_static(b": keepalive\n\n")), upstream)),
+        }
+    })
+}
+
+/// OpenAI 格式的流式错误事件,以 `[DONE]` 结束
+pub fn openai_error_event(error: GatewayError) -> String {
+    let mut out = String::new();
+    format_data(&mut out, &error.body().to_string());
+    format_data(&mut out, "[DONE]");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keepalive_fills_idle_gaps() {
+        let upstream = futures_util::stream::iter([Ok::<_, io::Error>(Bytes::from("data: 1\n\n"))])
+            .chain(futures_util::stream::once(async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(Bytes::from("data: 2\n\n"))
+            }));
+        let chunks: Vec<Bytes> = with_keepalive(upstream, Duration::from_millis(20))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(chunks.first().unwrap(), "data: 1\n\n");
+        assert_eq!(chunks.last().unwrap(), "data: 2\n\n");
+        assert!(chunks[1..chunks.len() - 1]
+            .iter()
+            .all(|c| c == ": keepalive\n\n"));
+        assert!(chunks.len() >= 3);
+    }
+
+    #[test]
+    fn test_decoder_handles_split_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: {\"a\"").is_empty());
+        let events = decoder.push(b":1}\r\n\r\nevent: ping\ndata: x\n");
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: None,
+                data: "{\"a\":1}".to_owned()
+            }]
+        );
+        assert_eq!(
+            decoder.finish(),
+            Some(SseEvent {
+                event: Some("ping".to_owned()),
+                data: "x".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_decoder_skips_comments_and_joins_data_lines() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b": keepalive\n\ndata: a\ndata: b\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "a\nb");
+    }
+}
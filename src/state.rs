@@ -1,23 +1,22 @@
-use crate::gcp::TokenManager;
+use crate::auth::KeyStore;
+use crate::billing::Billing;
+use crate::cache::{semantic::SemanticCache, ChatCache};
+use crate::config::Config;
+use crate::gcp::ProjectPool;
+use crate::models::responses::StoredResponse;
 use crate::models::Model;
+use crate::ratelimit::RateLimiter;
+use crate::routing::RouteTable;
 use moka::future::Cache;
-use std::process::exit;
+use std::sync::Arc;
 
-/// 应用配置
-#[derive(Clone)]
-pub struct Config {
-    pub location: String,
-    pub endpoint_id: &'static str,
-    pub project_id: &'static str,
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            location: "us-central1".to_string(),
-            endpoint_id: "openapi",
-            project_id: "",
-        }
+/// 判断模型是否匹配模式,支持以 `*` 结尾的前缀匹配,忽略 "google/" 等发布商前缀
+pub fn model_matches(pattern: &str, model: &str) -> bool {
+    let model = crate::translate::strip_publisher(model);
+    let pattern = crate::translate::strip_publisher(pattern);
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => model == pattern,
     }
 }
 
@@ -27,33 +26,51 @@ impl Default for Config {
 #[derive(Clone)]
 pub struct AppState {
     pub http_client: reqwest::Client,
-    pub token_manager: TokenManager,
+    /// GCP 项目池,每个项目持有自己的令牌管理器
+    pub projects: Arc<ProjectPool>,
     pub config: Config,
+    /// 模型路由表
+    pub routes: Arc<RouteTable>,
     pub models_cache: Cache<String, Vec<Model>>,
+    /// 已保存的 Responses 响应,用于 `previous_response_id` 续接对话
+    pub responses: moka::sync::Cache<String, Arc<StoredResponse>>,
+    /// 客户端 API Key,未配置时不校验
+    pub key_store: Option<KeyStore>,
+    /// 客户端限流器,未配置任何限制时为空
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// 用量计费与账本
+    pub billing: Arc<Billing>,
+    /// 聊天补全响应缓存,未启用时为空
+    pub chat_cache: Option<Arc<ChatCache>>,
+    /// 聊天补全语义缓存,未启用时为空
+    pub semantic_cache: Option<Arc<SemanticCache>>,
 }
 
 impl AppState {
     /// 创建新的应用状态实例
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    ///
+    /// 配置已在启动时完成校验
+    pub async fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let upstream = &config.upstream;
+
         // 创建 HTTP 客户端 - 优化配置
         let http_client = reqwest::Client::builder()
             // 超时配置
-            .timeout(std::time::Duration::from_secs(60)) // 总超时时间
-            .connect_timeout(std::time::Duration::from_secs(10)) // 连接超时
+            .connect_timeout(upstream.connect_timeout()) // 连接超时,读取超时按路由在每个请求上设置
             
             // 连接池配置
-            .pool_max_idle_per_host(10) // 每个主机最大空闲连接数
-            .pool_idle_timeout(std::time::Duration::from_secs(90)) // 空闲连接超时
+            .pool_max_idle_per_host(upstream.pool_max_idle_per_host) // 每个主机最大空闲连接数
+            .pool_idle_timeout(upstream.pool_idle_timeout()) // 空闲连接超时
             
             // TCP 配置
-            .tcp_keepalive(std::time::Duration::from_secs(60)) // TCP keep-alive
+            .tcp_keepalive(upstream.tcp_keepalive()) // TCP keep-alive
             .tcp_nodelay(true) // 禁用 Nagle 算法,减少延迟
             
             // HTTP/2 配置 - Vertex AI 支持 HTTP/2
             .http2_prior_knowledge() // 优先使用 HTTP/2
             .http2_adaptive_window(true) // 自适应窗口大小
-            .http2_keep_alive_interval(std::time::Duration::from_secs(30)) // HTTP/2 keep-alive
-            .http2_keep_alive_timeout(std::time::Duration::from_secs(20))
+            .http2_keep_alive_interval(upstream.http2_keep_alive_interval()) // HTTP/2 keep-alive
+            .http2_keep_alive_timeout(upstream.http2_keep_alive_timeout())
             .http2_keep_alive_while_idle(true) // 空闲时也保持 keep-alive
             
             // 其他优化
@@ -63,40 +80,129 @@ impl AppState {
             
             .build()?;
 
-        // 创建令牌管理器
-        let token_manager = TokenManager::new().await?;
-
-        // 从环境变量创建配置,未设置时使用默认值
-        let config = Config {
-            location: std::env::var("GCP_LOCATION").unwrap_or_else(|_| "global".to_string()),
-            endpoint_id: "openapi",
-            project_id: std::env::var("GCP_PROJECT_ID")
-                .unwrap_or_else(|_| {
-                    tracing::error!("请设置GCP_PROJECT_ID");
-                    exit(1);
-                })
-                .leak(),
-        };
+        // 创建项目池及各项目的令牌管理器
+        let pinned = config
+            .routing
+            .routes
+            .iter()
+            .filter_map(|r| r.project_id.as_deref());
+        let projects = Arc::new(ProjectPool::new(&config.gcp, pinned).await?);
 
         // 打印配置信息
         tracing::info!("========================================");
         tracing::info!("GCP Configuration:");
-        tracing::info!("  Location:    {}", config.location);
-        tracing::info!("  Endpoint ID: {}", config.endpoint_id);
-        tracing::info!("  Project ID:  {}", config.project_id);
+        tracing::info!("  Location:    {}", config.gcp.location);
+        tracing::info!("  Endpoint ID: {}", config.gcp.endpoint_id);
+        tracing::info!("  Project ID:  {}", config.gcp.project_id);
+        if !config.gcp.projects.is_empty() {
+            let members: Vec<String> = projects
+                .members()
+                .map(|(id, weight)| format!("{id}(weight={weight})"))
+                .collect();
+            tracing::info!(
+                "  Projects:    {} [{:?}]",
+                members.join(", "),
+                config.gcp.balance
+            );
+        }
+        if !config.routing.native_models.is_empty() {
+            tracing::info!("  Native:      {}", config.routing.native_models.join(", "));
+        }
+        for route in &config.routing.routes {
+            tracing::info!(
+                "  Route:       {} ({:?}) -> {}",
+                route.model,
+                route.match_type,
+                route.location.as_deref().unwrap_or(&config.gcp.location)
+            );
+        }
         tracing::info!("========================================");
 
-        // 创建模型缓存 - 1小时过期
+        // 构建模型路由表
+        let routes = Arc::new(RouteTable::new(&config)?);
+
+        // 加载客户端 API Key
+        let key_store = match &config.auth.keys_file {
+            Some(path) => {
+                let store = KeyStore::load(path)?;
+                tracing::info!("Loaded {} API keys from {}", store.len(), path.display());
+                Some(store)
+            }
+            None => {
+                tracing::warn!("auth.disabled = true,网关不会校验客户端 API Key");
+                None
+            }
+        };
+
+        // 创建限流器
+        let rate_limiter = RateLimiter::new(&config.rate_limit, key_store.as_ref()).map(Arc::new);
+        if rate_limiter.is_some() {
+            let global = &config.rate_limit.global;
+            tracing::info!(
+                "Rate limiting enabled (global rpm={}, tpm={}, max_concurrent={})",
+                global.rpm,
+                global.tpm,
+                global.max_concurrent
+            );
+        }
+
+        // 打开用量账本
+        let billing = Arc::new(Billing::new(&config.billing)?);
+
+        // 创建模型缓存
         let models_cache = Cache::builder()
             .max_capacity(100)
-            .time_to_live(std::time::Duration::from_secs(3600)) // 1小时
+            .time_to_live(std::time::Duration::from_secs(config.cache.models_ttl_secs))
             .build();
 
+        // 创建 Responses 对话存储
+        let responses = moka::sync::Cache::builder()
+            .max_capacity(config.cache.responses_max_entries)
+            .time_to_live(std::time::Duration::from_secs(
+                config.cache.responses_ttl_secs,
+            ))
+            .build();
+
+        // 创建聊天补全响应缓存
+        let chat_cache = ChatCache::new(&config.cache.chat).map(Arc::new);
+        if chat_cache.is_some() {
+            tracing::info!(
+                "Chat completion cache enabled (ttl={}s, max_entries={}, dir={})",
+                config.cache.chat.ttl_secs,
+                config.cache.chat.max_entries,
+                config
+                    .cache
+                    .chat
+                    .dir
+                    .as_ref()
+                    .map_or("none".to_owned(), |d| d.display().to_string())
+            );
+        }
+
+        // 创建聊天补全语义缓存
+        let semantic_cache = SemanticCache::new(&config.cache.semantic).map(Arc::new);
+        if semantic_cache.is_some() {
+            tracing::info!(
+                "Semantic chat cache enabled (embedding_model={}, threshold={}, ttl={}s, max_entries={})",
+                config.cache.semantic.embedding_model,
+                config.cache.semantic.threshold,
+                config.cache.semantic.ttl_secs,
+                config.cache.semantic.max_entries
+            );
+        }
+
         Ok(Self {
             http_client,
-            token_manager,
+            projects,
             config,
+            routes,
             models_cache,
+            responses,
+            key_store,
+            rate_limiter,
+            billing,
+            chat_cache,
+            semantic_cache,
         })
     }
 }
@@ -0,0 +1,208 @@
+//! OpenTelemetry 分布式追踪
+//!
+//! 启用后通过 OTLP(gRPC 或 HTTP)导出以下 span:
+//! - `request`: 入站请求,延续客户端 `traceparent` 中的 trace,持续到响应体发送完毕
+//! - `credentials`: 获取 GCP 访问令牌
+//! - `upstream`: 每次调用 Vertex AI(含重试和故障转移),并通过 `traceparent` 传递 trace
+//! - `stream`: 流式响应从响应头返回到最后一个事件
+//!
+//! 模型、token 用量和结束原因按 GenAI 语义约定记录为 span 属性。
+//! 未启用时不安装导出层,也不解析响应体
+
+use crate::config::{OtlpProtocol, TelemetryConfig};
+use crate::usage::ResponseSummary;
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// W3C trace context 请求头,启用追踪时由网关重新生成,不再原样转发
+pub const PROPAGATION_HEADERS: &[&str] = &["traceparent", "tracestate"];
+
+/// 追踪 span 使用的 target,日志输出会忽略这些 span
+pub const TARGET: &str = module_path!();
+
+/// `gen_ai.provider.name` 属性值
+pub const PROVIDER_NAME: &str = "gcp.vertex_ai";
+
+/// 已安装的 tracer provider,退出时用于刷新尚未导出的 span
+static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// 是否已启用追踪
+pub fn enabled() -> bool {
+    PROVIDER.get().is_some()
+}
+
+/// 根据配置创建 OTLP 导出层,未启用时返回 `None`
+///
+/// gRPC 导出器依赖 Tokio,需要在运行时上下文中调用
+pub fn layer<S>(
+    config: &TelemetryConfig,
+) -> Result<Option<impl Layer<S>>, Box<dyn std::error::Error>>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    if !config.enabled {
+        return Ok(None);
+    }
+    let timeout = Duration::from_secs(config.timeout_secs);
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => {
+            let builder = SpanExporter::builder().with_tonic().with_timeout(timeout);
+            match &config.endpoint {
+                Some(endpoint) => builder.with_endpoint(endpoint).build()?,
+                None => builder.build()?,
+            }
+        }
+        OtlpProtocol::Http => {
+            let builder = SpanExporter::builder().with_http().with_timeout(timeout);
+            match &config.endpoint {
+                Some(endpoint) => builder.with_endpoint(endpoint).build()?,
+                None => builder.build()?,
+            }
+        }
+    };
+    let resource = Resource::builder()
+        .with_service_name(config.service_name.clone())
+        .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+        .build();
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(resource)
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    let _ = PROVIDER.set(provider);
+
+    // 只导出追踪 span,本 crate 的警告和错误日志作为 span 事件导出
+    let targets = Targets::new()
+        .with_target(env!("CARGO_CRATE_NAME"), tracing::Level::WARN)
+        .with_target(TARGET, tracing::Level::INFO);
+    Ok(Some(
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(targets),
+    ))
+}
+
+/// 刷新并关闭导出器
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get() {
+        if let Err(e) = provider.shutdown() {
+            eprintln!("Failed to shut down OpenTelemetry exporter: {e}");
+        }
+    }
+}
+
+/// 以请求头中的 `traceparent` 作为 span 的父节点,请求头无效时开始新的 trace
+pub fn set_parent(span: &Span, headers: &HeaderMap) {
+    if !enabled() {
+        return;
+    }
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    if parent.span().span_context().is_valid() {
+        let _ = span.set_parent(parent);
+    }
+}
+
+/// 为上游请求加上当前 span 的 `traceparent`
+pub fn inject(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    if !enabled() {
+        return request;
+    }
+    let context = Span::current().context();
+    let mut headers = HeaderMap::new();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    request.headers(headers)
+}
+
+/// 路由对应的 `gen_ai.operation.name`,非模型调用的接口返回 `None`
+pub fn operation(route: &str) -> Option<&'static str> {
+    let route = route
+        .trim_start_matches("/v1beta")
+        .trim_start_matches("/v1");
+    match route {
+        "/chat/completions" | "/messages" | "/responses" => Some("chat"),
+        "/completions" => Some("text_completion"),
+        "/embeddings" => Some("embeddings"),
+        "/models/{model}" => Some("generate_content"),
+        _ => None,
+    }
+}
+
+/// 将响应摘要记录为 GenAI 语义约定属性
+pub fn record_summary(span: &Span, summary: &ResponseSummary) {
+    if let Some(usage) = &summary.usage {
+        span.record("gen_ai.usage.input_tokens", usage.prompt_tokens);
+        span.record("gen_ai.usage.output_tokens", usage.completion_tokens);
+    }
+    if !summary.finish_reasons.is_empty() {
+        span.record(
+            "gen_ai.response.finish_reasons",
+            summary.finish_reasons.join(","),
+        );
+    }
+    if let Some(id) = &summary.response_id {
+        span.record("gen_ai.response.id", id.as_str());
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_names() {
+        assert_eq!(operation("/v1/chat/completions"), Some("chat"));
+        assert_eq!(operation("/messages"), Some("chat"));
+        assert_eq!(
+            operation("/v1beta/models/{model}"),
+            Some("generate_content")
+        );
+        assert_eq!(operation("/v1/embeddings"), Some("embeddings"));
+        assert_eq!(operation("/v1/models"), None);
+    }
+}
@@ -0,0 +1,135 @@
+//! 上游请求超时
+//!
+//! 连接超时由 HTTP 客户端统一设置,其余超时按路由解析:
+//! - 非流式请求: 从发送到读完响应体的总时长
+//! - 流式请求: 从发送到收到首个数据块的时长(包括等待响应头),以及之后相邻两个数据块之间的最大间隔
+//!
+//! 流式响应不设总时长上限,长时间思考或输出的响应只要持续有数据就不会被中断
+
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+use std::io;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 一个路由的超时配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// 非流式请求的总超时
+    pub total: Duration,
+    /// 流式请求等待响应头和首个数据块的超时
+    pub first_byte: Duration,
+    /// 流式响应相邻两个数据块之间的最大间隔
+    pub stream_idle: Duration,
+}
+
+/// 流式请求收到首个数据块的截止时间
+///
+/// 由 [`crate::retry::send_with_retry`] 在发出请求时写入响应扩展,
+/// 等待响应头和等待首个数据块共用同一个首字节超时
+#[derive(Debug, Clone, Copy)]
+pub struct FirstByteDeadline(pub Instant);
+
+/// 为上游流式响应加上首字节和空闲超时,首字节超时只剩发送后余下的时间
+pub fn guard_response(
+    response: reqwest::Response,
+    timeouts: Timeouts,
+) -> impl Stream<Item = Result<Bytes, io::Error>> + Send {
+    let deadline = response
+        .extensions()
+        .get::<FirstByteDeadline>()
+        .map_or_else(|| Instant::now() + timeouts.first_byte, |d| d.0);
+    guard_stream(response.bytes_stream(), timeouts, deadline)
+}
+
+/// 为上游字节流加上首字节和空闲超时,`first_byte_deadline` 之前必须收到首个数据块
+///
+/// 超时后产出一个 `TimedOut` 错误并结束,由下游转换为 SSE 错误事件并记录日志
+pub fn guard_stream<S, E>(
+    upstream: S,
+    timeouts: Timeouts,
+    first_byte_deadline: Instant,
+) -> impl Stream<Item = Result<Bytes, io::Error>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: std::fmt::Display,
+{
+    struct State<S> {
+        upstream: S,
+        received: bool,
+    }
+
+    let state = State {
+        upstream: Box::pin(upstream),
+        received: false,
+    };
+
+    futures_util::stream::unfold(Some(state), move |state| async move {
+        let mut state = state?;
+        let (limit, deadline) = if state.received {
+            (timeouts.stream_idle, Instant::now() + timeouts.stream_idle)
+        } else {
+            (timeouts.first_byte, first_byte_deadline)
+        };
+        match tokio::time::timeout_at(deadline, state.upstream.next()).await {
+            Ok(Some(Ok(bytes))) => {
+                state.received = true;
+                Some((Ok(bytes), Some(state)))
+            }
+            Ok(Some(Err(e))) => Some((Err(io::Error::other(e.to_string())), None)),
+            Ok(None) => None,
+            Err(_) => {
+                let message = if state.received {
+                    format!("Vertex AI stream timed out: no data received for {limit:?}")
+                } else {
+                    format!("Vertex AI stream timed out: no data received within {limit:?}")
+                };
+                Some((Err(io::Error::new(io::ErrorKind::TimedOut, message)), None))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_guard_stream_times_out_when_idle() {
+        let timeouts = Timeouts {
+            total: Duration::from_secs(600),
+            first_byte: Duration::from_secs(60),
+            stream_idle: Duration::from_millis(20),
+        };
+        let upstream = futures_util::stream::iter([Ok::<_, io::Error>(Bytes::from("data: 1\n\n"))])
+            .chain(futures_util::stream::pending());
+        let deadline = Instant::now() + timeouts.first_byte;
+        let items: Vec<_> = guard_stream(upstream, timeouts, deadline).collect().await;
+
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        let error = items[1].as_ref().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(error.to_string().contains("20ms"));
+    }
+
+    #[tokio::test]
+    async fn test_guard_stream_counts_first_byte_from_deadline() {
+        let timeouts = Timeouts {
+            total: Duration::from_secs(600),
+            first_byte: Duration::from_secs(60),
+            stream_idle: Duration::from_secs(60),
+        };
+        // 等待响应头已用掉几乎全部首字节超时,首个数据块只剩余下的时间
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let upstream = futures_util::stream::pending::<Result<Bytes, io::Error>>();
+        let started = Instant::now();
+        let items: Vec<_> = guard_stream(upstream, timeouts, deadline).collect().await;
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(items.len(), 1);
+        let error = items[0].as_ref().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(error.to_string().contains("within 60s"));
+    }
+}
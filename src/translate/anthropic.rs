@@ -0,0 +1,578 @@
+//! OpenAI 聊天格式与 Anthropic Messages 格式互转
+//!
+//! 用于 Vertex AI 上的 Claude 模型(publishers/anthropic)
+
+use crate::error::GatewayError;
+use crate::models::anthropic::{
+    AnthropicContent, AnthropicMessage, AnthropicTool, AnthropicToolChoice, AnthropicUsage,
+    BlockDelta, ContentBlock, ImageSource, MessagesRequest, MessagesResponse, StreamEvent,
+    ThinkingParam, VERTEX_ANTHROPIC_VERSION,
+};
+use crate::models::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, Choice, ChunkChoice,
+    ContentPart, Delta, FunctionCall, Message, MessageContent, PromptTokensDetails, ResponseFormat,
+    ToolCall, ToolChoice, Usage,
+};
+use crate::sse::{format_data, EventTranslator, SseEvent};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// 未指定 max_tokens 时使用的默认值(Anthropic 要求必填)
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// 将 OpenAI 聊天请求转换为 Vertex AI rawPredict 使用的 Anthropic Messages 请求
+pub fn to_messages_request(request: &ChatCompletionRequest) -> Result<MessagesRequest, String> {
+    if request.n.is_some_and(|n| n > 1) {
+        return Err("Claude models do not support n > 1".to_owned());
+    }
+    if matches!(
+        request.response_format,
+        Some(ResponseFormat::JsonObject) | Some(ResponseFormat::JsonSchema { .. })
+    ) {
+        return Err("response_format is not supported for Claude models".to_owned());
+    }
+
+    let (system, messages) = messages_to_anthropic(&request.messages)?;
+
+    let thinking = match request.reasoning_effort.as_deref() {
+        None | Some("none") => None,
+        Some(effort) => Some(ThinkingParam {
+            kind: "enabled".to_owned(),
+            budget_tokens: Some(thinking_budget(effort)?),
+        }),
+    };
+    let mut max_tokens = request
+        .max_completion_tokens
+        .or(request.max_tokens)
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+    // 思考预算必须小于 max_tokens
+    if let Some(budget) = thinking.as_ref().and_then(|t| t.budget_tokens) {
+        if max_tokens <= budget {
+            max_tokens = budget + DEFAULT_MAX_TOKENS;
+        }
+    }
+
+    let tools = request
+        .tools
+        .as_ref()
+        .filter(|t| !t.is_empty())
+        .map(|tools| {
+            tools
+                .iter()
+                .map(|t| AnthropicTool {
+                    name: t.function.name.clone(),
+                    description: t.function.description.clone(),
+                    input_schema: t
+                        .function
+                        .parameters
+                        .clone()
+                        .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+                })
+                .collect()
+        });
+    let tool_choice = request.tool_choice.as_ref().map(|choice| match choice {
+        ToolChoice::Mode(mode) => match mode.as_str() {
+            "none" => AnthropicToolChoice::None,
+            "required" => AnthropicToolChoice::Any,
+            _ => AnthropicToolChoice::Auto,
+        },
+        ToolChoice::Named(named) => AnthropicToolChoice::Tool {
+            name: named.function.name.clone(),
+        },
+    });
+
+    Ok(MessagesRequest {
+        model: None,
+        anthropic_version: Some(VERTEX_ANTHROPIC_VERSION.to_owned()),
+        messages,
+        system,
+        max_tokens,
+        stop_sequences: request.stop.as_ref().map(|s| s.to_vec()),
+        temperature: request.temperature,
+        top_p: request.top_p,
+        top_k: None,
+        stream: request.is_stream().then_some(true),
+        tools,
+        tool_choice,
+        thinking,
+        metadata: request.user.as_ref().map(|user| json!({ "user_id": user })),
+    })
+}
+
+/// 将 reasoning_effort 映射为思考预算,与 Gemini 的映射保持一致
+fn thinking_budget(effort: &str) -> Result<u32, String> {
+    match effort {
+        "minimal" | "low" => Ok(1024),
+        "medium" => Ok(8192),
+        "high" => Ok(24576),
+        other => Err(format!("Unsupported reasoning_effort: {other}")),
+    }
+}
+
+/// 将 OpenAI 消息列表转换为 Anthropic 的 system 和 messages
+///
+/// - system/developer 消息合并为 system
+/// - assistant 的 tool_calls 映射为 tool_use 块
+/// - tool 消息映射为 user 角色的 tool_result 块
+/// - 相邻的同角色消息合并,满足 user/assistant 交替的要求
+fn messages_to_anthropic(
+    messages: &[Message],
+) -> Result<(Option<AnthropicContent>, Vec<AnthropicMessage>), String> {
+    let mut system = Vec::new();
+    let mut result: Vec<AnthropicMessage> = Vec::new();
+
+    for message in messages {
+        let (role, blocks) = match message.role.as_str() {
+            "system" | "developer" => {
+                if let Some(content) = &message.content {
+                    system.push(content.text());
+                }
+                continue;
+            }
+            "user" => ("user", content_to_blocks(message.content.as_ref())?),
+            "assistant" => {
+                let mut blocks = content_to_blocks(message.content.as_ref())?;
+                for call in message.tool_calls.iter().flatten() {
+                    let input = if call.function.arguments.trim().is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(&call.function.arguments).map_err(|e| {
+                            format!(
+                                "Invalid arguments for tool call {}: {}",
+                                call.function.name, e
+                            )
+                        })?
+                    };
+                    blocks.push(ContentBlock::ToolUse {
+                        id: call.id.clone(),
+                        name: call.function.name.clone(),
+                        input,
+                    });
+                }
+                ("assistant", blocks)
+            }
+            "tool" | "function" => {
+                let tool_use_id = message
+                    .tool_call_id
+                    .clone()
+                    .ok_or_else(|| "Tool message is missing tool_call_id".to_owned())?;
+                let text = message
+                    .content
+                    .as_ref()
+                    .map(|c| c.text())
+                    .unwrap_or_default();
+                let block = ContentBlock::ToolResult {
+                    tool_use_id,
+                    content: Some(AnthropicContent::Text(text)),
+                    is_error: None,
+                };
+                ("user", vec![block])
+            }
+            other => return Err(format!("Unsupported message role: {other}")),
+        };
+
+        if blocks.is_empty() {
+            continue;
+        }
+        match result.last_mut() {
+            Some(last) if last.role == role => {
+                let content =
+                    std::mem::replace(&mut last.content, AnthropicContent::Text(String::new()));
+                let mut merged = content.into_blocks();
+                merged.extend(blocks);
+                last.content = AnthropicContent::Blocks(merged);
+            }
+            _ => result.push(AnthropicMessage {
+                role: role.to_owned(),
+                content: AnthropicContent::Blocks(blocks),
+            }),
+        }
+    }
+
+    let system = (!system.is_empty()).then(|| AnthropicContent::Text(system.join("\n\n")));
+    Ok((system, result))
+}
+
+/// 将 OpenAI 消息内容转换为 Anthropic 内容块
+fn content_to_blocks(content: Option<&MessageContent>) -> Result<Vec<ContentBlock>, String> {
+    let Some(content) = content else {
+        return Ok(Vec::new());
+    };
+    match content {
+        MessageContent::Text(text) if text.is_empty() => Ok(Vec::new()),
+        MessageContent::Text(text) => Ok(vec![ContentBlock::Text { text: text.clone() }]),
+        MessageContent::Parts(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text } => Ok(ContentBlock::Text { text: text.clone() }),
+                ContentPart::ImageUrl { image_url } => Ok(ContentBlock::Image {
+                    source: image_source(&image_url.url)?,
+                }),
+                ContentPart::InputAudio { .. } => {
+                    Err("Audio input is not supported for Claude models".to_owned())
+                }
+            })
+            .collect(),
+    }
+}
+
+/// 将图片地址转换为 base64 或 url 图片来源
+fn image_source(url: &str) -> Result<ImageSource, String> {
+    let Some(rest) = url.strip_prefix("data:") else {
+        return Ok(ImageSource::Url {
+            url: url.to_owned(),
+        });
+    };
+    let (meta, data) = rest
+        .split_once(',')
+        .ok_or_else(|| "Malformed data URL".to_owned())?;
+    let media_type = meta
+        .strip_suffix(";base64")
+        .ok_or_else(|| "Only base64 data URLs are supported".to_owned())?;
+    Ok(ImageSource::Base64 {
+        media_type: media_type.to_owned(),
+        data: data.to_owned(),
+    })
+}
+
+/// 将 Anthropic 停止原因映射为 OpenAI finish_reason
+fn map_stop_reason(reason: &str) -> &'static str {
+    match reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// 将 Anthropic 用量转换为 OpenAI usage,缓存读写的 token 计入 prompt_tokens
+pub fn usage_from_anthropic(usage: &AnthropicUsage) -> Usage {
+    let cached = usage.cache_read_input_tokens.unwrap_or(0);
+    let prompt_tokens =
+        usage.input_tokens + cached + usage.cache_creation_input_tokens.unwrap_or(0);
+    Usage {
+        prompt_tokens,
+        completion_tokens: usage.output_tokens,
+        total_tokens: prompt_tokens + usage.output_tokens,
+        prompt_tokens_details: Some(PromptTokensDetails {
+            cached_tokens: cached,
+        }),
+        completion_tokens_details: None,
+    }
+}
+
+/// 生成 OpenAI 风格的响应 ID
+fn completion_id(message_id: &str) -> String {
+    format!("chatcmpl-{}", message_id.trim_start_matches("msg_"))
+}
+
+/// 将 Anthropic 非流式响应转换为 OpenAI chat.completion
+pub fn to_chat_completion(response: MessagesResponse, model: &str) -> ChatCompletionResponse {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in &response.content {
+        match block {
+            ContentBlock::Text { text: t } => text.push_str(t),
+            ContentBlock::Thinking { thinking, .. } => reasoning.push_str(thinking),
+            ContentBlock::ToolUse { id, name, input } => tool_calls.push(ToolCall {
+                index: None,
+                id: id.clone(),
+                kind: "function".to_owned(),
+                function: FunctionCall {
+                    name: name.clone(),
+                    arguments: input.to_string(),
+                },
+            }),
+            _ => {}
+        }
+    }
+    let finish_reason = map_stop_reason(response.stop_reason.as_deref().unwrap_or("end_turn"));
+
+    ChatCompletionResponse {
+        id: completion_id(&response.id),
+        object: "chat.completion".to_owned(),
+        created: chrono::Utc::now().timestamp(),
+        model: model.to_owned(),
+        choices: vec![Choice {
+            index: 0,
+            message: Message {
+                role: "assistant".to_owned(),
+                content: Some(MessageContent::Text(text)),
+                reasoning_content: (!reasoning.is_empty()).then_some(reasoning),
+                tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                ..Default::default()
+            },
+            finish_reason: Some(finish_reason.to_owned()),
+        }],
+        usage: Some(usage_from_anthropic(&response.usage)),
+    }
+}
+
+/// Anthropic streamRawPredict SSE -> OpenAI chat.completion.chunk 转换器
+pub struct AnthropicStreamTranslator {
+    id: String,
+    created: i64,
+    model: String,
+    include_usage: bool,
+    /// 上游报告的用量,收到 `message_start` 前为 `None`
+    usage: Option<AnthropicUsage>,
+    /// 内容块序号 -> 工具调用序号
+    tool_indexes: HashMap<u32, u32>,
+    finished: bool,
+}
+
+impl AnthropicStreamTranslator {
+    pub fn new(model: &str, include_usage: bool) -> Self {
+        Self {
+            id: completion_id(""),
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_owned(),
+            include_usage,
+            usage: None,
+            tool_indexes: HashMap::new(),
+            finished: false,
+        }
+    }
+
+    fn chunk(&self, delta: Delta, finish_reason: Option<String>) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_owned(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                finish_reason,
+            }],
+            usage: None,
+        }
+    }
+
+    /// 将一个 Anthropic 流式事件转换为 OpenAI 响应块
+    pub fn convert(&mut self, event: StreamEvent) -> Option<ChatCompletionChunk> {
+        match event {
+            StreamEvent::MessageStart { message } => {
+                self.id = completion_id(&message.id);
+                self.usage = Some(message.usage);
+                Some(self.chunk(
+                    Delta {
+                        role: Some("assistant".to_owned()),
+                        content: Some(String::new()),
+                        ..Default::default()
+                    },
+                    None,
+                ))
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => match content_block {
+                ContentBlock::ToolUse { id, name, .. } => {
+                    let tool_index = self.tool_indexes.len() as u32;
+                    self.tool_indexes.insert(index, tool_index);
+                    Some(self.chunk(
+                        Delta {
+                            tool_calls: Some(vec![ToolCall {
+                                index: Some(tool_index),
+                                id,
+                                kind: "function".to_owned(),
+                                function: FunctionCall {
+                                    name,
+                                    arguments: String::new(),
+                                },
+                            }]),
+                            ..Default::default()
+                        },
+                        None,
+                    ))
+                }
+                ContentBlock::Text { text } if !text.is_empty() => Some(self.chunk(
+                    Delta {
+                        content: Some(text),
+                        ..Default::default()
+                    },
+                    None,
+                )),
+                _ => None,
+            },
+            StreamEvent::ContentBlockDelta { index, delta } => {
+                let delta = match delta {
+                    BlockDelta::TextDelta { text } => Delta {
+                        content: Some(text),
+                        ..Default::default()
+                    },
+                    BlockDelta::ThinkingDelta { thinking } => Delta {
+                        reasoning_content: Some(thinking),
+                        ..Default::default()
+                    },
+                    BlockDelta::InputJsonDelta { partial_json } => Delta {
+                        tool_calls: Some(vec![ToolCall {
+                            index: self.tool_indexes.get(&index).copied(),
+                            id: String::new(),
+                            kind: "function".to_owned(),
+                            function: FunctionCall {
+                                name: String::new(),
+                                arguments: partial_json,
+                            },
+                        }]),
+                        ..Default::default()
+                    },
+                    BlockDelta::SignatureDelta { .. } | BlockDelta::Unknown => return None,
+                };
+                Some(self.chunk(delta, None))
+            }
+            StreamEvent::MessageDelta { delta, usage } => {
+                if let Some(usage) = usage {
+                    self.usage
+                        .get_or_insert_with(AnthropicUsage::default)
+                        .output_tokens = usage.output_tokens;
+                }
+                let reason = delta.stop_reason.as_deref()?;
+                self.finished = true;
+                Some(self.chunk(Delta::default(), Some(map_stop_reason(reason).to_owned())))
+            }
+            StreamEvent::ContentBlockStop { .. } | StreamEvent::MessageStop | StreamEvent::Ping => {
+                None
+            }
+            StreamEvent::Error { .. } => None,
+        }
+    }
+
+    /// 流结束时的 usage 块
+    pub fn usage_chunk(&self) -> Option<ChatCompletionChunk> {
+        if !self.include_usage {
+            return None;
+        }
+        Some(ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk".to_owned(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: Vec::new(),
+            usage: Some(self.usage().unwrap_or_default()),
+        })
+    }
+}
+
+impl EventTranslator for AnthropicStreamTranslator {
+    fn on_event(&mut self, event: SseEvent, out: &mut String) {
+        match serde_json::from_str::<StreamEvent>(&event.data) {
+            Ok(StreamEvent::Error { error }) => {
+                // 上游在流中途返回错误,转换为 OpenAI 错误对象
+                tracing::error!("Claude stream returned error: {}", error);
+                let message = error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Upstream stream error");
+                format_data(out, &GatewayError::bad_gateway(message).body().to_string());
+            }
+            Ok(event) => {
+                if let Some(chunk) = self.convert(event) {
+                    format_data(out, &serde_json::to_string(&chunk).unwrap_or_default());
+                }
+            }
+            Err(e) => {
+                tracing::debug!("Ignoring Claude stream event ({}): {}", e, event.data);
+            }
+        }
+    }
+
+    fn on_end(&mut self, out: &mut String) {
+        if !self.finished {
+            tracing::warn!("Claude stream ended without stop reason");
+        }
+        if let Some(chunk) = self.usage_chunk() {
+            format_data(out, &serde_json::to_string(&chunk).unwrap_or_default());
+        }
+        format_data(out, "[DONE]");
+    }
+
+    fn on_error(&mut self, message: &str, out: &mut String) {
+        let error = GatewayError::bad_gateway(message);
+        format_data(out, &error.body().to_string());
+        format_data(out, "[DONE]");
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        self.usage.as_ref().map(usage_from_anthropic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_messages_request_maps_system_tools_and_results() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "toolu_1", "type": "function",
+                     "function": {"name": "lookup", "arguments": "{\"q\":\"x\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "toolu_1", "content": "found"},
+                {"role": "user", "content": "thanks"}
+            ],
+            "stop": ["END"],
+            "tools": [{"type": "function", "function": {"name": "lookup"}}],
+            "tool_choice": "required"
+        }))
+        .unwrap();
+        let body = serde_json::to_value(to_messages_request(&request).unwrap()).unwrap();
+        assert_eq!(body["anthropic_version"], VERTEX_ANTHROPIC_VERSION);
+        assert!(body.get("model").is_none());
+        assert_eq!(body["system"], "be brief");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(body["stop_sequences"], json!(["END"]));
+        assert_eq!(body["tool_choice"], json!({"type": "any"}));
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0]["content"][1]["source"]["media_type"],
+            "image/png"
+        );
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][0]["input"]["q"], "x");
+        // tool_result 与随后的 user 消息合并为一轮
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][1]["text"], "thanks");
+    }
+
+    #[test]
+    fn test_stream_translator_converts_tool_use() {
+        let mut translator = AnthropicStreamTranslator::new("claude-sonnet-4-5", true);
+        let events = [
+            r#"{"type":"message_start","message":{"id":"msg_1","type":"message","role":"assistant","content":[],"model":"claude","usage":{"input_tokens":10,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_1","name":"lookup","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{\"q\":"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":7}}"#,
+        ];
+        let chunks: Vec<ChatCompletionChunk> = events
+            .iter()
+            .filter_map(|e| translator.convert(serde_json::from_str(e).unwrap()))
+            .collect();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0].id, "chatcmpl-1");
+        let call = &chunks[1].choices[0].delta.tool_calls.as_ref().unwrap()[0];
+        assert_eq!((call.index, call.id.as_str()), (Some(0), "toolu_1"));
+        let args = &chunks[2].choices[0].delta.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(args.function.arguments, "{\"q\":");
+        assert_eq!(
+            chunks[3].choices[0].finish_reason.as_deref(),
+            Some("tool_calls")
+        );
+        let usage = translator.usage_chunk().unwrap().usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (10, 7));
+    }
+}
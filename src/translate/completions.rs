@@ -0,0 +1,212 @@
+//! OpenAI 文本补全格式与聊天完成格式互转
+//!
+//! 用于 `/v1/completions` 接口: 每个提示包装为一条 user 消息,
+//! 聊天完成响应和 `chat.completion.chunk` 流再转换为 `text_completion`
+
+use crate::error::GatewayError;
+use crate::models::completions::{CompletionChoice, CompletionRequest, CompletionResponse};
+use crate::models::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, Message, MessageContent,
+};
+use crate::sse::{format_data, EventTranslator, SseEvent};
+use std::collections::HashSet;
+
+/// 生成文本补全 ID
+pub fn completion_id() -> String {
+    format!(
+        "cmpl-{:x}",
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+    )
+}
+
+/// 将一个提示包装为聊天完成请求
+pub fn to_chat_request(request: &CompletionRequest, prompt: &str) -> ChatCompletionRequest {
+    ChatCompletionRequest {
+        model: request.model.clone(),
+        messages: vec![Message {
+            role: "user".to_owned(),
+            content: Some(MessageContent::Text(prompt.to_owned())),
+            ..Default::default()
+        }],
+        temperature: request.temperature,
+        top_p: request.top_p,
+        n: request.n,
+        max_tokens: request.max_tokens,
+        stop: request.stop.clone(),
+        presence_penalty: request.presence_penalty,
+        frequency_penalty: request.frequency_penalty,
+        seed: request.seed,
+        stream: Some(request.is_stream()),
+        stream_options: request.stream_options.clone(),
+        user: request.user.clone(),
+        ..Default::default()
+    }
+}
+
+/// 将一个提示的聊天完成响应转换为补全结果
+///
+/// 多个提示时结果下标为 `提示下标 * n + 选项下标`,与 OpenAI 一致
+pub fn to_choices(
+    chat: ChatCompletionResponse,
+    prompt: &str,
+    prompt_index: u32,
+    request: &CompletionRequest,
+) -> Vec<CompletionChoice> {
+    let n = request.n.unwrap_or(1).max(1);
+    chat.choices
+        .into_iter()
+        .map(|choice| {
+            let mut text = if request.echo() {
+                prompt.to_owned()
+            } else {
+                String::new()
+            };
+            if let Some(content) = &choice.message.content {
+                text.push_str(&content.text());
+            }
+            CompletionChoice {
+                text,
+                index: prompt_index * n + choice.index,
+                logprobs: None,
+                finish_reason: choice.finish_reason,
+            }
+        })
+        .collect()
+}
+
+/// OpenAI chat.completion.chunk SSE -> text_completion 流式块转换器
+pub struct CompletionStreamTranslator {
+    id: String,
+    created: i64,
+    model: String,
+    /// echo 时需要附加的提示
+    echo: Option<String>,
+    /// 已附加过提示的选项
+    echoed: HashSet<u32>,
+}
+
+impl CompletionStreamTranslator {
+    pub fn new(model: &str, echo: Option<String>) -> Self {
+        Self {
+            id: completion_id(),
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_owned(),
+            echo,
+            echoed: HashSet::new(),
+        }
+    }
+
+    /// 将一个聊天响应块转换为补全块,思考内容和工具调用被忽略
+    pub fn convert(&mut self, chunk: ChatCompletionChunk) -> Option<CompletionResponse> {
+        let mut choices = Vec::new();
+        for choice in chunk.choices {
+            let mut text = String::new();
+            if let Some(prompt) = &self.echo {
+                if self.echoed.insert(choice.index) {
+                    text.push_str(prompt);
+                }
+            }
+            if let Some(content) = choice.delta.content {
+                text.push_str(&content);
+            }
+            if text.is_empty() && choice.finish_reason.is_none() {
+                continue;
+            }
+            choices.push(CompletionChoice {
+                text,
+                index: choice.index,
+                logprobs: None,
+                finish_reason: choice.finish_reason,
+            });
+        }
+
+        if choices.is_empty() && chunk.usage.is_none() {
+            return None;
+        }
+        Some(CompletionResponse {
+            id: self.id.clone(),
+            object: "text_completion",
+            created: self.created,
+            model: self.model.clone(),
+            choices,
+            usage: chunk.usage,
+        })
+    }
+}
+
+impl EventTranslator for CompletionStreamTranslator {
+    fn on_event(&mut self, event: SseEvent, out: &mut String) {
+        if event.data == "[DONE]" {
+            return;
+        }
+        match serde_json::from_str::<ChatCompletionChunk>(&event.data) {
+            Ok(chunk) => {
+                if let Some(completion) = self.convert(chunk) {
+                    format_data(out, &serde_json::to_string(&completion).unwrap_or_default());
+                }
+            }
+            // 通常是错误对象,原样透传给客户端
+            Err(_) => format_data(out, &event.data),
+        }
+    }
+
+    fn on_end(&mut self, out: &mut String) {
+        format_data(out, "[DONE]");
+    }
+
+    fn on_error(&mut self, message: &str, out: &mut String) {
+        let error = GatewayError::bad_gateway(message);
+        format_data(out, &error.body().to_string());
+        format_data(out, "[DONE]");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(body: serde_json::Value) -> CompletionRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_to_choices_applies_echo_and_batch_index() {
+        let request = request(json!({
+            "model": "gemini-2.5-flash", "prompt": ["a", "Say"], "n": 2, "echo": true
+        }));
+        let chat: ChatCompletionResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1", "object": "chat.completion", "created": 0, "model": "m",
+            "choices": [
+                {"index": 0, "message": {"role": "assistant", "content": " hi"}, "finish_reason": "stop"},
+                {"index": 1, "message": {"role": "assistant", "content": " yo"}, "finish_reason": "length"}
+            ]
+        }))
+        .unwrap();
+
+        let choices = to_choices(chat, "Say", 1, &request);
+        assert_eq!(choices[0].text, "Say hi");
+        assert_eq!(choices[0].index, 2);
+        assert_eq!(choices[1].index, 3);
+        assert_eq!(choices[1].finish_reason.as_deref(), Some("length"));
+    }
+
+    #[test]
+    fn test_stream_translator_echoes_prompt_once() {
+        let mut translator = CompletionStreamTranslator::new("gemini-2.5-flash", Some("Q:".into()));
+        let chunk = |content: &str, finish: Option<&str>| -> ChatCompletionChunk {
+            serde_json::from_value(json!({
+                "id": "c", "object": "chat.completion.chunk", "created": 0, "model": "m",
+                "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish}]
+            }))
+            .unwrap()
+        };
+
+        let first = translator.convert(chunk(" A", None)).unwrap();
+        assert_eq!(first.object, "text_completion");
+        assert_eq!(first.choices[0].text, "Q: A");
+        let last = translator.convert(chunk("B", Some("stop"))).unwrap();
+        assert_eq!(last.choices[0].text, "B");
+        assert_eq!(last.choices[0].finish_reason.as_deref(), Some("stop"));
+    }
+}
@@ -0,0 +1,712 @@
+//! OpenAI 聊天格式与 Gemini generateContent 格式互转
+
+use crate::error::GatewayError;
+use crate::models::gemini::{
+    Blob, Candidate, Content, CountTokensRequest, CountTokensResponse, FileData,
+    FunctionCallingConfig, FunctionDeclaration, GeminiFunctionCall, GeminiFunctionResponse,
+    GeminiTool, GenerateContentRequest, GenerateContentResponse, GenerationConfig, Part,
+    ThinkingConfig, ToolConfig, UsageMetadata,
+};
+use crate::models::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, Choice, ChunkChoice,
+    CompletionTokensDetails, ContentPart, Delta, FunctionCall, Message, MessageContent,
+    PromptTokensDetails, ResponseFormat, TokenCount, ToolCall, ToolChoice, Usage,
+};
+use crate::sse::{format_data, EventTranslator, SseEvent};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// Gemini 3 要求历史中的函数调用携带思考签名,无法还原时使用官方提供的占位值
+pub(crate) const SKIP_THOUGHT_SIGNATURE: &str = "skip_thought_signature_validator";
+
+/// 将 OpenAI 聊天请求转换为 Gemini generateContent 请求
+pub fn to_generate_content(
+    request: &ChatCompletionRequest,
+) -> Result<GenerateContentRequest, String> {
+    let needs_signature = request.model.contains("gemini-3");
+    let (system_instruction, contents) = messages_to_contents(&request.messages, needs_signature)?;
+
+    let response_format = request.response_format.as_ref();
+    let generation_config = GenerationConfig {
+        temperature: request.temperature,
+        top_p: request.top_p,
+        top_k: None,
+        candidate_count: request.n,
+        max_output_tokens: request.max_completion_tokens.or(request.max_tokens),
+        stop_sequences: request.stop.as_ref().map(|s| s.to_vec()),
+        presence_penalty: request.presence_penalty,
+        frequency_penalty: request.frequency_penalty,
+        seed: request.seed,
+        response_mime_type: match response_format {
+            Some(ResponseFormat::JsonObject) | Some(ResponseFormat::JsonSchema { .. }) => {
+                Some("application/json".to_owned())
+            }
+            _ => None,
+        },
+        response_json_schema: match response_format {
+            Some(ResponseFormat::JsonSchema { json_schema }) => json_schema.schema.clone(),
+            _ => None,
+        },
+        thinking_config: request
+            .reasoning_effort
+            .as_deref()
+            .map(thinking_config)
+            .transpose()?,
+    };
+
+    let tools = request
+        .tools
+        .as_ref()
+        .filter(|t| !t.is_empty())
+        .map(|tools| {
+            vec![GeminiTool {
+                function_declarations: tools
+                    .iter()
+                    .map(|t| FunctionDeclaration {
+                        name: t.function.name.clone(),
+                        description: t.function.description.clone(),
+                        parameters_json_schema: t.function.parameters.clone(),
+                    })
+                    .collect(),
+            }]
+        });
+
+    let tool_config = request.tool_choice.as_ref().map(|choice| {
+        let (mode, allowed) = match choice {
+            ToolChoice::Mode(mode) => match mode.as_str() {
+                "none" => ("NONE", None),
+                "required" => ("ANY", None),
+                _ => ("AUTO", None),
+            },
+            ToolChoice::Named(named) => ("ANY", Some(vec![named.function.name.clone()])),
+        };
+        ToolConfig {
+            function_calling_config: FunctionCallingConfig {
+                mode: mode.to_owned(),
+                allowed_function_names: allowed,
+            },
+        }
+    });
+
+    Ok(GenerateContentRequest {
+        contents,
+        system_instruction,
+        generation_config: Some(generation_config),
+        safety_settings: request.safety_settings.clone(),
+        tools,
+        tool_config,
+        cached_content: None,
+    })
+}
+
+/// 将 OpenAI 聊天请求转换为 Gemini countTokens 请求,只保留计入输入 token 的部分
+pub fn to_count_tokens(request: &ChatCompletionRequest) -> Result<CountTokensRequest, String> {
+    let GenerateContentRequest {
+        contents,
+        system_instruction,
+        tools,
+        ..
+    } = to_generate_content(request)?;
+    Ok(CountTokensRequest {
+        contents,
+        system_instruction,
+        tools,
+    })
+}
+
+/// 将 Gemini countTokens 响应转换为令牌计数,模态名称转为小写
+pub fn to_token_count(response: CountTokensResponse, model: &str) -> TokenCount {
+    let mut modalities = BTreeMap::new();
+    for detail in response.prompt_tokens_details {
+        *modalities
+            .entry(detail.modality.to_ascii_lowercase())
+            .or_default() += detail.token_count;
+    }
+    TokenCount {
+        object: "token_count",
+        model: model.to_owned(),
+        total_tokens: response.total_tokens,
+        modalities,
+        total_billable_characters: response.total_billable_characters,
+    }
+}
+
+/// 将 reasoning_effort 映射为思考预算,与 Vertex openapi 端点的映射保持一致
+fn thinking_config(effort: &str) -> Result<ThinkingConfig, String> {
+    let budget = match effort {
+        "none" => 0,
+        "minimal" | "low" => 1024,
+        "medium" => 8192,
+        "high" => 24576,
+        other => return Err(format!("Unsupported reasoning_effort: {other}")),
+    };
+    Ok(ThinkingConfig {
+        include_thoughts: None,
+        thinking_budget: Some(budget),
+    })
+}
+
+/// 将 OpenAI 消息列表转换为 Gemini 的 systemInstruction 和 contents
+///
+/// - system/developer 消息合并为 systemInstruction
+/// - assistant 消息映射为 model 角色,tool_calls 映射为 functionCall
+/// - tool 消息映射为 user 角色的 functionResponse
+/// - 相邻的同角色内容合并为一轮
+pub fn messages_to_contents(
+    messages: &[Message],
+    needs_signature: bool,
+) -> Result<(Option<Content>, Vec<Content>), String> {
+    let mut system_parts = Vec::new();
+    let mut contents: Vec<Content> = Vec::new();
+    // tool_call_id -> 函数名,用于还原 functionResponse 的名称
+    let mut tool_names: HashMap<String, String> = HashMap::new();
+
+    for message in messages {
+        let (role, parts) = match message.role.as_str() {
+            "system" | "developer" => {
+                if let Some(content) = &message.content {
+                    system_parts.push(Part::text(content.text()));
+                }
+                continue;
+            }
+            "user" => ("user", content_to_parts(message.content.as_ref())?),
+            "assistant" => {
+                let mut parts = content_to_parts(message.content.as_ref())?;
+                for (i, call) in message.tool_calls.iter().flatten().enumerate() {
+                    tool_names.insert(call.id.clone(), call.function.name.clone());
+                    let args = if call.function.arguments.trim().is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(&call.function.arguments).map_err(|e| {
+                            format!(
+                                "Invalid arguments for tool call {}: {}",
+                                call.function.name, e
+                            )
+                        })?
+                    };
+                    parts.push(Part {
+                        function_call: Some(GeminiFunctionCall {
+                            name: call.function.name.clone(),
+                            args,
+                        }),
+                        thought_signature: (needs_signature && i == 0)
+                            .then(|| SKIP_THOUGHT_SIGNATURE.to_owned()),
+                        ..Default::default()
+                    });
+                }
+                ("model", parts)
+            }
+            "tool" | "function" => {
+                let name = message
+                    .tool_call_id
+                    .as_ref()
+                    .and_then(|id| tool_names.get(id).cloned())
+                    .or_else(|| message.name.clone())
+                    .ok_or_else(|| {
+                        "Tool message does not match any previous tool call".to_owned()
+                    })?;
+                let text = message
+                    .content
+                    .as_ref()
+                    .map(|c| c.text())
+                    .unwrap_or_default();
+                let response = match serde_json::from_str::<Value>(&text) {
+                    Ok(Value::Object(obj)) => Value::Object(obj),
+                    Ok(value) => json!({ "content": value }),
+                    Err(_) => json!({ "content": text }),
+                };
+                let part = Part {
+                    function_response: Some(GeminiFunctionResponse { name, response }),
+                    ..Default::default()
+                };
+                ("user", vec![part])
+            }
+            other => return Err(format!("Unsupported message role: {other}")),
+        };
+
+        if parts.is_empty() {
+            continue;
+        }
+        match contents.last_mut() {
+            Some(last) if last.role.as_deref() == Some(role) => last.parts.extend(parts),
+            _ => contents.push(Content {
+                role: Some(role.to_owned()),
+                parts,
+            }),
+        }
+    }
+
+    let system_instruction = (!system_parts.is_empty()).then_some(Content {
+        role: None,
+        parts: system_parts,
+    });
+    Ok((system_instruction, contents))
+}
+
+/// 将 OpenAI 消息内容转换为 Gemini 片段
+fn content_to_parts(content: Option<&MessageContent>) -> Result<Vec<Part>, String> {
+    let Some(content) = content else {
+        return Ok(Vec::new());
+    };
+    match content {
+        MessageContent::Text(text) if text.is_empty() => Ok(Vec::new()),
+        MessageContent::Text(text) => Ok(vec![Part::text(text.clone())]),
+        MessageContent::Parts(parts) => parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text } => Ok(Part::text(text.clone())),
+                ContentPart::ImageUrl { image_url } => url_to_part(&image_url.url),
+                ContentPart::InputAudio { input_audio } => Ok(Part {
+                    inline_data: Some(Blob {
+                        mime_type: format!("audio/{}", input_audio.format),
+                        data: input_audio.data.clone(),
+                    }),
+                    ..Default::default()
+                }),
+            })
+            .collect(),
+    }
+}
+
+/// 将图片地址转换为 inlineData(data URL)或 fileData(远程链接)
+pub fn url_to_part(url: &str) -> Result<Part, String> {
+    if let Some(rest) = url.strip_prefix("data:") {
+        let (meta, data) = rest
+            .split_once(',')
+            .ok_or_else(|| "Malformed data URL".to_owned())?;
+        let mime_type = meta
+            .strip_suffix(";base64")
+            .ok_or_else(|| "Only base64 data URLs are supported".to_owned())?;
+        return Ok(Part {
+            inline_data: Some(Blob {
+                mime_type: mime_type.to_owned(),
+                data: data.to_owned(),
+            }),
+            ..Default::default()
+        });
+    }
+    Ok(Part {
+        file_data: Some(FileData {
+            mime_type: guess_mime_type(url).to_owned(),
+            file_uri: url.to_owned(),
+        }),
+        ..Default::default()
+    })
+}
+
+/// 根据扩展名猜测文件类型
+fn guess_mime_type(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let ext = path.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        _ => "image/jpeg",
+    }
+}
+
+/// 将 Gemini 结束原因映射为 OpenAI finish_reason
+pub fn map_finish_reason(reason: &str, has_tool_calls: bool) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => {
+            "content_filter"
+        }
+        _ if has_tool_calls => "tool_calls",
+        _ => "stop",
+    }
+}
+
+/// 将 Gemini 用量元数据转换为 OpenAI usage
+pub fn usage_from_metadata(metadata: &UsageMetadata) -> Usage {
+    let completion_tokens = metadata.candidates_token_count + metadata.thoughts_token_count;
+    Usage {
+        prompt_tokens: metadata.prompt_token_count,
+        completion_tokens,
+        total_tokens: metadata
+            .total_token_count
+            .max(metadata.prompt_token_count + completion_tokens),
+        prompt_tokens_details: Some(PromptTokensDetails {
+            cached_tokens: metadata.cached_content_token_count,
+        }),
+        completion_tokens_details: Some(CompletionTokensDetails {
+            reasoning_tokens: metadata.thoughts_token_count,
+        }),
+    }
+}
+
+/// 拆分候选结果中的正文、思考内容和函数调用
+fn split_candidate(
+    candidate: &Candidate,
+    call_id: &mut impl FnMut() -> String,
+) -> (String, String, Vec<ToolCall>) {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for part in candidate.content.iter().flat_map(|c| c.parts.iter()) {
+        if let Some(call) = &part.function_call {
+            tool_calls.push(ToolCall {
+                index: None,
+                id: call_id(),
+                kind: "function".to_owned(),
+                function: FunctionCall {
+                    name: call.name.clone(),
+                    arguments: call.args.to_string(),
+                },
+            });
+        } else if let Some(t) = &part.text {
+            if part.is_thought() {
+                reasoning.push_str(t);
+            } else {
+                text.push_str(t);
+            }
+        }
+    }
+    (text, reasoning, tool_calls)
+}
+
+/// 生成 OpenAI 风格的响应 ID
+fn completion_id(response_id: Option<&str>) -> String {
+    match response_id {
+        Some(id) => format!("chatcmpl-{id}"),
+        None => format!(
+            "chatcmpl-{:x}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ),
+    }
+}
+
+/// 将 Gemini 非流式响应转换为 OpenAI chat.completion
+pub fn to_chat_completion(
+    response: GenerateContentResponse,
+    model: &str,
+) -> ChatCompletionResponse {
+    let id = completion_id(response.response_id.as_deref());
+    let mut counter = 0;
+    let mut call_id = || {
+        counter += 1;
+        format!("call_{}_{}", id.trim_start_matches("chatcmpl-"), counter)
+    };
+
+    let choices = response
+        .candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let (text, reasoning, tool_calls) = split_candidate(candidate, &mut call_id);
+            let finish_reason = map_finish_reason(
+                candidate.finish_reason.as_deref().unwrap_or("STOP"),
+                !tool_calls.is_empty(),
+            );
+            Choice {
+                index: candidate.index.unwrap_or(i as u32),
+                message: Message {
+                    role: "assistant".to_owned(),
+                    content: Some(MessageContent::Text(text)),
+                    reasoning_content: (!reasoning.is_empty()).then_some(reasoning),
+                    tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+                    ..Default::default()
+                },
+                finish_reason: Some(finish_reason.to_owned()),
+            }
+        })
+        .collect();
+
+    ChatCompletionResponse {
+        id,
+        object: "chat.completion".to_owned(),
+        created: chrono::Utc::now().timestamp(),
+        model: model.to_owned(),
+        choices,
+        usage: response.usage_metadata.as_ref().map(usage_from_metadata),
+    }
+}
+
+/// Gemini streamGenerateContent SSE -> OpenAI chat.completion.chunk 转换器
+pub struct GeminiStreamTranslator {
+    id: Option<String>,
+    created: i64,
+    model: String,
+    include_usage: bool,
+    /// 每个候选是否已发送过 role
+    role_sent: HashMap<u32, bool>,
+    /// 每个候选已发出的工具调用数量
+    tool_calls: HashMap<u32, u32>,
+    usage: Option<Usage>,
+    call_counter: u32,
+}
+
+impl GeminiStreamTranslator {
+    pub fn new(model: &str, include_usage: bool) -> Self {
+        Self {
+            id: None,
+            created: chrono::Utc::now().timestamp(),
+            model: model.to_owned(),
+            include_usage,
+            role_sent: HashMap::new(),
+            tool_calls: HashMap::new(),
+            usage: None,
+            call_counter: 0,
+        }
+    }
+
+    /// 将一个 Gemini 响应块转换为 OpenAI 响应块
+    pub fn convert(&mut self, response: GenerateContentResponse) -> Option<ChatCompletionChunk> {
+        let id = self
+            .id
+            .get_or_insert_with(|| completion_id(response.response_id.as_deref()))
+            .clone();
+        if let Some(metadata) = &response.usage_metadata {
+            self.usage = Some(usage_from_metadata(metadata));
+        }
+
+        let mut choices = Vec::new();
+        for (i, candidate) in response.candidates.iter().enumerate() {
+            let index = candidate.index.unwrap_or(i as u32);
+            let counter = &mut self.call_counter;
+            let mut call_id = || {
+                *counter += 1;
+                format!("call_{}_{}", id.trim_start_matches("chatcmpl-"), counter)
+            };
+            let (text, reasoning, mut tool_calls) = split_candidate(candidate, &mut call_id);
+
+            let emitted = self.tool_calls.entry(index).or_insert(0);
+            for call in tool_calls.iter_mut() {
+                call.index = Some(*emitted);
+                *emitted += 1;
+            }
+            let has_tool_calls = *emitted > 0;
+
+            let role_sent = self.role_sent.entry(index).or_insert(false);
+            let delta = Delta {
+                role: (!*role_sent).then(|| "assistant".to_owned()),
+                content: (!text.is_empty()).then_some(text),
+                reasoning_content: (!reasoning.is_empty()).then_some(reasoning),
+                tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+            };
+            *role_sent = true;
+            let finish_reason = candidate
+                .finish_reason
+                .as_deref()
+                .map(|r| map_finish_reason(r, has_tool_calls).to_owned());
+
+            choices.push(ChunkChoice {
+                index,
+                delta,
+                finish_reason,
+            });
+        }
+
+        if choices.is_empty() {
+            return None;
+        }
+        Some(ChatCompletionChunk {
+            id,
+            object: "chat.completion.chunk".to_owned(),
+            created: self.created,
+            model: self.model.clone(),
+            choices,
+            usage: None,
+        })
+    }
+
+    /// 流结束时的 usage 块
+    pub fn usage_chunk(&self) -> Option<ChatCompletionChunk> {
+        if !self.include_usage {
+            return None;
+        }
+        Some(ChatCompletionChunk {
+            id: self.id.clone().unwrap_or_else(|| completion_id(None)),
+            object: "chat.completion.chunk".to_owned(),
+            created: self.created,
+            model: self.model.clone(),
+            choices: Vec::new(),
+            usage: Some(self.usage.clone().unwrap_or_default()),
+        })
+    }
+}
+
+impl EventTranslator for GeminiStreamTranslator {
+    fn on_event(&mut self, event: SseEvent, out: &mut String) {
+        if event.data == "[DONE]" {
+            return;
+        }
+        match serde_json::from_str::<GenerateContentResponse>(&event.data) {
+            Ok(response) => {
+                if let Some(chunk) = self.convert(response) {
+                    format_data(out, &serde_json::to_string(&chunk).unwrap_or_default());
+                }
+            }
+            Err(e) => {
+                // 通常是上游返回的错误对象,原样透传给客户端
+                tracing::warn!("Unexpected Gemini stream event ({}): {}", e, event.data);
+                format_data(out, &event.data);
+            }
+        }
+    }
+
+    fn on_end(&mut self, out: &mut String) {
+        if let Some(chunk) = self.usage_chunk() {
+            format_data(out, &serde_json::to_string(&chunk).unwrap_or_default());
+        }
+        format_data(out, "[DONE]");
+    }
+
+    fn on_error(&mut self, message: &str, out: &mut String) {
+        let error = GatewayError::bad_gateway(message);
+        format_data(out, &error.body().to_string());
+        format_data(out, "[DONE]");
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        self.usage.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: Value) -> ChatCompletionRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_messages_to_contents_maps_roles_and_tools() {
+        let req = request(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "weather?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,AAAA"}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function",
+                     "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "sunny"}
+            ],
+            "stop": "END",
+            "max_tokens": 100
+        }));
+        let gemini = to_generate_content(&req).unwrap();
+
+        assert_eq!(
+            gemini.system_instruction.unwrap().parts[0].text.as_deref(),
+            Some("be brief")
+        );
+        assert_eq!(gemini.contents.len(), 3);
+        assert_eq!(
+            gemini.contents[0].parts[1]
+                .inline_data
+                .as_ref()
+                .unwrap()
+                .mime_type,
+            "image/png"
+        );
+        assert_eq!(gemini.contents[1].role.as_deref(), Some("model"));
+        assert_eq!(
+            gemini.contents[1].parts[0]
+                .function_call
+                .as_ref()
+                .unwrap()
+                .args["city"],
+            "Paris"
+        );
+        let response = gemini.contents[2].parts[0]
+            .function_response
+            .as_ref()
+            .unwrap();
+        assert_eq!(response.name, "get_weather");
+        assert_eq!(response.response, json!({"content": "sunny"}));
+        let config = gemini.generation_config.unwrap();
+        assert_eq!(config.stop_sequences, Some(vec!["END".to_owned()]));
+        assert_eq!(config.max_output_tokens, Some(100));
+    }
+
+    #[test]
+    fn test_to_chat_completion_maps_tool_calls_and_usage() {
+        let response: GenerateContentResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "thinking", "thought": true},
+                    {"functionCall": {"name": "get_weather", "args": {"city": "Paris"}}}
+                ]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 5,
+                              "thoughtsTokenCount": 3, "totalTokenCount": 18},
+            "responseId": "abc"
+        }))
+        .unwrap();
+        let completion = to_chat_completion(response, "gemini-2.5-flash");
+
+        assert_eq!(completion.id, "chatcmpl-abc");
+        let choice = &completion.choices[0];
+        assert_eq!(choice.finish_reason.as_deref(), Some("tool_calls"));
+        assert_eq!(
+            choice.message.reasoning_content.as_deref(),
+            Some("thinking")
+        );
+        let call = &choice.message.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(call.function.arguments, r#"{"city":"Paris"}"#);
+        let usage = completion.usage.unwrap();
+        assert_eq!(usage.completion_tokens, 8);
+        assert_eq!(usage.total_tokens, 18);
+    }
+
+    #[test]
+    fn test_to_count_tokens_keeps_only_input() {
+        let req = request(json!({
+            "model": "gemini-2.5-flash",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": "weather?"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "max_tokens": 100,
+            "temperature": 0.2
+        }));
+        let body = serde_json::to_value(to_count_tokens(&req).unwrap()).unwrap();
+
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "be brief");
+        assert_eq!(body["contents"][0]["role"], "user");
+        assert_eq!(body["contents"][0]["parts"][0]["text"], "weather?");
+        assert_eq!(
+            body["tools"][0]["functionDeclarations"][0]["name"],
+            "get_weather"
+        );
+        // 生成参数不计入输入 token,countTokens 也不接受
+        assert!(body.get("generationConfig").is_none());
+        assert_eq!(body.as_object().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_to_token_count_groups_modalities() {
+        let response: CountTokensResponse = serde_json::from_value(json!({
+            "totalTokens": 300,
+            "totalBillableCharacters": 42,
+            "promptTokensDetails": [
+                {"modality": "TEXT", "tokenCount": 42},
+                {"modality": "IMAGE", "tokenCount": 258}
+            ]
+        }))
+        .unwrap();
+
+        let count = to_token_count(response, "gemini-2.5-flash");
+        assert_eq!(count.total_tokens, 300);
+        assert_eq!(count.modalities["text"], 42);
+        assert_eq!(count.modalities["image"], 258);
+        assert_eq!(count.total_billable_characters, Some(42));
+    }
+}
@@ -0,0 +1,591 @@
+//! Anthropic Messages 格式与 Gemini generateContent 格式互转
+//!
+//! 用于 `/v1/messages` 接口,让 Anthropic SDK 调用 Vertex AI 上的 Gemini 模型
+
+use super::gemini::{url_to_part, usage_from_metadata, SKIP_THOUGHT_SIGNATURE};
+use crate::models::anthropic::{
+    AnthropicContent, AnthropicToolChoice, AnthropicUsage, BlockDelta, ContentBlock, ImageSource,
+    MessageDelta, MessagesRequest, MessagesResponse, StreamEvent,
+};
+use crate::models::gemini::{
+    Blob, Content, FunctionCallingConfig, FunctionDeclaration, GeminiFunctionCall,
+    GeminiFunctionResponse, GeminiTool, GenerateContentRequest, GenerateContentResponse,
+    GenerationConfig, Part, ThinkingConfig, ToolConfig, UsageMetadata,
+};
+use crate::models::Usage;
+use crate::sse::{format_event, EventTranslator, SseEvent};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// 将 Anthropic Messages 请求转换为 Gemini generateContent 请求
+pub fn to_generate_content(
+    request: &MessagesRequest,
+    model: &str,
+) -> Result<GenerateContentRequest, String> {
+    let needs_signature = model.contains("gemini-3");
+
+    let system_instruction = request
+        .system
+        .as_ref()
+        .map(AnthropicContent::text)
+        .filter(|t| !t.is_empty())
+        .map(|text| Content {
+            role: None,
+            parts: vec![Part::text(text)],
+        });
+
+    // tool_use id -> 函数名,用于还原 functionResponse 的名称
+    let mut tool_names: HashMap<String, String> = HashMap::new();
+    let mut contents: Vec<Content> = Vec::new();
+    for message in &request.messages {
+        let role = match message.role.as_str() {
+            "user" => "user",
+            "assistant" => "model",
+            other => return Err(format!("Unsupported message role: {other}")),
+        };
+        let mut parts = Vec::new();
+        let mut first_call = true;
+        for block in message.content.clone().into_blocks() {
+            match block {
+                ContentBlock::Text { text } if text.is_empty() => {}
+                ContentBlock::Text { text } => parts.push(Part::text(text)),
+                ContentBlock::Image { source } => parts.push(match source {
+                    ImageSource::Base64 { media_type, data } => Part {
+                        inline_data: Some(Blob {
+                            mime_type: media_type,
+                            data,
+                        }),
+                        ..Default::default()
+                    },
+                    ImageSource::Url { url } => url_to_part(&url)?,
+                }),
+                ContentBlock::ToolUse { id, name, input } => {
+                    tool_names.insert(id, name.clone());
+                    parts.push(Part {
+                        function_call: Some(GeminiFunctionCall { name, args: input }),
+                        thought_signature: (needs_signature && first_call)
+                            .then(|| SKIP_THOUGHT_SIGNATURE.to_owned()),
+                        ..Default::default()
+                    });
+                    first_call = false;
+                }
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } => {
+                    let name = tool_names.get(&tool_use_id).cloned().ok_or_else(|| {
+                        format!("tool_result {tool_use_id} does not match any previous tool_use")
+                    })?;
+                    let text = content
+                        .as_ref()
+                        .map(AnthropicContent::text)
+                        .unwrap_or_default();
+                    let mut response = match serde_json::from_str::<Value>(&text) {
+                        Ok(Value::Object(obj)) => Value::Object(obj),
+                        Ok(value) => json!({ "content": value }),
+                        Err(_) => json!({ "content": text }),
+                    };
+                    if is_error == Some(true) {
+                        response = json!({ "error": response });
+                    }
+                    parts.push(Part {
+                        function_response: Some(GeminiFunctionResponse { name, response }),
+                        ..Default::default()
+                    });
+                }
+                // 思考内容由 Gemini 自行管理,历史中的思考块不回传
+                ContentBlock::Thinking { .. }
+                | ContentBlock::RedactedThinking { .. }
+                | ContentBlock::Unknown => {}
+            }
+        }
+
+        if parts.is_empty() {
+            continue;
+        }
+        match contents.last_mut() {
+            Some(last) if last.role.as_deref() == Some(role) => last.parts.extend(parts),
+            _ => contents.push(Content {
+                role: Some(role.to_owned()),
+                parts,
+            }),
+        }
+    }
+
+    let thinking_config = request
+        .thinking
+        .as_ref()
+        .filter(|t| t.kind == "enabled")
+        .map(|t| ThinkingConfig {
+            include_thoughts: Some(true),
+            thinking_budget: t.budget_tokens.map(|b| b as i32),
+        });
+    let generation_config = GenerationConfig {
+        temperature: request.temperature,
+        top_p: request.top_p,
+        top_k: request.top_k,
+        max_output_tokens: Some(request.max_tokens),
+        stop_sequences: request.stop_sequences.clone(),
+        thinking_config,
+        ..Default::default()
+    };
+
+    let tools = request
+        .tools
+        .as_ref()
+        .filter(|t| !t.is_empty())
+        .map(|tools| {
+            vec![GeminiTool {
+                function_declarations: tools
+                    .iter()
+                    .map(|t| FunctionDeclaration {
+                        name: t.name.clone(),
+                        description: t.description.clone(),
+                        parameters_json_schema: Some(t.input_schema.clone()),
+                    })
+                    .collect(),
+            }]
+        });
+    let tool_config = request.tool_choice.as_ref().map(|choice| {
+        let (mode, allowed) = match choice {
+            AnthropicToolChoice::Auto => ("AUTO", None),
+            AnthropicToolChoice::Any => ("ANY", None),
+            AnthropicToolChoice::Tool { name } => ("ANY", Some(vec![name.clone()])),
+            AnthropicToolChoice::None => ("NONE", None),
+        };
+        ToolConfig {
+            function_calling_config: FunctionCallingConfig {
+                mode: mode.to_owned(),
+                allowed_function_names: allowed,
+            },
+        }
+    });
+
+    Ok(GenerateContentRequest {
+        contents,
+        system_instruction,
+        generation_config: Some(generation_config),
+        safety_settings: None,
+        tools,
+        tool_config,
+        cached_content: None,
+    })
+}
+
+/// 将 Gemini 结束原因映射为 Anthropic stop_reason
+fn map_finish_reason(reason: Option<&str>, has_tool_use: bool) -> &'static str {
+    match reason {
+        Some("MAX_TOKENS") => "max_tokens",
+        Some(
+            "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY",
+        ) => "refusal",
+        _ if has_tool_use => "tool_use",
+        _ => "end_turn",
+    }
+}
+
+/// 将 Gemini 用量元数据转换为 Anthropic usage,缓存命中的 token 单独统计
+fn usage_from_gemini(metadata: &UsageMetadata) -> AnthropicUsage {
+    let cached = metadata.cached_content_token_count;
+    AnthropicUsage {
+        input_tokens: metadata.prompt_token_count.saturating_sub(cached),
+        output_tokens: metadata.candidates_token_count + metadata.thoughts_token_count,
+        cache_creation_input_tokens: None,
+        cache_read_input_tokens: (cached > 0).then_some(cached),
+    }
+}
+
+/// 生成 Anthropic 风格的消息 ID
+fn message_id(response_id: Option<&str>) -> String {
+    match response_id {
+        Some(id) => format!("msg_{id}"),
+        None => format!(
+            "msg_{:x}",
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ),
+    }
+}
+
+/// 生成 Anthropic 风格的工具调用 ID
+fn tool_use_id(message_id: &str, counter: u32) -> String {
+    format!(
+        "toolu_{}_{}",
+        message_id.trim_start_matches("msg_"),
+        counter
+    )
+}
+
+/// 将 Gemini 非流式响应转换为 Anthropic Messages 响应(只取第一个候选)
+pub fn to_messages_response(response: GenerateContentResponse, model: &str) -> MessagesResponse {
+    let id = message_id(response.response_id.as_deref());
+    let candidate = response.candidates.first();
+    let mut content: Vec<ContentBlock> = Vec::new();
+    let mut calls = 0;
+    for part in candidate
+        .iter()
+        .flat_map(|c| c.content.iter())
+        .flat_map(|c| &c.parts)
+    {
+        if let Some(call) = &part.function_call {
+            calls += 1;
+            content.push(ContentBlock::ToolUse {
+                id: tool_use_id(&id, calls),
+                name: call.name.clone(),
+                input: call.args.clone(),
+            });
+        } else if let Some(text) = &part.text {
+            match content.last_mut() {
+                Some(ContentBlock::Thinking { thinking, .. }) if part.is_thought() => {
+                    thinking.push_str(text)
+                }
+                Some(ContentBlock::Text { text: last }) if !part.is_thought() => {
+                    last.push_str(text)
+                }
+                _ if part.is_thought() => content.push(ContentBlock::Thinking {
+                    thinking: text.clone(),
+                    signature: part.thought_signature.clone(),
+                }),
+                _ => content.push(ContentBlock::Text { text: text.clone() }),
+            }
+        }
+    }
+
+    let stop_reason = map_finish_reason(
+        candidate.and_then(|c| c.finish_reason.as_deref()),
+        calls > 0,
+    );
+    MessagesResponse {
+        id,
+        kind: "message".to_owned(),
+        role: "assistant".to_owned(),
+        content,
+        model: model.to_owned(),
+        stop_reason: Some(stop_reason.to_owned()),
+        stop_sequence: None,
+        usage: response
+            .usage_metadata
+            .as_ref()
+            .map(usage_from_gemini)
+            .unwrap_or_default(),
+    }
+}
+
+/// 当前打开的内容块类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenBlock {
+    Text,
+    Thinking,
+}
+
+/// Gemini streamGenerateContent SSE -> Anthropic Messages 流式事件转换器
+pub struct MessagesStreamTranslator {
+    id: Option<String>,
+    model: String,
+    /// 下一个内容块的序号
+    next_index: u32,
+    open: Option<OpenBlock>,
+    tool_uses: u32,
+    finish_reason: Option<String>,
+    usage: Option<UsageMetadata>,
+}
+
+impl MessagesStreamTranslator {
+    pub fn new(model: &str) -> Self {
+        Self {
+            id: None,
+            model: model.to_owned(),
+            next_index: 0,
+            open: None,
+            tool_uses: 0,
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    fn emit(out: &mut String, event: StreamEvent) {
+        format_event(
+            out,
+            event.name(),
+            &serde_json::to_string(&event).unwrap_or_default(),
+        );
+    }
+
+    /// 首个响应块到达时发送 message_start
+    fn start(&mut self, response_id: Option<&str>, out: &mut String) {
+        if self.id.is_some() {
+            return;
+        }
+        let id = message_id(response_id);
+        self.id = Some(id.clone());
+        let message = MessagesResponse {
+            id,
+            kind: "message".to_owned(),
+            role: "assistant".to_owned(),
+            content: Vec::new(),
+            model: self.model.clone(),
+            stop_reason: None,
+            stop_sequence: None,
+            usage: self
+                .usage
+                .as_ref()
+                .map(usage_from_gemini)
+                .unwrap_or_default(),
+        };
+        Self::emit(out, StreamEvent::MessageStart { message });
+    }
+
+    /// 关闭当前打开的内容块
+    fn close(&mut self, out: &mut String) {
+        if self.open.take().is_some() {
+            Self::emit(
+                out,
+                StreamEvent::ContentBlockStop {
+                    index: self.next_index - 1,
+                },
+            );
+        }
+    }
+
+    /// 确保打开指定类型的内容块,返回其序号
+    fn ensure_open(&mut self, kind: OpenBlock, out: &mut String) -> u32 {
+        if self.open == Some(kind) {
+            return self.next_index - 1;
+        }
+        self.close(out);
+        let index = self.next_index;
+        self.next_index += 1;
+        self.open = Some(kind);
+        let content_block = match kind {
+            OpenBlock::Text => ContentBlock::Text {
+                text: String::new(),
+            },
+            OpenBlock::Thinking => ContentBlock::Thinking {
+                thinking: String::new(),
+                signature: None,
+            },
+        };
+        Self::emit(
+            out,
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block,
+            },
+        );
+        index
+    }
+
+    /// 转换一个 Gemini 响应块
+    pub fn convert(&mut self, response: GenerateContentResponse, out: &mut String) {
+        if response.usage_metadata.is_some() {
+            self.usage = response.usage_metadata.clone();
+        }
+        self.start(response.response_id.as_deref(), out);
+        let Some(candidate) = response.candidates.into_iter().next() else {
+            return;
+        };
+        if candidate.finish_reason.is_some() {
+            self.finish_reason = candidate.finish_reason.clone();
+        }
+
+        for part in candidate.content.into_iter().flat_map(|c| c.parts) {
+            if let Some(call) = part.function_call {
+                self.close(out);
+                self.tool_uses += 1;
+                let index = self.next_index;
+                self.next_index += 1;
+                let id = tool_use_id(self.id.as_deref().unwrap_or_default(), self.tool_uses);
+                Self::emit(
+                    out,
+                    StreamEvent::ContentBlockStart {
+                        index,
+                        content_block: ContentBlock::ToolUse {
+                            id,
+                            name: call.name,
+                            input: json!({}),
+                        },
+                    },
+                );
+                Self::emit(
+                    out,
+                    StreamEvent::ContentBlockDelta {
+                        index,
+                        delta: BlockDelta::InputJsonDelta {
+                            partial_json: call.args.to_string(),
+                        },
+                    },
+                );
+                Self::emit(out, StreamEvent::ContentBlockStop { index });
+                continue;
+            }
+
+            let is_thought = part.is_thought();
+            let Some(text) = part.text else {
+                continue;
+            };
+            if is_thought {
+                let index = self.ensure_open(OpenBlock::Thinking, out);
+                Self::emit(
+                    out,
+                    StreamEvent::ContentBlockDelta {
+                        index,
+                        delta: BlockDelta::ThinkingDelta { thinking: text },
+                    },
+                );
+                if let Some(signature) = part.thought_signature {
+                    Self::emit(
+                        out,
+                        StreamEvent::ContentBlockDelta {
+                            index,
+                            delta: BlockDelta::SignatureDelta { signature },
+                        },
+                    );
+                }
+            } else if !text.is_empty() {
+                let index = self.ensure_open(OpenBlock::Text, out);
+                Self::emit(
+                    out,
+                    StreamEvent::ContentBlockDelta {
+                        index,
+                        delta: BlockDelta::TextDelta { text },
+                    },
+                );
+            }
+        }
+    }
+}
+
+impl EventTranslator for MessagesStreamTranslator {
+    fn on_event(&mut self, event: SseEvent, out: &mut String) {
+        match serde_json::from_str::<GenerateContentResponse>(&event.data) {
+            Ok(response) => self.convert(response, out),
+            Err(e) => {
+                tracing::warn!("Unexpected Gemini stream event ({}): {}", e, event.data);
+                self.on_error(&event.data, out);
+            }
+        }
+    }
+
+    fn on_end(&mut self, out: &mut String) {
+        self.start(None, out);
+        self.close(out);
+        let usage = self
+            .usage
+            .as_ref()
+            .map(usage_from_gemini)
+            .unwrap_or_default();
+        let stop_reason = map_finish_reason(self.finish_reason.as_deref(), self.tool_uses > 0);
+        Self::emit(
+            out,
+            StreamEvent::MessageDelta {
+                delta: MessageDelta {
+                    stop_reason: Some(stop_reason.to_owned()),
+                    stop_sequence: None,
+                },
+                usage: Some(usage),
+            },
+        );
+        Self::emit(out, StreamEvent::MessageStop);
+    }
+
+    fn on_error(&mut self, message: &str, out: &mut String) {
+        Self::emit(
+            out,
+            StreamEvent::Error {
+                error: json!({ "type": "api_error", "message": message }),
+            },
+        );
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        self.usage.as_ref().map(usage_from_metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_generate_content_maps_blocks() {
+        let request: MessagesRequest = serde_json::from_value(json!({
+            "model": "gemini-2.5-pro",
+            "max_tokens": 1024,
+            "system": [{"type": "text", "text": "be brief"}],
+            "messages": [
+                {"role": "user", "content": "weather in Paris?"},
+                {"role": "assistant", "content": [
+                    {"type": "text", "text": "checking"},
+                    {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "sunny"}
+                ]}
+            ],
+            "tools": [{"name": "get_weather", "input_schema": {"type": "object"}}],
+            "tool_choice": {"type": "tool", "name": "get_weather"},
+            "thinking": {"type": "enabled", "budget_tokens": 2048}
+        }))
+        .unwrap();
+        let body =
+            serde_json::to_value(to_generate_content(&request, "gemini-2.5-pro").unwrap()).unwrap();
+        assert_eq!(body["systemInstruction"]["parts"][0]["text"], "be brief");
+        assert_eq!(body["contents"][1]["role"], "model");
+        assert_eq!(
+            body["contents"][1]["parts"][1]["functionCall"]["args"]["city"],
+            "Paris"
+        );
+        assert_eq!(
+            body["contents"][2]["parts"][0]["functionResponse"]["name"],
+            "get_weather"
+        );
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 1024);
+        assert_eq!(
+            body["generationConfig"]["thinkingConfig"]["thinkingBudget"],
+            2048
+        );
+        assert_eq!(
+            body["toolConfig"]["functionCallingConfig"]["allowedFunctionNames"],
+            json!(["get_weather"])
+        );
+    }
+
+    #[test]
+    fn test_stream_translator_emits_anthropic_events() {
+        let mut translator = MessagesStreamTranslator::new("gemini-2.5-flash");
+        let mut out = String::new();
+        let chunk = |value: Value| serde_json::from_value(value).unwrap();
+        translator.convert(
+            chunk(json!({"responseId": "r1", "candidates": [{"content": {"role": "model", "parts": [{"text": "Hi"}]}}]})),
+            &mut out,
+        );
+        translator.convert(
+            chunk(json!({"candidates": [{"content": {"role": "model", "parts": [{"functionCall": {"name": "f", "args": {"a": 1}}}]}, "finishReason": "STOP"}],
+                         "usageMetadata": {"promptTokenCount": 5, "candidatesTokenCount": 3, "totalTokenCount": 8}})),
+            &mut out,
+        );
+        translator.on_end(&mut out);
+
+        let events: Vec<&str> = out
+            .lines()
+            .filter_map(|l| l.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        assert!(out.contains(r#""stop_reason":"tool_use""#));
+        assert!(out.contains(r#""id":"toolu_r1_1""#));
+        assert!(out.contains(r#""output_tokens":3"#));
+    }
+}
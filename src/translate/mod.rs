@@ -0,0 +1,62 @@
+//! 协议转换层
+//!
+//! 负责在 OpenAI 格式与各后端原生格式之间相互转换
+
+pub mod anthropic;
+pub mod completions;
+pub mod gemini;
+pub mod messages;
+pub mod openapi;
+pub mod responses;
+
+use crate::error::GatewayError;
+
+/// 去掉 `/v1/models` 返回的发布商前缀,如 "google/gemini-2.5-pro" -> "gemini-2.5-pro"
+pub fn strip_publisher(model: &str) -> &str {
+    model.split_once('/').map(|(_, m)| m).unwrap_or(model)
+}
+
+/// 去掉发布商前缀并校验模型 ID,用于拼接 Vertex AI 的 URL 和资源名
+///
+/// 只允许字母、数字和 `._@-`,拒绝 `/`、`?`、`%` 等可能改写请求路径的字符
+pub fn model_id(model: &str) -> Result<&str, GatewayError> {
+    let id = strip_publisher(model);
+    let valid = !id.is_empty()
+        && !id.bytes().all(|b| b == b'.')
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"._@-".contains(&b));
+    if !valid {
+        return Err(
+            GatewayError::bad_request(format!("Invalid model ID: '{model}'")).with_param("model"),
+        );
+    }
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_id_rejects_path_characters() {
+        assert_eq!(model_id("google/gemini-2.5-pro").unwrap(), "gemini-2.5-pro");
+        assert_eq!(
+            model_id("claude-sonnet-4@20250514").unwrap(),
+            "claude-sonnet-4@20250514"
+        );
+        for model in [
+            "",
+            "..",
+            "google/",
+            "a/../b",
+            "gemini?alt=json",
+            "gemini%2F..",
+            "gemini:predict",
+            "gemini flash",
+        ] {
+            let error = model_id(model).unwrap_err();
+            assert_eq!(error.status, axum::http::StatusCode::BAD_REQUEST, "{model}");
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! openapi 端点流式响应规范化
+//!
+//! Vertex AI 的 OpenAI 兼容端点返回的流式块与 OpenAI 并不完全一致:
+//! 各块的 `id`/`created` 可能不同、`choices` 中缺少 `index` 或 `finish_reason`、
+//! 错误以 Google 格式(有时包一层数组)出现在流中且之后没有 `[DONE]`。
+//! 这里逐个事件改写为 OpenAI 的 `chat.completion.chunk`,并保证流以 `[DONE]` 结束
+
+use crate::error::GatewayError;
+use crate::sse::{format_data, EventTranslator, SseEvent};
+use crate::translate::gemini::map_finish_reason;
+use axum::http::StatusCode;
+use serde_json::{Map, Value};
+
+/// openapi 流式响应规范化器
+pub struct OpenapiStreamNormalizer {
+    model: String,
+    id: Option<String>,
+    created: i64,
+    /// 已经输出 `[DONE]`,之后的事件全部忽略
+    done: bool,
+}
+
+impl OpenapiStreamNormalizer {
+    /// `model` 为客户端请求的模型名,所有块统一使用
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_owned(),
+            id: None,
+            created: chrono::Utc::now().timestamp(),
+            done: false,
+        }
+    }
+
+    /// 整个流统一使用第一个块的 id,上游没有时生成一个
+    fn id(&mut self, chunk: &Map<String, Value>) -> String {
+        self.id
+            .get_or_insert_with(|| match chunk.get("id").and_then(Value::as_str) {
+                Some(id) if !id.is_empty() => id.to_owned(),
+                _ => format!(
+                    "chatcmpl-{:x}",
+                    chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+                ),
+            })
+            .clone()
+    }
+
+    fn normalize(&mut self, mut chunk: Map<String, Value>) -> Value {
+        let id = self.id(&chunk);
+        chunk.insert("id".to_owned(), Value::String(id));
+        chunk.insert("object".to_owned(), Value::from("chat.completion.chunk"));
+        chunk.insert("created".to_owned(), Value::from(self.created));
+        chunk.insert("model".to_owned(), Value::String(self.model.clone()));
+
+        let choices = match chunk.remove("choices") {
+            Some(Value::Array(choices)) => choices,
+            _ => Vec::new(),
+        };
+        let choices = choices
+            .into_iter()
+            .enumerate()
+            .filter_map(|(position, choice)| match choice {
+                Value::Object(choice) => Some(Value::Object(normalize_choice(choice, position))),
+                _ => None,
+            })
+            .collect();
+        chunk.insert("choices".to_owned(), Value::Array(choices));
+        if chunk.get("usage").is_some_and(Value::is_null) {
+            chunk.remove("usage");
+        }
+        Value::Object(chunk)
+    }
+
+    fn finish(&mut self, out: &mut String) {
+        if !self.done {
+            self.done = true;
+            format_data(out, "[DONE]");
+        }
+    }
+
+    fn fail(&mut self, error: GatewayError, out: &mut String) {
+        if self.done {
+            return;
+        }
+        tracing::error!("Vertex AI stream returned an error: {}", error);
+        format_data(out, &error.body().to_string());
+        self.finish(out);
+    }
+}
+
+/// 补全单个选项的 `index`、`delta` 和 `finish_reason`
+fn normalize_choice(mut choice: Map<String, Value>, position: usize) -> Map<String, Value> {
+    if !choice.get("index").is_some_and(Value::is_u64) {
+        choice.insert("index".to_owned(), Value::from(position));
+    }
+    if !choice.get("delta").is_some_and(Value::is_object) {
+        // 个别块使用非流式的 message 字段
+        let delta = match choice.remove("message") {
+            Some(Value::Object(message)) => Value::Object(message),
+            _ => Value::Object(Map::new()),
+        };
+        choice.insert("delta".to_owned(), delta);
+    }
+    // 个别块使用 Gemini 的大写结束原因
+    let finish_reason = match choice.remove("finish_reason") {
+        Some(Value::String(reason)) if reason.chars().any(|c| c.is_ascii_uppercase()) => {
+            let has_tool_calls = choice["delta"].get("tool_calls").is_some();
+            Value::from(map_finish_reason(&reason, has_tool_calls))
+        }
+        Some(Value::String(reason)) if !reason.is_empty() => Value::String(reason),
+        _ => Value::Null,
+    };
+    choice.insert("finish_reason".to_owned(), finish_reason);
+    choice
+}
+
+/// 流中的错误对象(可能包一层数组)转换为 OpenAI 格式
+fn stream_error(value: &Value) -> Option<GatewayError> {
+    let error = match value {
+        Value::Array(items) => items.first()?.get("error")?,
+        _ => value.get("error")?,
+    };
+    let status = error
+        .get("code")
+        .and_then(Value::as_u64)
+        .and_then(|code| StatusCode::from_u16(code as u16).ok())
+        .filter(|status| status.is_client_error() || status.is_server_error())
+        .unwrap_or(StatusCode::BAD_GATEWAY);
+    Some(GatewayError::from_vertex(
+        status,
+        value.to_string().as_bytes(),
+    ))
+}
+
+impl EventTranslator for OpenapiStreamNormalizer {
+    fn on_event(&mut self, event: SseEvent, out: &mut String) {
+        if self.done {
+            return;
+        }
+        let data = event.data.trim();
+        if data == "[DONE]" {
+            self.finish(out);
+            return;
+        }
+        let value: Value = match serde_json::from_str(data) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Skipping malformed stream chunk from Vertex AI: {}", e);
+                return;
+            }
+        };
+        if let Some(error) = stream_error(&value) {
+            self.fail(error, out);
+            return;
+        }
+        let chunks = match value {
+            Value::Array(items) => items,
+            value => vec![value],
+        };
+        for chunk in chunks {
+            if let Value::Object(chunk) = chunk {
+                let chunk = self.normalize(chunk);
+                format_data(out, &chunk.to_string());
+            }
+        }
+    }
+
+    fn on_end(&mut self, out: &mut String) {
+        self.finish(out);
+    }
+
+    fn on_error(&mut self, message: &str, out: &mut String) {
+        self.fail(GatewayError::bad_gateway(message), out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sse::SseDecoder;
+
+    fn run(input: &str) -> Vec<String> {
+        let mut normalizer = OpenapiStreamNormalizer::new("google/gemini-2.5-pro");
+        let mut out = String::new();
+        let mut decoder = SseDecoder::new();
+        for event in decoder.push(input.as_bytes()) {
+            normalizer.on_event(event, &mut out);
+        }
+        normalizer.on_end(&mut out);
+        SseDecoder::new()
+            .push(out.as_bytes())
+            .into_iter()
+            .map(|e| e.data)
+            .collect()
+    }
+
+    #[test]
+    fn test_normalizes_ids_and_choices() {
+        let events = run(concat!(
+            "data: {\"id\":\"a\",\"created\":1,\"model\":\"gemini\",\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Hi\"}}]}\n\n",
+            "data: {\"id\":\"b\",\"created\":2,\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"MAX_TOKENS\"}],\"usage\":null}\n\n",
+        ));
+
+        assert_eq!(events.len(), 3);
+        let first: Value = serde_json::from_str(&events[0]).unwrap();
+        let second: Value = serde_json::from_str(&events[1]).unwrap();
+        assert_eq!(first["id"], "a");
+        assert_eq!(second["id"], "a");
+        assert_eq!(first["created"], second["created"]);
+        assert_eq!(first["object"], "chat.completion.chunk");
+        assert_eq!(first["model"], "google/gemini-2.5-pro");
+        assert_eq!(first["choices"][0]["index"], 0);
+        assert!(first["choices"][0]["finish_reason"].is_null());
+        assert_eq!(second["choices"][0]["finish_reason"], "length");
+        assert!(second.get("usage").is_none());
+        assert_eq!(events[2], "[DONE]");
+    }
+
+    #[test]
+    fn test_error_event_terminates_stream() {
+        let events = run(concat!(
+            "data: [{\"error\":{\"code\":429,\"message\":\"Quota exceeded\",\"status\":\"RESOURCE_EXHAUSTED\"}}]\n\n",
+            "data: {\"id\":\"a\",\"choices\":[]}\n\n",
+        ));
+
+        assert_eq!(events.len(), 2);
+        let error: Value = serde_json::from_str(&events[0]).unwrap();
+        assert_eq!(error["error"]["message"], "Quota exceeded");
+        assert_eq!(error["error"]["type"], "rate_limit_error");
+        assert_eq!(events[1], "[DONE]");
+    }
+}
@@ -0,0 +1,869 @@
+//! OpenAI Responses 格式与聊天完成格式互转
+//!
+//! 用于 `/v1/responses` 接口: 请求翻译为 `chat.completion` 请求后走现有的路由和后端,
+//! 响应和 `chat.completion.chunk` 流再翻译为 Responses 对象和类型化事件
+
+use crate::models::responses::{
+    InputContent, InputItem, InputPart, InputTokensDetails, OutputContent, OutputItem,
+    OutputTokensDetails, ResponseInput, ResponseObject, ResponseToolChoice, ResponseUsage,
+    ResponsesRequest, TextFormat,
+};
+use crate::models::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, ContentPart, FunctionCall,
+    FunctionDefinition, FunctionName, ImageUrl, JsonSchemaFormat, Message, MessageContent,
+    NamedToolChoice, ResponseFormat, StreamOptions, Tool, ToolCall, ToolChoice, Usage,
+};
+use crate::sse::{format_event, EventTranslator, SseEvent};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// 生成随机的响应 ID,同时作为存储的键,需要不可猜测
+pub fn response_id() -> String {
+    format!("resp_{:032x}", fastrand::u128(..))
+}
+
+/// 生成输出项 ID,如 `msg_<响应ID>_0`
+fn item_id(prefix: &str, response_id: &str, index: usize) -> String {
+    format!(
+        "{prefix}_{}_{index}",
+        response_id.trim_start_matches("resp_")
+    )
+}
+
+/// 创建处理中的响应对象,输出和用量在完成后填充
+pub fn in_progress(request: &ResponsesRequest, id: String) -> ResponseObject {
+    ResponseObject {
+        id,
+        object: "response",
+        created_at: chrono::Utc::now().timestamp(),
+        status: "in_progress",
+        model: request.model.clone(),
+        output: Vec::new(),
+        usage: None,
+        previous_response_id: request.previous_response_id.clone(),
+        instructions: request.instructions.clone(),
+        incomplete_details: None,
+        error: None,
+        metadata: request.metadata.clone().unwrap_or_else(|| json!({})),
+    }
+}
+
+/// 将 Responses 请求转换为聊天完成请求
+///
+/// `history` 为 `previous_response_id` 对应的历史对话。返回聊天请求和
+/// 追加本次输入后的对话(不含 instructions,instructions 不会延续到后续响应)
+pub fn to_chat_request(
+    request: &ResponsesRequest,
+    history: Vec<Message>,
+) -> Result<(ChatCompletionRequest, Vec<Message>), String> {
+    let mut conversation = history;
+    match &request.input {
+        Some(ResponseInput::Text(text)) => conversation.push(Message {
+            role: "user".to_owned(),
+            content: Some(MessageContent::Text(text.clone())),
+            ..Default::default()
+        }),
+        Some(ResponseInput::Items(items)) => {
+            for item in items {
+                push_input_item(&mut conversation, item)?;
+            }
+        }
+        None => {}
+    }
+    if conversation.is_empty() {
+        return Err("'input' is required".to_owned());
+    }
+
+    let mut messages = Vec::with_capacity(conversation.len() + 1);
+    if let Some(instructions) = request.instructions.as_ref().filter(|i| !i.is_empty()) {
+        messages.push(Message {
+            role: "system".to_owned(),
+            content: Some(MessageContent::Text(instructions.clone())),
+            ..Default::default()
+        });
+    }
+    messages.extend(conversation.iter().cloned());
+
+    let tools = match &request.tools {
+        Some(tools) if !tools.is_empty() => Some(
+            tools
+                .iter()
+                .map(|t| {
+                    if t.kind != "function" {
+                        return Err(format!(
+                            "Unsupported tool type: {}; only function tools are supported",
+                            t.kind
+                        ));
+                    }
+                    Ok(Tool {
+                        kind: "function".to_owned(),
+                        function: FunctionDefinition {
+                            name: t.name.clone().ok_or("Function tools require a name")?,
+                            description: t.description.clone(),
+                            parameters: t.parameters.clone(),
+                        },
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+        ),
+        _ => None,
+    };
+    let tool_choice = request.tool_choice.as_ref().map(|choice| match choice {
+        ResponseToolChoice::Mode(mode) => ToolChoice::Mode(mode.clone()),
+        ResponseToolChoice::Function { name } => ToolChoice::Named(NamedToolChoice {
+            kind: "function".to_owned(),
+            function: FunctionName { name: name.clone() },
+        }),
+    });
+    let response_format = request
+        .text
+        .as_ref()
+        .and_then(|t| t.format.as_ref())
+        .map(|format| match format {
+            TextFormat::Text => ResponseFormat::Text,
+            TextFormat::JsonObject => ResponseFormat::JsonObject,
+            TextFormat::JsonSchema {
+                name,
+                schema,
+                strict,
+            } => ResponseFormat::JsonSchema {
+                json_schema: JsonSchemaFormat {
+                    name: name.clone(),
+                    schema: schema.clone(),
+                    strict: *strict,
+                },
+            },
+        });
+
+    let stream = request.is_stream();
+    let chat = ChatCompletionRequest {
+        model: request.model.clone(),
+        messages,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        max_completion_tokens: request.max_output_tokens,
+        response_format,
+        reasoning_effort: request.reasoning.as_ref().and_then(|r| r.effort.clone()),
+        tools,
+        tool_choice,
+        stream: Some(stream),
+        // 流式时需要 usage 块来填充 response.completed 中的用量
+        stream_options: stream.then_some(StreamOptions {
+            include_usage: true,
+        }),
+        user: request.user.clone(),
+        ..Default::default()
+    };
+    Ok((chat, conversation))
+}
+
+/// 将一个输入项追加到对话中
+///
+/// - 消息项按角色转换,developer 视为 system
+/// - 连续的 function_call 合并到同一条助手消息的 tool_calls
+/// - function_call_output 转换为 tool 消息
+/// - reasoning 项由模型自行管理,直接忽略
+fn push_input_item(messages: &mut Vec<Message>, item: &InputItem) -> Result<(), String> {
+    match item.kind.as_deref().unwrap_or("message") {
+        "message" => {
+            let role = match item.role.as_deref() {
+                Some("developer") => "system",
+                Some(role @ ("user" | "system" | "assistant")) => role,
+                Some(other) => return Err(format!("Unsupported message role: {other}")),
+                None => return Err("Message input items require a role".to_owned()),
+            };
+            let content = match &item.content {
+                Some(InputContent::Text(text)) => MessageContent::Text(text.clone()),
+                Some(InputContent::Parts(parts)) if role == "assistant" => {
+                    MessageContent::Text(parts.iter().filter_map(part_text).collect())
+                }
+                Some(InputContent::Parts(parts)) => MessageContent::Parts(
+                    parts
+                        .iter()
+                        .map(input_part_to_chat)
+                        .collect::<Result<_, _>>()?,
+                ),
+                None => return Err("Message input items require content".to_owned()),
+            };
+            messages.push(Message {
+                role: role.to_owned(),
+                content: Some(content),
+                ..Default::default()
+            });
+        }
+        "function_call" => {
+            let call = ToolCall {
+                index: None,
+                id: item
+                    .call_id
+                    .clone()
+                    .ok_or("function_call items require a call_id")?,
+                kind: "function".to_owned(),
+                function: FunctionCall {
+                    name: item
+                        .name
+                        .clone()
+                        .ok_or("function_call items require a name")?,
+                    arguments: item.arguments.clone().unwrap_or_default(),
+                },
+            };
+            match messages.last_mut() {
+                Some(last) if last.role == "assistant" => {
+                    last.tool_calls.get_or_insert_with(Vec::new).push(call)
+                }
+                _ => messages.push(Message {
+                    role: "assistant".to_owned(),
+                    tool_calls: Some(vec![call]),
+                    ..Default::default()
+                }),
+            }
+        }
+        "function_call_output" => {
+            let output = match &item.output {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Array(parts)) => parts
+                    .iter()
+                    .filter_map(|p| p.get("text").and_then(Value::as_str))
+                    .collect(),
+                Some(other) => other.to_string(),
+                None => String::new(),
+            };
+            messages.push(Message {
+                role: "tool".to_owned(),
+                content: Some(MessageContent::Text(output)),
+                tool_call_id: Some(
+                    item.call_id
+                        .clone()
+                        .ok_or("function_call_output items require a call_id")?,
+                ),
+                ..Default::default()
+            });
+        }
+        "reasoning" => {}
+        other => return Err(format!("Unsupported input item type: {other}")),
+    }
+    Ok(())
+}
+
+/// 内容片段中的文本
+fn part_text(part: &InputPart) -> Option<&str> {
+    match part {
+        InputPart::InputText { text } | InputPart::OutputText { text } => Some(text),
+        InputPart::Refusal { refusal } => Some(refusal),
+        _ => None,
+    }
+}
+
+/// 将输入内容片段转换为聊天内容片段
+fn input_part_to_chat(part: &InputPart) -> Result<ContentPart, String> {
+    match part {
+        InputPart::InputImage {
+            image_url: Some(url),
+            detail,
+        } => Ok(ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: url.clone(),
+                detail: detail.clone(),
+            },
+        }),
+        InputPart::InputImage {
+            image_url: None, ..
+        } => Err("input_image requires an image_url; file_id is not supported".to_owned()),
+        InputPart::Unknown => Err("Unsupported input content type".to_owned()),
+        part => Ok(ContentPart::Text {
+            text: part_text(part).unwrap_or_default().to_owned(),
+        }),
+    }
+}
+
+/// 将聊天用量转换为 Responses 用量
+fn usage_from_chat(usage: &Usage) -> ResponseUsage {
+    ResponseUsage {
+        input_tokens: usage.prompt_tokens,
+        input_tokens_details: InputTokensDetails {
+            cached_tokens: usage
+                .prompt_tokens_details
+                .as_ref()
+                .map_or(0, |d| d.cached_tokens),
+        },
+        output_tokens: usage.completion_tokens,
+        output_tokens_details: OutputTokensDetails {
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .as_ref()
+                .map_or(0, |d| d.reasoning_tokens),
+        },
+        total_tokens: usage.total_tokens,
+    }
+}
+
+/// 根据结束原因设置响应状态,长度截断和内容过滤为 incomplete
+fn finish(response: &mut ResponseObject, finish_reason: Option<&str>) {
+    let reason = match finish_reason {
+        Some("length") => Some("max_output_tokens"),
+        Some("content_filter") => Some("content_filter"),
+        _ => None,
+    };
+    response.status = if reason.is_some() {
+        "incomplete"
+    } else {
+        "completed"
+    };
+    response.incomplete_details = reason.map(|r| json!({ "reason": r }));
+}
+
+/// 将聊天完成响应转换为 Responses 对象(只取第一个选项)
+pub fn to_response(chat: ChatCompletionResponse, mut response: ResponseObject) -> ResponseObject {
+    let id = response.id.clone();
+    let mut output = Vec::new();
+    let choice = chat.choices.into_iter().next();
+    if let Some(choice) = &choice {
+        let message = &choice.message;
+        if let Some(reasoning) = message.reasoning_content.as_ref().filter(|r| !r.is_empty()) {
+            output.push(OutputItem::Reasoning {
+                id: item_id("rs", &id, output.len()),
+                summary: vec![OutputContent::SummaryText {
+                    text: reasoning.clone(),
+                }],
+            });
+        }
+        let text = message
+            .content
+            .as_ref()
+            .map(MessageContent::text)
+            .unwrap_or_default();
+        if !text.is_empty() {
+            output.push(OutputItem::Message {
+                id: item_id("msg", &id, output.len()),
+                status: "completed",
+                role: "assistant",
+                content: vec![OutputContent::OutputText {
+                    text,
+                    annotations: Vec::new(),
+                }],
+            });
+        }
+        for call in message.tool_calls.iter().flatten() {
+            output.push(OutputItem::FunctionCall {
+                id: item_id("fc", &id, output.len()),
+                call_id: call.id.clone(),
+                name: call.function.name.clone(),
+                arguments: call.function.arguments.clone(),
+                status: "completed",
+            });
+        }
+    }
+
+    response.output = output;
+    response.usage = chat.usage.as_ref().map(usage_from_chat);
+    finish(
+        &mut response,
+        choice.as_ref().and_then(|c| c.finish_reason.as_deref()),
+    );
+    response
+}
+
+/// 将输出项还原为助手消息,用于保存对话历史
+pub fn output_to_message(output: &[OutputItem]) -> Option<Message> {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for item in output {
+        match item {
+            OutputItem::Message { content, .. } => {
+                for part in content {
+                    if let OutputContent::OutputText { text: t, .. } = part {
+                        text.push_str(t);
+                    }
+                }
+            }
+            OutputItem::FunctionCall {
+                call_id,
+                name,
+                arguments,
+                ..
+            } => tool_calls.push(ToolCall {
+                index: None,
+                id: call_id.clone(),
+                kind: "function".to_owned(),
+                function: FunctionCall {
+                    name: name.clone(),
+                    arguments: arguments.clone(),
+                },
+            }),
+            OutputItem::Reasoning { .. } => {}
+        }
+    }
+    if text.is_empty() && tool_calls.is_empty() {
+        return None;
+    }
+    Some(Message {
+        role: "assistant".to_owned(),
+        content: (!text.is_empty()).then_some(MessageContent::Text(text)),
+        tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+        ..Default::default()
+    })
+}
+
+/// 响应完成后的回调,用于保存响应
+type CompleteHook = Box<dyn FnOnce(&ResponseObject) + Send>;
+
+/// OpenAI chat.completion.chunk SSE -> Responses 类型化事件转换器
+///
+/// 输出 `response.created`、`response.output_item.added`、`response.output_text.delta`、
+/// `response.function_call_arguments.delta`、`response.completed` 等事件
+pub struct ResponsesStreamTranslator {
+    response: ResponseObject,
+    sequence: u64,
+    started: bool,
+    /// 当前未结束的输出项下标
+    open: Option<usize>,
+    /// 流式工具调用下标 -> 输出项下标
+    tool_items: HashMap<u32, usize>,
+    finish_reason: Option<String>,
+    error: Option<String>,
+    on_complete: Option<CompleteHook>,
+}
+
+impl ResponsesStreamTranslator {
+    pub fn new(response: ResponseObject) -> Self {
+        Self {
+            response,
+            sequence: 0,
+            started: false,
+            open: None,
+            tool_items: HashMap::new(),
+            finish_reason: None,
+            error: None,
+            on_complete: None,
+        }
+    }
+
+    /// 设置响应成功完成后的回调
+    pub fn on_complete(mut self, hook: impl FnOnce(&ResponseObject) + Send + 'static) -> Self {
+        self.on_complete = Some(Box::new(hook));
+        self
+    }
+
+    /// 输出一个事件,自动填充 `type` 和 `sequence_number`
+    fn emit(&mut self, out: &mut String, kind: &str, mut payload: Value) {
+        payload["type"] = Value::from(kind);
+        payload["sequence_number"] = Value::from(self.sequence);
+        self.sequence += 1;
+        format_event(out, kind, &payload.to_string());
+    }
+
+    /// 第一个事件前输出 response.created 和 response.in_progress
+    fn start(&mut self, out: &mut String) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let response = serde_json::to_value(&self.response).unwrap_or_default();
+        self.emit(out, "response.created", json!({ "response": response }));
+        self.emit(out, "response.in_progress", json!({ "response": response }));
+    }
+
+    /// 结束当前输出项并开始新的输出项,返回其下标
+    fn open_item(&mut self, out: &mut String, item: OutputItem) -> usize {
+        self.close_item(out);
+        let index = self.response.output.len();
+        self.emit(
+            out,
+            "response.output_item.added",
+            json!({ "output_index": index, "item": item }),
+        );
+        let item_id = output_item_id(&item).to_owned();
+        self.response.output.push(item);
+        match &mut self.response.output[index] {
+            OutputItem::Message { content, .. } => {
+                let part = OutputContent::OutputText {
+                    text: String::new(),
+                    annotations: Vec::new(),
+                };
+                let payload = json!({
+                    "item_id": item_id, "output_index": index, "content_index": 0, "part": part,
+                });
+                content.push(part);
+                self.emit(out, "response.content_part.added", payload);
+            }
+            OutputItem::Reasoning { summary, .. } => {
+                let part = OutputContent::SummaryText {
+                    text: String::new(),
+                };
+                let payload = json!({
+                    "item_id": item_id, "output_index": index, "summary_index": 0, "part": part,
+                });
+                summary.push(part);
+                self.emit(out, "response.reasoning_summary_part.added", payload);
+            }
+            OutputItem::FunctionCall { .. } => {}
+        }
+        self.open = Some(index);
+        index
+    }
+
+    /// 结束当前输出项,输出对应的 done 事件
+    fn close_item(&mut self, out: &mut String) {
+        let Some(index) = self.open.take() else {
+            return;
+        };
+        let item = &mut self.response.output[index];
+        let item_id = output_item_id(item).to_owned();
+        match item {
+            OutputItem::Message {
+                status, content, ..
+            } => {
+                *status = "completed";
+                let part = content[0].clone();
+                let text = match &part {
+                    OutputContent::OutputText { text, .. } => text.clone(),
+                    OutputContent::SummaryText { text } => text.clone(),
+                };
+                let base = json!({ "item_id": item_id, "output_index": index, "content_index": 0 });
+                self.emit(out, "response.output_text.done", with(&base, "text", text));
+                self.emit(out, "response.content_part.done", with(&base, "part", part));
+            }
+            OutputItem::Reasoning { summary, .. } => {
+                let part = summary[0].clone();
+                let text = match &part {
+                    OutputContent::OutputText { text, .. } => text.clone(),
+                    OutputContent::SummaryText { text } => text.clone(),
+                };
+                let base = json!({ "item_id": item_id, "output_index": index, "summary_index": 0 });
+                self.emit(
+                    out,
+                    "response.reasoning_summary_text.done",
+                    with(&base, "text", text),
+                );
+                self.emit(
+                    out,
+                    "response.reasoning_summary_part.done",
+                    with(&base, "part", part),
+                );
+            }
+            OutputItem::FunctionCall {
+                status, arguments, ..
+            } => {
+                *status = "completed";
+                let arguments = arguments.clone();
+                self.emit(
+                    out,
+                    "response.function_call_arguments.done",
+                    json!({ "item_id": item_id, "output_index": index, "arguments": arguments }),
+                );
+            }
+        }
+        let item = self.response.output[index].clone();
+        self.emit(
+            out,
+            "response.output_item.done",
+            json!({ "output_index": index, "item": item }),
+        );
+    }
+
+    /// 追加文本增量,当前输出项类型不同时先开始新的输出项
+    fn push_text(&mut self, out: &mut String, delta: &str, reasoning: bool) {
+        let current = self.open.map(|i| &self.response.output[i]);
+        let matches = match current {
+            Some(OutputItem::Message { .. }) => !reasoning,
+            Some(OutputItem::Reasoning { .. }) => reasoning,
+            _ => false,
+        };
+        let index = match self.open {
+            Some(index) if matches => index,
+            _ => {
+                let id = &self.response.id;
+                let index = self.response.output.len();
+                let item = if reasoning {
+                    OutputItem::Reasoning {
+                        id: item_id("rs", id, index),
+                        summary: Vec::new(),
+                    }
+                } else {
+                    OutputItem::Message {
+                        id: item_id("msg", id, index),
+                        status: "in_progress",
+                        role: "assistant",
+                        content: Vec::new(),
+                    }
+                };
+                self.open_item(out, item)
+            }
+        };
+
+        let item = &mut self.response.output[index];
+        let item_id = output_item_id(item).to_owned();
+        let (kind, payload) = match item {
+            OutputItem::Message { content, .. } => {
+                if let OutputContent::OutputText { text, .. } = &mut content[0] {
+                    text.push_str(delta);
+                }
+                (
+                    "response.output_text.delta",
+                    json!({ "item_id": item_id, "output_index": index, "content_index": 0, "delta": delta }),
+                )
+            }
+            OutputItem::Reasoning { summary, .. } => {
+                if let OutputContent::SummaryText { text } = &mut summary[0] {
+                    text.push_str(delta);
+                }
+                (
+                    "response.reasoning_summary_text.delta",
+                    json!({ "item_id": item_id, "output_index": index, "summary_index": 0, "delta": delta }),
+                )
+            }
+            OutputItem::FunctionCall { .. } => return,
+        };
+        self.emit(out, kind, payload);
+    }
+
+    /// 处理工具调用增量,新的工具调用开始新的输出项
+    fn push_tool_call(&mut self, out: &mut String, call: ToolCall) {
+        let key = call.index.unwrap_or(0);
+        let index = match self.tool_items.get(&key) {
+            Some(&index) if call.id.is_empty() => index,
+            _ => {
+                let id = &self.response.id;
+                let index = self.response.output.len();
+                let call_id = if call.id.is_empty() {
+                    item_id("call", id, index)
+                } else {
+                    call.id.clone()
+                };
+                let item = OutputItem::FunctionCall {
+                    id: item_id("fc", id, index),
+                    call_id,
+                    name: call.function.name.clone(),
+                    arguments: String::new(),
+                    status: "in_progress",
+                };
+                let index = self.open_item(out, item);
+                self.tool_items.insert(key, index);
+                index
+            }
+        };
+        // 已结束的工具调用不再接收增量
+        if call.function.arguments.is_empty() || self.open != Some(index) {
+            return;
+        }
+        if let OutputItem::FunctionCall { id, arguments, .. } = &mut self.response.output[index] {
+            arguments.push_str(&call.function.arguments);
+            let payload = json!({
+                "item_id": id.clone(), "output_index": index, "delta": call.function.arguments,
+            });
+            self.emit(out, "response.function_call_arguments.delta", payload);
+        }
+    }
+
+    /// 以失败状态结束响应
+    fn fail(&mut self, out: &mut String, message: &str) {
+        self.start(out);
+        self.close_item(out);
+        self.response.status = "failed";
+        self.response.error = Some(json!({ "code": "server_error", "message": message }));
+        let response = serde_json::to_value(&self.response).unwrap_or_default();
+        self.emit(out, "response.failed", json!({ "response": response }));
+    }
+}
+
+/// 输出项 ID
+fn output_item_id(item: &OutputItem) -> &str {
+    match item {
+        OutputItem::Message { id, .. }
+        | OutputItem::FunctionCall { id, .. }
+        | OutputItem::Reasoning { id, .. } => id,
+    }
+}
+
+/// 复制事件公共字段并添加一个字段
+fn with(base: &Value, key: &str, value: impl serde::Serialize) -> Value {
+    let mut payload = base.clone();
+    payload[key] = serde_json::to_value(value).unwrap_or_default();
+    payload
+}
+
+impl EventTranslator for ResponsesStreamTranslator {
+    fn on_event(&mut self, event: SseEvent, out: &mut String) {
+        if event.data.trim() == "[DONE]" || event.data.trim().is_empty() {
+            return;
+        }
+        let value: Value = match serde_json::from_str(&event.data) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Failed to parse chat completion chunk: {}", e);
+                return;
+            }
+        };
+        // 聊天流中的错误块
+        if let Some(error) = value.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("Upstream stream failed");
+            self.error = Some(message.to_owned());
+            return;
+        }
+        let chunk: ChatCompletionChunk = match serde_json::from_value(value) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                tracing::warn!("Failed to parse chat completion chunk: {}", e);
+                return;
+            }
+        };
+
+        self.start(out);
+        if let Some(usage) = &chunk.usage {
+            self.response.usage = Some(usage_from_chat(usage));
+        }
+        for choice in chunk.choices.into_iter().filter(|c| c.index == 0) {
+            let delta = choice.delta;
+            if let Some(reasoning) = delta.reasoning_content.filter(|r| !r.is_empty()) {
+                self.push_text(out, &reasoning, true);
+            }
+            if let Some(content) = delta.content.filter(|c| !c.is_empty()) {
+                self.push_text(out, &content, false);
+            }
+            for call in delta.tool_calls.into_iter().flatten() {
+                self.push_tool_call(out, call);
+            }
+            if choice.finish_reason.is_some() {
+                self.finish_reason = choice.finish_reason;
+            }
+        }
+    }
+
+    fn on_end(&mut self, out: &mut String) {
+        if let Some(message) = self.error.take() {
+            self.fail(out, &message);
+            return;
+        }
+        self.start(out);
+        self.close_item(out);
+        finish(&mut self.response, self.finish_reason.as_deref());
+        let kind = if self.response.status == "incomplete" {
+            "response.incomplete"
+        } else {
+            "response.completed"
+        };
+        let response = serde_json::to_value(&self.response).unwrap_or_default();
+        self.emit(out, kind, json!({ "response": response }));
+        if let Some(hook) = self.on_complete.take() {
+            hook(&self.response);
+        }
+    }
+
+    fn on_error(&mut self, message: &str, out: &mut String) {
+        self.fail(out, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sse::SseDecoder;
+
+    fn request(body: Value) -> ResponsesRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_to_chat_request_maps_items_and_history() {
+        let request = request(json!({
+            "model": "gemini-2.5-flash",
+            "instructions": "Be brief",
+            "previous_response_id": "resp_1",
+            "input": [
+                {"type": "function_call", "call_id": "call_1", "name": "get_weather",
+                 "arguments": "{\"city\":\"Paris\"}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "sunny"},
+                {"role": "user", "content": [{"type": "input_text", "text": "And tomorrow?"}]}
+            ],
+            "tools": [{"type": "function", "name": "get_weather", "parameters": {"type": "object"}}],
+            "max_output_tokens": 256,
+            "stream": true
+        }));
+        let history = vec![Message {
+            role: "user".to_owned(),
+            content: Some(MessageContent::Text("Weather in Paris?".to_owned())),
+            ..Default::default()
+        }];
+
+        let (chat, conversation) = to_chat_request(&request, history).unwrap();
+        let roles: Vec<_> = chat.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "assistant", "tool", "user"]);
+        assert_eq!(conversation.len(), 4);
+        assert_eq!(
+            chat.messages[2].tool_calls.as_ref().unwrap()[0].id,
+            "call_1"
+        );
+        assert_eq!(chat.messages[3].tool_call_id.as_deref(), Some("call_1"));
+        assert_eq!(chat.tools.as_ref().unwrap()[0].function.name, "get_weather");
+        assert_eq!(chat.max_completion_tokens, Some(256));
+        assert!(chat.include_usage());
+
+        let invalid = self::request(json!({
+            "model": "gemini-2.5-flash", "input": "hi", "tools": [{"type": "web_search"}]
+        }));
+        assert!(to_chat_request(&invalid, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_stream_translator_emits_typed_events() {
+        let request = request(json!({"model": "gemini-2.5-flash", "input": "hi"}));
+        let response = in_progress(&request, "resp_abc".to_owned());
+        let completed = std::sync::Arc::new(std::sync::Mutex::new(None));
+        let saved = completed.clone();
+        let mut translator = ResponsesStreamTranslator::new(response)
+            .on_complete(move |r| *saved.lock().unwrap() = Some(r.clone()));
+
+        let upstream = concat!(
+            "data: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"m\",",
+            "\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n",
+            "data: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"m\",",
+            "\"choices\":[{\"index\":0,\"delta\":{\"content\":\"lo\"},\"finish_reason\":null}]}\n\n",
+            "data: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"m\",",
+            "\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",",
+            "\"type\":\"function\",\"function\":{\"name\":\"f\",\"arguments\":\"{}\"}}]},",
+            "\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: {\"id\":\"c\",\"object\":\"chat.completion.chunk\",\"created\":0,\"model\":\"m\",",
+            "\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2,\"total_tokens\":5}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let mut out = String::new();
+        for event in SseDecoder::new().push(upstream.as_bytes()) {
+            translator.on_event(event, &mut out);
+        }
+        translator.on_end(&mut out);
+
+        let events: Vec<&str> = out
+            .lines()
+            .filter_map(|l| l.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "response.created",
+                "response.in_progress",
+                "response.output_item.added",
+                "response.content_part.added",
+                "response.output_text.delta",
+                "response.output_text.delta",
+                "response.output_text.done",
+                "response.content_part.done",
+                "response.output_item.done",
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                "response.function_call_arguments.done",
+                "response.output_item.done",
+                "response.completed",
+            ]
+        );
+
+        let response = completed.lock().unwrap().take().unwrap();
+        assert_eq!(response.status, "completed");
+        assert_eq!(response.usage.unwrap().total_tokens, 5);
+        let message = output_to_message(&response.output).unwrap();
+        assert_eq!(message.content.unwrap().text(), "Hello");
+        assert_eq!(message.tool_calls.unwrap()[0].id, "call_1");
+    }
+}
@@ -0,0 +1,497 @@
+//! 响应用量跟踪
+//!
+//! 从网关返回给客户端的响应体中提取 token 用量,兼容所有对外接口的格式;
+//! 同时汇总结束原因、上游响应 ID、字节数和首字节时间。请求上下文中间件对每个响应
+//! 只汇总一次,计费、限流、指标、追踪和访问日志都读取同一份 [`ResponseSummary`]
+
+use crate::context::UsageSlot;
+use crate::models::gemini::UsageMetadata;
+use crate::models::{CompletionTokensDetails, PromptTokensDetails, Usage};
+use crate::sse::{EventTranslator, SseDecoder, SseEvent};
+use crate::translate::gemini::usage_from_metadata;
+use axum::{body::Body, http::header::CONTENT_TYPE, response::Response};
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::time::Instant;
+
+/// 响应结束时的回调
+type OnDone = Box<dyn FnOnce(ResponseSummary) + Send>;
+
+/// 从响应中汇总的 token 用量,统一为 OpenAI usage 的语义
+///
+/// 兼容 OpenAI(`usage`)、Anthropic(`message.usage` 与 `message_delta` 中的 `usage`)、
+/// Responses(`response.usage`)和 Gemini(`usageMetadata`)格式,流式事件中各项取最大值
+#[derive(Debug, Default)]
+pub struct UsageTally {
+    usage: Usage,
+    seen: bool,
+}
+
+impl UsageTally {
+    /// 记录一个 JSON 响应或 SSE 事件中的用量,JSON 数组逐个元素处理
+    pub fn observe(&mut self, value: &Value) {
+        if let Value::Array(items) = value {
+            items.iter().for_each(|item| self.observe(item));
+            return;
+        }
+        for usage in [
+            value.get("usage"),
+            value.pointer("/message/usage"),
+            value.pointer("/response/usage"),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|u| u.is_object())
+        {
+            self.merge(&parse_usage(usage));
+        }
+        if let Some(metadata) = value.get("usageMetadata") {
+            if let Ok(metadata) = serde_json::from_value::<UsageMetadata>(metadata.clone()) {
+                self.merge(&usage_from_metadata(&metadata));
+            }
+        }
+    }
+
+    fn merge(&mut self, usage: &Usage) {
+        let total = &mut self.usage;
+        total.prompt_tokens = total.prompt_tokens.max(usage.prompt_tokens);
+        total.completion_tokens = total.completion_tokens.max(usage.completion_tokens);
+        total.total_tokens = total
+            .total_tokens
+            .max(usage.total_tokens)
+            .max(total.prompt_tokens + total.completion_tokens);
+        let cached = cached_tokens(usage).max(cached_tokens(total));
+        if cached > 0 {
+            total.prompt_tokens_details = Some(PromptTokensDetails {
+                cached_tokens: cached,
+            });
+        }
+        let reasoning = reasoning_tokens(usage).max(reasoning_tokens(total));
+        if reasoning > 0 {
+            total.completion_tokens_details = Some(CompletionTokensDetails {
+                reasoning_tokens: reasoning,
+            });
+        }
+        self.seen = true;
+    }
+
+    /// 汇总的用量,响应中没有 usage 时返回 `None`
+    pub fn finish(self) -> Option<Usage> {
+        self.seen.then_some(self.usage)
+    }
+}
+
+/// 响应摘要
+#[derive(Debug, Default)]
+pub struct ResponseSummary {
+    /// 汇总的用量,响应中没有 usage 时为 `None`
+    pub usage: Option<Usage>,
+    /// 去重后的结束原因,保持原始格式(如 `stop`、`end_turn`、`STOP`)
+    pub finish_reasons: Vec<String>,
+    /// 第一个出现的响应 ID,如 `chatcmpl-...`、`msg_...` 或 Gemini 的 `responseId`
+    pub response_id: Option<String>,
+    /// 发送给客户端的字节数
+    pub bytes: u64,
+    /// 第一个非空数据块的发送时间
+    pub first_byte: Option<Instant>,
+    /// 响应体是否读到结尾
+    pub completed: bool,
+    /// 响应体读取是否出错
+    pub failed: bool,
+}
+
+impl ResponseSummary {
+    /// 响应体既没有读到结尾也没有出错就被丢弃,说明客户端中途断开
+    pub fn cancelled(&self) -> bool {
+        !self.completed && !self.failed
+    }
+}
+
+/// 逐个 JSON 响应或 SSE 事件汇总 [`ResponseSummary`]
+#[derive(Debug, Default)]
+struct SummaryTally {
+    usage: UsageTally,
+    finish_reasons: Vec<String>,
+    response_id: Option<String>,
+}
+
+impl SummaryTally {
+    fn observe(&mut self, value: &Value) {
+        self.usage.observe(value);
+        if let Value::Array(items) = value {
+            items.iter().for_each(|item| self.observe_reasons(item));
+        } else {
+            self.observe_reasons(value);
+        }
+    }
+
+    fn observe_reasons(&mut self, value: &Value) {
+        if self.response_id.is_none() {
+            self.response_id = ["/id", "/message/id", "/response/id", "/responseId"]
+                .into_iter()
+                .find_map(|pointer| value.pointer(pointer)?.as_str())
+                .filter(|id| !id.is_empty())
+                .map(str::to_owned);
+        }
+        let choices = ["choices", "candidates"]
+            .into_iter()
+            .filter_map(|key| value.get(key)?.as_array())
+            .flatten()
+            .filter_map(|c| c.get("finish_reason").or_else(|| c.get("finishReason")));
+        let stop = [
+            value.get("stop_reason"),
+            value.pointer("/delta/stop_reason"),
+        ];
+        for reason in choices
+            .chain(stop.into_iter().flatten())
+            .filter_map(Value::as_str)
+        {
+            if !self.finish_reasons.iter().any(|r| r == reason) {
+                self.finish_reasons.push(reason.to_owned());
+            }
+        }
+    }
+
+    fn finish(self) -> ResponseSummary {
+        ResponseSummary {
+            usage: self.usage.finish(),
+            finish_reasons: self.finish_reasons,
+            response_id: self.response_id,
+            ..Default::default()
+        }
+    }
+}
+
+/// 累加多个请求的 token 用量,缓存和思考 token 数同样累加
+pub fn add_usage(total: &mut Usage, usage: &Usage) {
+    total.prompt_tokens += usage.prompt_tokens;
+    total.completion_tokens += usage.completion_tokens;
+    total.total_tokens += usage.total_tokens;
+    let cached = cached_tokens(total) + cached_tokens(usage);
+    if cached > 0 {
+        total.prompt_tokens_details = Some(PromptTokensDetails {
+            cached_tokens: cached,
+        });
+    }
+    let reasoning = reasoning_tokens(total) + reasoning_tokens(usage);
+    if reasoning > 0 {
+        total.completion_tokens_details = Some(CompletionTokensDetails {
+            reasoning_tokens: reasoning,
+        });
+    }
+}
+
+/// 缓存命中的输入 token 数
+pub fn cached_tokens(usage: &Usage) -> u64 {
+    usage
+        .prompt_tokens_details
+        .as_ref()
+        .map_or(0, |d| d.cached_tokens)
+}
+
+/// 思考 token 数
+pub fn reasoning_tokens(usage: &Usage) -> u64 {
+    usage
+        .completion_tokens_details
+        .as_ref()
+        .map_or(0, |d| d.reasoning_tokens)
+}
+
+/// 解析 `usage` 对象
+///
+/// OpenAI 使用 `prompt_tokens`/`completion_tokens`,Responses 和 Anthropic 使用
+/// `input_tokens`/`output_tokens`;Anthropic 的 `input_tokens` 不含缓存部分,需加回
+fn parse_usage(usage: &Value) -> Usage {
+    let n = |pointer: &str| usage.pointer(pointer).and_then(Value::as_u64).unwrap_or(0);
+    let cache_read = n("/cache_read_input_tokens");
+    let prompt_tokens =
+        n("/prompt_tokens") + n("/input_tokens") + cache_read + n("/cache_creation_input_tokens");
+    let completion_tokens = n("/completion_tokens") + n("/output_tokens");
+    Usage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: n("/total_tokens").max(prompt_tokens + completion_tokens),
+        prompt_tokens_details: Some(PromptTokensDetails {
+            cached_tokens: n("/prompt_tokens_details/cached_tokens")
+                + n("/input_tokens_details/cached_tokens")
+                + cache_read,
+        }),
+        completion_tokens_details: Some(CompletionTokensDetails {
+            reasoning_tokens: n("/completion_tokens_details/reasoning_tokens")
+                + n("/output_tokens_details/reasoning_tokens"),
+        }),
+    }
+}
+
+/// 响应体跟踪,跟随响应体一起被丢弃,丢弃时(正常结束或客户端断开)调用回调
+struct UsageTracker {
+    /// 是否解析响应体,不解析时只统计字节数和首字节时间
+    parse: bool,
+    decoder: Option<SseDecoder>,
+    buffer: Vec<u8>,
+    tally: SummaryTally,
+    bytes: u64,
+    first_byte: Option<Instant>,
+    completed: bool,
+    failed: bool,
+    on_done: Option<OnDone>,
+}
+
+impl UsageTracker {
+    fn on_chunk(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.bytes += bytes.len() as u64;
+        self.first_byte.get_or_insert_with(Instant::now);
+        if !self.parse {
+            return;
+        }
+        match &mut self.decoder {
+            Some(decoder) => {
+                for event in decoder.push(bytes) {
+                    if let Ok(value) = serde_json::from_str::<Value>(&event.data) {
+                        self.tally.observe(&value);
+                    }
+                }
+            }
+            None => self.buffer.extend_from_slice(bytes),
+        }
+    }
+}
+
+impl Drop for UsageTracker {
+    fn drop(&mut self) {
+        let value = match &mut self.decoder {
+            _ if !self.parse => None,
+            Some(decoder) => decoder
+                .finish()
+                .and_then(|event| serde_json::from_str::<Value>(&event.data).ok()),
+            None => serde_json::from_slice::<Value>(&self.buffer).ok(),
+        };
+        if let Some(value) = value {
+            self.tally.observe(&value);
+        }
+        if let Some(on_done) = self.on_done.take() {
+            on_done(ResponseSummary {
+                bytes: self.bytes,
+                first_byte: self.first_byte,
+                completed: self.completed,
+                failed: self.failed,
+                ..std::mem::take(&mut self.tally).finish()
+            });
+        }
+    }
+}
+
+/// 记录上游用量的转换器包装
+///
+/// 每处理一个事件就把转换器从上游事件中读取到的用量写入请求上下文,
+/// 因此客户端中途断开时也保留已读取的用量;翻译后的响应只在客户端请求时才带 usage,
+/// 计费以这里记录的用量为准
+pub struct ReportUsage<T: EventTranslator> {
+    inner: T,
+    slot: UsageSlot,
+}
+
+impl<T: EventTranslator> ReportUsage<T> {
+    pub fn new(inner: T, slot: UsageSlot) -> Self {
+        Self { inner, slot }
+    }
+
+    fn report(&self) {
+        if let Some(usage) = self.inner.usage() {
+            *self.slot.lock().unwrap() = Some(usage);
+        }
+    }
+}
+
+impl<T: EventTranslator> EventTranslator for ReportUsage<T> {
+    fn on_event(&mut self, event: SseEvent, out: &mut String) {
+        self.inner.on_event(event, out);
+        self.report();
+    }
+
+    fn on_end(&mut self, out: &mut String) {
+        self.inner.on_end(out);
+        self.report();
+    }
+
+    fn on_error(&mut self, message: &str, out: &mut String) {
+        self.inner.on_error(message, out);
+        self.report();
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        self.inner.usage()
+    }
+}
+
+/// 跟踪响应体,响应体结束或被丢弃后以 [`ResponseSummary`] 调用 `on_done`
+///
+/// `parse` 为 true 时解析用量、结束原因和响应 ID: `text/event-stream` 响应逐个事件解析,
+/// 其余响应缓存完整响应体后解析
+pub fn summarize_response<F>(response: Response, parse: bool, on_done: F) -> Response
+where
+    F: FnOnce(ResponseSummary) + Send + 'static,
+{
+    let sse = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    let tracker = UsageTracker {
+        parse,
+        decoder: sse.then(SseDecoder::new),
+        buffer: Vec::new(),
+        tally: SummaryTally::default(),
+        bytes: 0,
+        first_byte: None,
+        completed: false,
+        failed: false,
+        on_done: Some(Box::new(on_done)),
+    };
+    response.map(|body| {
+        Body::from_stream(futures_util::stream::unfold(
+            (body.into_data_stream(), tracker),
+            |(mut body, mut tracker)| async move {
+                let item = body.next().await;
+                match &item {
+                    Some(Ok(bytes)) => tracker.on_chunk(bytes),
+                    Some(Err(_)) => tracker.failed = true,
+                    None => tracker.completed = true,
+                }
+                item.map(|item| (item, (body, tracker)))
+            },
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translate::gemini::GeminiStreamTranslator;
+    use serde_json::json;
+
+    #[test]
+    fn test_tally_merges_anthropic_stream_events() {
+        let mut tally = UsageTally::default();
+        tally.observe(&json!({"type": "message_start", "message": {"usage": {
+            "input_tokens": 12, "cache_read_input_tokens": 8, "output_tokens": 1
+        }}}));
+        tally.observe(&json!({"type": "message_delta", "usage": {"output_tokens": 30}}));
+
+        let usage = tally.finish().unwrap();
+        assert_eq!(usage.prompt_tokens, 20);
+        assert_eq!(usage.completion_tokens, 30);
+        assert_eq!(usage.total_tokens, 50);
+        assert_eq!(cached_tokens(&usage), 8);
+    }
+
+    #[test]
+    fn test_tally_reads_gemini_and_responses_formats() {
+        let mut tally = UsageTally::default();
+        tally.observe(&json!([{"usageMetadata": {
+            "promptTokenCount": 5, "candidatesTokenCount": 7, "thoughtsTokenCount": 3,
+            "totalTokenCount": 15
+        }}]));
+        let usage = tally.finish().unwrap();
+        assert_eq!(usage.completion_tokens, 10);
+        assert_eq!(reasoning_tokens(&usage), 3);
+
+        let mut tally = UsageTally::default();
+        tally.observe(
+            &json!({"type": "response.completed", "response": {"usage": {
+                "input_tokens": 9, "output_tokens": 4, "total_tokens": 13,
+                "output_tokens_details": {"reasoning_tokens": 2}
+            }}}),
+        );
+        let usage = tally.finish().unwrap();
+        assert_eq!(usage.total_tokens, 13);
+        assert_eq!(reasoning_tokens(&usage), 2);
+        assert!(UsageTally::default().finish().is_none());
+    }
+
+    #[test]
+    fn test_summary_collects_finish_reasons_and_response_id() {
+        let mut tally = SummaryTally::default();
+        tally.observe(&json!({"type": "message_start", "message": {"id": "msg_1", "usage": {}}}));
+        tally.observe(&json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}}));
+        let summary = tally.finish();
+        assert_eq!(summary.response_id.as_deref(), Some("msg_1"));
+        assert_eq!(summary.finish_reasons, vec!["end_turn"]);
+
+        let mut tally = SummaryTally::default();
+        tally.observe(&json!({"id": "chatcmpl-1", "choices": [{"finish_reason": null}]}));
+        tally.observe(&json!({"id": "chatcmpl-1", "choices": [
+            {"finish_reason": "stop"}, {"finish_reason": "length"}, {"finish_reason": "stop"}
+        ]}));
+        tally.observe(&json!([{"responseId": "r", "candidates": [{"finishReason": "STOP"}]}]));
+        let summary = tally.finish();
+        assert_eq!(summary.response_id.as_deref(), Some("chatcmpl-1"));
+        assert_eq!(summary.finish_reasons, vec!["stop", "length", "STOP"]);
+    }
+
+    #[test]
+    fn test_report_usage_without_include_usage() {
+        let slot = UsageSlot::default();
+        let mut translator = ReportUsage::new(
+            GeminiStreamTranslator::new("gemini-2.5-flash", false),
+            slot.clone(),
+        );
+        let data = json!({
+            "candidates": [{"content": {"role": "model", "parts": [{"text": "hi"}]}}],
+            "usageMetadata": {
+                "promptTokenCount": 5, "candidatesTokenCount": 7, "totalTokenCount": 12
+            }
+        });
+        let mut out = String::new();
+        translator.on_event(
+            SseEvent {
+                event: None,
+                data: data.to_string(),
+            },
+            &mut out,
+        );
+        translator.on_end(&mut out);
+
+        // 客户端未请求 usage,响应中不带 usage,但上游用量已写入请求上下文
+        assert!(out.contains("hi"));
+        assert!(!out.contains("\"usage\""));
+        let usage = slot.lock().unwrap().clone().unwrap();
+        assert_eq!(usage.prompt_tokens, 5);
+        assert_eq!(usage.completion_tokens, 7);
+        assert_eq!(usage.total_tokens, 12);
+    }
+
+    #[tokio::test]
+    async fn test_summarize_response_reports_bytes_and_completion() {
+        let body =
+            json!({"id": "chatcmpl-1", "usage": {"prompt_tokens": 3, "completion_tokens": 4}})
+                .to_string();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let response =
+            summarize_response(Response::new(Body::from(body.clone())), true, move |s| {
+                tx.send(s).unwrap();
+            });
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let summary = rx.recv().unwrap();
+        assert_eq!(summary.bytes, body.len() as u64);
+        assert!(summary.completed && !summary.cancelled());
+        assert!(summary.first_byte.is_some());
+        assert_eq!(summary.response_id.as_deref(), Some("chatcmpl-1"));
+        assert_eq!(summary.usage.unwrap().total_tokens, 7);
+
+        // 不解析时只统计字节数;响应体未读完就被丢弃记为取消
+        let (tx, rx) = std::sync::mpsc::channel();
+        let response = summarize_response(Response::new(Body::from(body)), false, move |s| {
+            tx.send(s).unwrap();
+        });
+        drop(response);
+        let summary = rx.recv().unwrap();
+        assert!(summary.usage.is_none() && summary.cancelled());
+    }
+}